@@ -1,5 +1,5 @@
 use std::path::PathBuf;
-use clap_derive::{Parser, Subcommand};
+use clap_derive::{Parser, Subcommand, ValueEnum};
 
 #[derive(Parser)]
 #[command(version)]
@@ -37,7 +37,7 @@ pub enum Commands {
         stdout: bool,
     },
 
-    /// Repack enshrouded files (will backup the origin .kfc to .kfc.bak)
+    /// Repack enshrouded files (will create an `original` snapshot of the unmodified files)
     Repack {
         /// Game directory (should contain enshrouded.kfc and enshrouded._XXX.dat files)
         #[arg(short, long)]
@@ -67,22 +67,282 @@ pub enum Commands {
         file_name: Option<String>,
     },
 
+    /// Generate JSON schemas for the unpacked files of each resource type
+    ExportSchema {
+        /// Game directory (should contain enshrouded.kfc)
+        #[arg(short, long)]
+        game_directory: PathBuf,
+
+        /// File name override (defaults to `enshrouded` and `enshrouded_server`)
+        #[arg(long)]
+        file_name: Option<String>,
+
+        /// Output directory, the schema of each type is written to `<type>.schema.json`
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+
+    /// Generate Rust types for the given resource types and all types they use
+    GenerateRust {
+        /// Game directory (should contain enshrouded.kfc)
+        #[arg(short, long)]
+        game_directory: PathBuf,
+
+        /// File name override (defaults to `enshrouded` and `enshrouded_server`)
+        #[arg(long)]
+        file_name: Option<String>,
+
+        /// Comma separated qualified type names
+        #[arg(short, long, value_delimiter = ',', required = true)]
+        types: Vec<String>,
+
+        /// Output file (defaults to stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
     /// Restore the original enshrouded files
     Restore {
-        /// Game directory (should contain enshrouded.kfc.bak)
+        /// Game directory (should contain the kfc_snapshots directory)
+        #[arg(short, long)]
+        game_directory: PathBuf,
+
+        /// File name override (defaults to `enshrouded` and `enshrouded_server`)
+        #[arg(long)]
+        file_name: Option<String>,
+    },
+
+    /// Verify the integrity of enshrouded files
+    Verify {
+        /// Game directory (should contain enshrouded.kfc and enshrouded._XXX.dat files)
+        #[arg(short, long)]
+        game_directory: PathBuf,
+
+        /// File name override (defaults to `enshrouded` and `enshrouded_server`)
+        #[arg(long)]
+        file_name: Option<String>,
+
+        /// Skip hashing the content of the .dat files
+        #[arg(long)]
+        skip_contents: bool,
+
+        /// Write the report as JSON to stdout
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show how the storage of the enshrouded files is used
+    Analyze {
+        /// Game directory (should contain enshrouded.kfc and enshrouded._XXX.dat files)
+        #[arg(short, long)]
+        game_directory: PathBuf,
+
+        /// File name override (defaults to `enshrouded` and `enshrouded_server`)
+        #[arg(long)]
+        file_name: Option<String>,
+
+        /// How many of the largest resource types to show
+        #[arg(long, default_value_t = 20)]
+        top: usize,
+
+        /// Write the report as JSON to stdout
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Compare the enshrouded files of two game versions
+    Diff {
+        /// Game directory of the old version
+        #[arg(long)]
+        old_directory: PathBuf,
+
+        /// Game directory of the new version (also used to load the type information)
+        #[arg(long)]
+        new_directory: PathBuf,
+
+        /// File name override (defaults to `enshrouded` and `enshrouded_server`)
+        #[arg(long)]
+        file_name: Option<String>,
+
+        /// Compare the data of resources which exist in both versions instead of only their size
+        #[arg(long)]
+        compare_data: bool,
+
+        /// Output file for the JSON report (defaults to stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Compare the type information of two game versions
+    DiffTypes {
+        /// Type information of the old version (types.json or types.bin)
+        #[arg(long)]
+        old: PathBuf,
+
+        /// Type information of the new version (types.json or types.bin)
+        #[arg(long)]
+        new: PathBuf,
+
+        /// Game directory of the new version, used to list the affected resource types
+        #[arg(short, long)]
+        game_directory: Option<PathBuf>,
+
+        /// File name override (defaults to `enshrouded` and `enshrouded_server`)
+        #[arg(long)]
+        file_name: Option<String>,
+
+        /// Output file for the JSON report (defaults to stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Remove unreferenced data from the enshrouded files
+    ///
+    /// The files can only be compacted in place if there are no snapshots,
+    /// since snapshots refer to the data which is moved by compaction.
+    Compact {
+        /// Game directory (should contain enshrouded.kfc and enshrouded._XXX.dat files)
         #[arg(short, long)]
         game_directory: PathBuf,
 
         /// File name override (defaults to `enshrouded` and `enshrouded_server`)
         #[arg(long)]
         file_name: Option<String>,
+
+        /// Write the compacted files into this directory instead of replacing the original files
+        #[arg(short, long)]
+        output_directory: Option<PathBuf>,
+    },
+
+    /// Merge multiple archives into one archive
+    Merge {
+        /// The .kfc files to merge, later archives override earlier ones by default
+        #[arg(required = true, num_args = 2..)]
+        inputs: Vec<PathBuf>,
+
+        /// The .kfc file to write, its directory must not contain any of the inputs
+        #[arg(short, long)]
+        output: PathBuf,
+
+        /// What to do with resources which exist in multiple archives
+        #[arg(long, value_enum, default_value_t = MergeConflict::Last)]
+        conflict: MergeConflict,
+    },
+
+    /// Export the index of the enshrouded files as a JSON manifest
+    ExportIndex {
+        /// Game directory (should contain enshrouded.kfc)
+        #[arg(short, long)]
+        game_directory: PathBuf,
+
+        /// File name override (defaults to `enshrouded` and `enshrouded_server`)
+        #[arg(long)]
+        file_name: Option<String>,
+
+        /// Output file for the manifest
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+
+    /// Rebuild an index from a JSON manifest
+    ImportIndex {
+        /// The manifest created by `export-index`
+        #[arg(short, long)]
+        input: PathBuf,
+
+        /// Output file for the index (e.g. enshrouded.kfc)
+        #[arg(short, long)]
+        output: PathBuf,
     },
 
+    /// Manage snapshots of the enshrouded files
+    #[command(subcommand)]
+    Snapshot(CommandSnapshot),
+
     /// CLI for impact files
     #[command(subcommand)]
     Impact(CommandImpact),
 }
 
+#[derive(Subcommand)]
+pub enum CommandSnapshot {
+    /// Creates a snapshot of the current enshrouded files
+    Create {
+        /// Game directory (should contain enshrouded.kfc and enshrouded._XXX.dat files)
+        #[arg(short, long)]
+        game_directory: PathBuf,
+
+        /// File name override (defaults to `enshrouded` and `enshrouded_server`)
+        #[arg(long)]
+        file_name: Option<String>,
+
+        /// The name of the snapshot
+        #[arg(short, long)]
+        name: String,
+    },
+
+    /// Lists all snapshots of all game versions
+    List {
+        /// Game directory (should contain enshrouded.kfc and enshrouded._XXX.dat files)
+        #[arg(short, long)]
+        game_directory: PathBuf,
+
+        /// File name override (defaults to `enshrouded` and `enshrouded_server`)
+        #[arg(long)]
+        file_name: Option<String>,
+    },
+
+    /// Checks whether a snapshot of the current game version can be restored
+    Verify {
+        /// Game directory (should contain enshrouded.kfc and enshrouded._XXX.dat files)
+        #[arg(short, long)]
+        game_directory: PathBuf,
+
+        /// File name override (defaults to `enshrouded` and `enshrouded_server`)
+        #[arg(long)]
+        file_name: Option<String>,
+
+        /// The name of the snapshot
+        #[arg(short, long, default_value = "original")]
+        name: String,
+    },
+
+    /// Restores a snapshot of the current game version
+    Restore {
+        /// Game directory (should contain enshrouded.kfc and enshrouded._XXX.dat files)
+        #[arg(short, long)]
+        game_directory: PathBuf,
+
+        /// File name override (defaults to `enshrouded` and `enshrouded_server`)
+        #[arg(long)]
+        file_name: Option<String>,
+
+        /// The name of the snapshot
+        #[arg(short, long, default_value = "original")]
+        name: String,
+    },
+
+    /// Removes old snapshots
+    Prune {
+        /// Game directory (should contain enshrouded.kfc and enshrouded._XXX.dat files)
+        #[arg(short, long)]
+        game_directory: PathBuf,
+
+        /// File name override (defaults to `enshrouded` and `enshrouded_server`)
+        #[arg(long)]
+        file_name: Option<String>,
+
+        /// How many of the newest snapshots to keep for each game version,
+        /// the original snapshot of the current game version is always kept
+        #[arg(short, long, default_value_t = 3)]
+        keep: usize,
+
+        /// Remove all snapshots of other game versions
+        #[arg(long)]
+        remove_other_versions: bool,
+    },
+}
+
 #[derive(Subcommand)]
 pub enum CommandImpact {
     /// Creates a descriptor file from a disassembled impact program
@@ -118,3 +378,13 @@ pub enum CommandImpact {
     /// Extracts all nodes from the reflection data
     ExtractNodes,
 }
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum MergeConflict {
+    /// Keep the resource of the first archive
+    First,
+    /// Keep the resource of the last archive
+    Last,
+    /// Abort the merge
+    Error,
+}
@@ -3,10 +3,12 @@
 use clap::Parser;
 use colored::Colorize;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use kfc::container::{KFCFile, KFCReader, KFCWriter};
+use kfc::container::{analyze_file, compact_archive, merge_archives, diff_archives, diff_files, DiskStorage, KFCFile, KFCManifest, KFCMergeConflictPolicy, KFCMergeOptions, KFCReadError, KFCReader, KFCSharedCursor, KFCSnapshot, KFCSnapshotError, KFCSnapshotManager, KFCSnapshotPruneOptions, KFCStream, KFCVerifyOptions, KFCVerifySeverity, KFCWriteOptions, KFCWriter, verify_storage, DEFAULT_CHUNK_CACHE_BUDGET, ORIGINAL_SNAPSHOT_NAME};
+use kfc::resource::codegen::generate_rust;
+use kfc::resource::schema::generate_resource_schema;
 use kfc::resource::value::Value;
 use kfc::guid::ResourceId;
-use kfc::reflection::{LookupKey, TypeRegistry};
+use kfc::reflection::{diff_type_registries, LookupKey, TypeCacheError, TypeRegistry};
 use kfc::content::impact::bytecode::{ImpactAssembler, ImpactProgramData};
 use kfc::content::impact::{ImpactProgram, TypeRegistryImpactExt};
 use thiserror::Error;
@@ -20,7 +22,7 @@ use std::sync::atomic::{AtomicBool, AtomicU32};
 use std::sync::Mutex;
 use walkdir::WalkDir;
 
-use crate::cli::{Cli, CommandImpact, Commands};
+use crate::cli::{Cli, CommandImpact, CommandSnapshot, Commands, MergeConflict};
 use crate::logging::*;
 
 mod cli;
@@ -85,6 +87,22 @@ fn main() {
         } => {
             extract_types(&game_directory, file_name.as_deref())
         }
+        Commands::ExportSchema {
+            game_directory,
+            file_name,
+            output,
+        } => {
+            export_schema(&game_directory, file_name.as_deref(), &output)
+        }
+        Commands::GenerateRust {
+            game_directory,
+            file_name,
+            types,
+            output,
+        } => {
+            set_logging(output.is_some());
+            generate_rust_types(&game_directory, file_name.as_deref(), &types, output.as_deref())
+        }
         Commands::Restore {
             game_directory,
             file_name,
@@ -95,6 +113,137 @@ fn main() {
                 false
             )
         }
+        Commands::Verify {
+            game_directory,
+            file_name,
+            skip_contents,
+            json,
+        } => {
+            set_logging(!json);
+            verify(
+                &game_directory,
+                file_name.as_deref(),
+                skip_contents,
+                json
+            )
+        }
+        Commands::Analyze {
+            game_directory,
+            file_name,
+            top,
+            json,
+        } => {
+            set_logging(!json);
+            analyze(
+                &game_directory,
+                file_name.as_deref(),
+                top,
+                json
+            )
+        }
+        Commands::Diff {
+            old_directory,
+            new_directory,
+            file_name,
+            compare_data,
+            output,
+        } => {
+            set_logging(output.is_some());
+            diff(
+                &old_directory,
+                &new_directory,
+                file_name.as_deref(),
+                compare_data,
+                output.as_deref()
+            )
+        }
+        Commands::DiffTypes {
+            old,
+            new,
+            game_directory,
+            file_name,
+            output,
+        } => {
+            set_logging(output.is_some());
+            diff_types(
+                &old,
+                &new,
+                game_directory.as_deref(),
+                file_name.as_deref(),
+                output.as_deref()
+            )
+        }
+        Commands::Compact {
+            game_directory,
+            file_name,
+            output_directory,
+        } => {
+            set_logging(true);
+            compact(
+                &game_directory,
+                file_name.as_deref(),
+                output_directory.as_deref(),
+                thread_count
+            )
+        }
+        Commands::Merge {
+            inputs,
+            output,
+            conflict,
+        } => {
+            set_logging(true);
+            merge(&inputs, &output, conflict, thread_count)
+        }
+        Commands::ExportIndex {
+            game_directory,
+            file_name,
+            output,
+        } => {
+            export_index(&game_directory, file_name.as_deref(), &output)
+        }
+        Commands::ImportIndex {
+            input,
+            output,
+        } => {
+            import_index(&input, &output)
+        }
+        Commands::Snapshot(snapshot) => match snapshot {
+            CommandSnapshot::Create {
+                game_directory,
+                file_name,
+                name,
+            } => {
+                create_snapshot(&game_directory, file_name.as_deref(), &name)
+            }
+            CommandSnapshot::List {
+                game_directory,
+                file_name,
+            } => {
+                list_snapshots(&game_directory, file_name.as_deref())
+            }
+            CommandSnapshot::Verify {
+                game_directory,
+                file_name,
+                name,
+            } => {
+                verify_snapshot(&game_directory, file_name.as_deref(), &name)
+            }
+            CommandSnapshot::Restore {
+                game_directory,
+                file_name,
+                name,
+            } => {
+                restore_snapshot(&game_directory, file_name.as_deref(), &name)
+            }
+            CommandSnapshot::Prune {
+                game_directory,
+                file_name,
+                keep,
+                remove_other_versions,
+            } => {
+                prune_snapshots(&game_directory, file_name.as_deref(), keep, remove_other_versions)
+            }
+        }
         Commands::Impact(impact) => match impact {
             CommandImpact::Assemble {
                 input,
@@ -215,6 +364,11 @@ fn unpack(
         Err(e) => fatal!("Failed to open {}: {}", file_path.display(), e)
     };
 
+    let kfc_reader = match kfc_reader.into_shared_cursor(DEFAULT_CHUNK_CACHE_BUDGET) {
+        Ok(reader) => reader,
+        Err(e) => fatal!("Failed to open {}: {}", file_path.display(), e)
+    };
+
     if let Some(output_dir) = output_dir {
         info!("Unpacking {} to {}", file_path.display(), output_dir.display());
 
@@ -237,8 +391,11 @@ fn unpack(
     }
 }
 
+/// How many neighbouring resources are read at once while unpacking.
+const UNPACK_BATCH_SIZE: usize = 256;
+
 fn unpack_files(
-    kfc_reader: &KFCReader,
+    reader: &KFCSharedCursor<KFCReader>,
     type_registry: &TypeRegistry,
     output_dir: &Path,
     guids: HashSet<&ResourceId>,
@@ -251,7 +408,7 @@ fn unpack_files(
         .progress_chars("##-"));
 
     let total = guids.len() as u32;
-    let pending_guids = Mutex::new(guids.into_iter().collect::<Vec<_>>());
+    let pending_batches = Mutex::new(crate::util::resource_batches(reader.file(), guids, UNPACK_BATCH_SIZE));
     let failed_unpacks = AtomicU32::new(0);
     let start = std::time::Instant::now();
     let names = Mutex::new(HashSet::new());
@@ -259,53 +416,35 @@ fn unpack_files(
     std::thread::scope(|s| {
         let mut handles = Vec::new();
 
-        for i in 0..thread_count {
+        for _ in 0..thread_count {
             let failed_unpacks = &failed_unpacks;
-            let pending_guids = &pending_guids;
+            let pending_batches = &pending_batches;
             let output_dir = &output_dir;
             let pb = &pb;
             let names = &names;
 
-            let handle = s.spawn(move || {
-                let mut buf = Vec::with_capacity(1024);
-                let mut reader = match kfc_reader.new_cursor() {
-                    Ok(file) => file,
-                    Err(e) => {
-                        pb.suspend(|| {
-                            error!("Failed to open {}: {}", kfc_reader.kfc_path().display(), e);
-                            error!("Worker #{} has been suspended", i);
-                        });
-                        return;
+            let handle = s.spawn(move || loop {
+                let batch = {
+                    let mut lock = pending_batches.lock().unwrap();
+
+                    if let Some(entry) = lock.pop() {
+                        entry
+                    } else {
+                        break;
                     }
                 };
 
-                loop {
-                    let guid = {
-                        let mut lock = pending_guids.lock().unwrap();
+                let mut processed = HashSet::with_capacity(batch.len());
 
-                        if let Some(entry) = lock.pop() {
-                            entry
-                        } else {
-                            break;
-                        }
-                    };
+                let result = reader.read_resources(batch.iter().copied(), |guid, data| {
+                    processed.insert(*guid);
 
                     let result: anyhow::Result<()> = (|| {
-                        buf.clear();
-                        let descriptor = match crate::util::read_descriptor_into(
-                            &mut reader,
+                        let descriptor = crate::util::deserialize_descriptor(
                             type_registry,
                             guid,
-                            &mut buf
-                        )? {
-                            Some(d) => d,
-                            None => {
-                                pb.suspend(|| {
-                                    warn!("Skipping descriptor (not found): {}", guid.to_qualified_string());
-                                });
-                                return Ok(());
-                            }
-                        };
+                            data
+                        )?;
 
                         let r#type = type_registry.get_by_hash(LookupKey::Qualified(guid.type_hash()))
                             .ok_or_else(|| anyhow::anyhow!("Type not found: {:0>8x}", guid.type_hash()))?;
@@ -358,6 +497,27 @@ fn unpack_files(
                     }
 
                     pb.inc(1);
+
+                    Ok::<_, std::io::Error>(())
+                });
+
+                // resources which are not covered by the resource chunks are skipped by the reader
+                let remaining = batch.iter()
+                    .filter(|guid| !processed.contains(**guid))
+                    .collect::<Vec<_>>();
+
+                failed_unpacks.fetch_add(remaining.len() as u32, std::sync::atomic::Ordering::Relaxed);
+                pb.inc(remaining.len() as u64);
+
+                match result {
+                    Ok(()) => pb.suspend(|| {
+                        for guid in &remaining {
+                            warn!("Skipping descriptor (not found): {}", guid.to_qualified_string());
+                        }
+                    }),
+                    Err(e) => pb.suspend(|| {
+                        error!("Error occurred while reading {} descriptors: {}", remaining.len(), e);
+                    }),
                 }
             });
 
@@ -380,12 +540,12 @@ fn unpack_files(
 }
 
 fn unpack_stdout(
-    kfc_reader: &KFCReader,
+    reader: &KFCSharedCursor<KFCReader>,
     type_registry: &TypeRegistry,
     guids: HashSet<&ResourceId>,
     thread_count: u8
 ) -> Result<(), Error> {
-    let pending_guids = Mutex::new(guids.into_iter().collect::<Vec<_>>());
+    let pending_batches = Mutex::new(crate::util::resource_batches(reader.file(), guids, UNPACK_BATCH_SIZE));
     let (tx, rx) = std::sync::mpsc::sync_channel(1024);
 
     std::thread::scope(|s| {
@@ -393,43 +553,30 @@ fn unpack_stdout(
 
         for _ in 0..thread_count {
             let tx = tx.clone();
-            let pending_guids = &pending_guids;
+            let pending_batches = &pending_batches;
 
-            let handle = s.spawn(move || {
-                let mut buf = Vec::with_capacity(1024);
-                let mut reader = match kfc_reader.new_cursor() {
-                    Ok(file) => file,
-                    Err(e) => {
-                        let error = serde_json::json!({
-                            "$error": "KFCReaderError",
-                            "$message": format!("Failed to open {}: {}", kfc_reader.kfc_path().display(), e),
-                        }).to_string();
-
-                        tx.send(error).unwrap();
+            let handle = s.spawn(move || loop {
+                let batch = {
+                    let mut lock = pending_batches.lock().unwrap();
 
-                        return;
+                    if let Some(entry) = lock.pop() {
+                        entry
+                    } else {
+                        break;
                     }
                 };
 
-                loop {
-                    let guid = {
-                        let mut lock = pending_guids.lock().unwrap();
+                let mut processed = HashSet::with_capacity(batch.len());
 
-                        if let Some(entry) = lock.pop() {
-                            entry
-                        } else {
-                            break;
-                        }
-                    };
+                let result = reader.read_resources(batch.iter().copied(), |guid, data| {
+                    processed.insert(*guid);
 
-                    buf.clear();
-                    let descriptor = match crate::util::read_descriptor_into(
-                        &mut reader,
+                    let descriptor = match crate::util::deserialize_descriptor(
                         type_registry,
                         guid,
-                        &mut buf
+                        data
                     ) {
-                        Ok(Some(d)) => match serde_json::to_string(&d) {
+                        Ok(d) => match serde_json::to_string(&d) {
                             Ok(data) => data,
                             Err(e) => {
                                 serde_json::json!({
@@ -439,19 +586,35 @@ fn unpack_stdout(
                                 }).to_string()
                             }
                         },
-                        Ok(None) => serde_json::json!({
+                        Err(e) => serde_json::json!({
+                            "$guid": guid.to_qualified_string(),
+                            "$error": "ReadError",
+                            "$message": e.to_string(),
+                        }).to_string()
+                    };
+
+                    tx.send(descriptor).unwrap();
+
+                    Ok::<_, std::io::Error>(())
+                });
+
+                // resources which are not covered by the resource chunks are skipped by the reader
+                let remaining = batch.iter().filter(|guid| !processed.contains(**guid));
+
+                for guid in remaining {
+                    let descriptor = match &result {
+                        Ok(()) => serde_json::json!({
                             "$guid": guid.to_qualified_string(),
                             "$error": "NotFound",
-                        }).to_string(),
+                        }),
                         Err(e) => serde_json::json!({
                             "$guid": guid.to_qualified_string(),
                             "$error": "ReadError",
                             "$message": e.to_string(),
-                        }).to_string()
+                        }),
                     };
-                    let result = descriptor.to_string();
 
-                    tx.send(result).unwrap();
+                    tx.send(descriptor.to_string()).unwrap();
                 }
             });
 
@@ -491,23 +654,6 @@ fn unpack_stdout(
     })
 }
 
-fn validate_backup(
-    kfc_path: &Path,
-    kfc_path_bak: &Path
-) -> Result<bool, Error> {
-    let version_bak = match KFCFile::get_version_tag(kfc_path_bak) {
-        Ok(file) => file,
-        Err(_) => return Ok(false),
-    };
-
-    let version = match KFCFile::get_version_tag(kfc_path) {
-        Ok(file) => file,
-        Err(e) => fatal!("Failed to read {}: {}", kfc_path.display(), e)
-    };
-
-    Ok(version == version_bak)
-}
-
 fn repack(
     game_dir: &Path,
     file_name: Option<&str>,
@@ -524,34 +670,15 @@ fn repack(
     }
 
     let kfc_path = get_file(game_dir, file_name, "kfc")?;
-    let kfc_path_bak = get_file_opt(game_dir, file_name, "kfc.bak")?;
-
-    if kfc_path_bak.exists() && !validate_backup(&kfc_path, &kfc_path_bak)? {
-        warn!("Backup file is not valid, deleting it...");
-
-        if !kfc_path_bak.is_file() {
-            fatal!("Backup path is not a file, please remove it manually: {}", kfc_path_bak.display())
-        }
-
-        if let Err(e) = std::fs::remove_file(&kfc_path_bak) {
-            fatal!("Failed to delete backup file: {}", e);
-        }
-    }
-
-    if !kfc_path_bak.exists() {
-        info!("Creating backup of {}...", kfc_path.display());
-
-        match std::fs::copy(&kfc_path, &kfc_path_bak) {
-            Ok(_) => {},
-            Err(e) => fatal!("Failed to create backup: {}", e)
-        }
-    }
+    let snapshots = KFCSnapshotManager::for_game_dir(game_dir, get_file_name(game_dir, file_name)?);
+    let snapshot = ensure_original_snapshot(&snapshots)?;
 
     let type_registry = load_type_registry(Some(game_dir), file_name, true)?;
 
-    let mut ref_kfc_file = match KFCFile::from_path(&kfc_path_bak, false) {
+    let ref_kfc_path = snapshots.storage(&snapshot).stream_path(KFCStream::Index);
+    let mut ref_kfc_file = match KFCFile::from_path(&ref_kfc_path, false) {
         Ok(file) => file,
-        Err(e) => fatal!("Failed to read {}: {}", kfc_path_bak.display(), e)
+        Err(e) => fatal!("Failed to read {}: {}", ref_kfc_path.display(), e)
     };
 
     if let Some(input_dir) = input_dir {
@@ -619,11 +746,15 @@ fn repack_files(
     let file_name = get_file_name(game_dir, file_name)?;
     let file_path = get_file(game_dir, Some(&file_name), "kfc")?;
 
-    let mut writer = match KFCWriter::new_incremental(
+    let mut writer = match KFCWriter::new_incremental_with_options(
         game_dir,
         &file_name,
         ref_kfc_file,
-        type_registry
+        type_registry,
+        KFCWriteOptions {
+            compression_threads: thread_count as usize,
+            ..Default::default()
+        }
     ) {
         Ok(writer) => writer,
         Err(e) => fatal!("Failed to open {}: {}", file_path.display(), e)
@@ -780,11 +911,15 @@ fn repack_stdin(
     let file_name = get_file_name(game_dir, file_name)?;
     let file_path = get_file(game_dir, Some(&file_name), "kfc")?;
 
-    let mut writer = match KFCWriter::new_incremental(
+    let mut writer = match KFCWriter::new_incremental_with_options(
         game_dir,
         &file_name,
         ref_kfc_file,
-        type_registry
+        type_registry,
+        KFCWriteOptions {
+            compression_threads: thread_count as usize,
+            ..Default::default()
+        }
     ) {
         Ok(writer) => writer,
         Err(e) => fatal!("Failed to open {}: {}", file_path.display(), e)
@@ -919,34 +1054,468 @@ fn repack_stdin(
     Ok(())
 }
 
+fn export_index(
+    game_dir: &Path,
+    file_name: Option<&str>,
+    output: &Path,
+) -> Result<(), Error> {
+    let kfc_path = get_file(game_dir, file_name, "kfc")?;
+
+    let manifest = match File::open(&kfc_path)
+        .map_err(KFCReadError::from)
+        .and_then(|file| KFCManifest::read(&mut BufReader::new(file)))
+    {
+        Ok(manifest) => manifest,
+        Err(e) => fatal!("Failed to read {}: {}", kfc_path.display(), e)
+    };
+
+    let writer = match File::create(output) {
+        Ok(file) => BufWriter::new(file),
+        Err(e) => fatal!("Failed to create {}: {}", output.display(), e)
+    };
+
+    if let Err(e) = serde_json::to_writer_pretty(writer, &manifest) {
+        fatal!("Failed to write {}: {}", output.display(), e);
+    }
+
+    info!("Exported index of {} to {}", kfc_path.display(), output.display());
+
+    Ok(())
+}
+
+fn import_index(
+    input: &Path,
+    output: &Path,
+) -> Result<(), Error> {
+    let manifest = match File::open(input) {
+        Ok(file) => match serde_json::from_reader::<_, KFCManifest>(BufReader::new(file)) {
+            Ok(manifest) => manifest,
+            Err(e) => fatal!("Failed to parse {}: {}", input.display(), e)
+        },
+        Err(e) => fatal!("Failed to open {}: {}", input.display(), e)
+    };
+
+    let mut writer = match File::create(output) {
+        Ok(file) => BufWriter::new(file),
+        Err(e) => fatal!("Failed to create {}: {}", output.display(), e)
+    };
+
+    if let Err(e) = manifest.write_index(&mut writer) {
+        fatal!("Failed to write {}: {}", output.display(), e);
+    }
+
+    if let Err(e) = writer.flush() {
+        fatal!("Failed to write {}: {}", output.display(), e);
+    }
+
+    info!("Rebuilt index {} from {}", output.display(), input.display());
+
+    Ok(())
+}
+
+fn ensure_original_snapshot(
+    snapshots: &KFCSnapshotManager<DiskStorage>,
+) -> Result<KFCSnapshot, Error> {
+    match snapshots.ensure_original() {
+        Ok(snapshot) => Ok(snapshot),
+        Err(KFCSnapshotError::OriginalMismatch { issues, .. }) => {
+            for issue in &issues {
+                error!("{}", issue);
+            }
+
+            fatal!(
+                "The original snapshot does not match the current files, found {} issues. \
+                Please verify the game files, e.g. through Steam, to restore them.",
+                issues.len(),
+            );
+        }
+        Err(e) => fatal!("Failed to create snapshot: {}", e)
+    }
+}
+
 fn revert_repack(
     game_dir: &Path,
     file_name: Option<&str>,
     because_of_error: bool,
 ) -> Result<(), Error> {
-    let kfc_file = get_file(game_dir, file_name, "kfc")?;
-    let kfc_file_bak = get_file(game_dir, file_name, "kfc.bak")?;
-
     if because_of_error {
         error!("An error occurred during repack, reverting changes...");
     } else {
         info!("Reverting repack...");
     }
 
-    match std::fs::copy(&kfc_file_bak, &kfc_file) {
-        Ok(_) => {},
-        Err(e) => {
-            error!("Failed to revert repack: {}", e);
+    restore_snapshot(game_dir, file_name, ORIGINAL_SNAPSHOT_NAME)?;
 
-            if kfc_file_bak.exists() {
-                fatal!("Please manually restore from backup file: {}", kfc_file_bak.display());
-            } else {
-                fatal!("Backup file is missing, please verify integrity of the game files");
-            }
+    info!("Reverted repack successfully");
+
+    Ok(())
+}
+
+fn find_snapshot(
+    snapshots: &KFCSnapshotManager<DiskStorage>,
+    name: &str,
+) -> Result<KFCSnapshot, Error> {
+    let game_version = match snapshots.game_version() {
+        Ok(version) => version,
+        Err(e) => fatal!("Failed to read the game version: {}", e)
+    };
+
+    match snapshots.get(&game_version, name) {
+        Ok(Some(snapshot)) => Ok(snapshot),
+        Ok(None) => fatal!("Snapshot `{}` does not exist for game version {}", name, game_version),
+        Err(e) => fatal!("Failed to read snapshot `{}`: {}", name, e)
+    }
+}
+
+fn create_snapshot(
+    game_dir: &Path,
+    file_name: Option<&str>,
+    name: &str,
+) -> Result<(), Error> {
+    let snapshots = KFCSnapshotManager::for_game_dir(game_dir, get_file_name(game_dir, file_name)?);
+
+    info!("Creating snapshot `{}`...", name);
+
+    match snapshots.create(name) {
+        Ok(snapshot) => info!("Created snapshot `{}` for game version {}", snapshot.name, snapshot.game_version),
+        Err(e) => fatal!("Failed to create snapshot: {}", e)
+    }
+
+    Ok(())
+}
+
+fn list_snapshots(
+    game_dir: &Path,
+    file_name: Option<&str>,
+) -> Result<(), Error> {
+    let snapshots = KFCSnapshotManager::for_game_dir(game_dir, get_file_name(game_dir, file_name)?);
+
+    let snapshots = match snapshots.snapshots() {
+        Ok(snapshots) => snapshots,
+        Err(e) => fatal!("Failed to list snapshots: {}", e)
+    };
+
+    for snapshot in snapshots {
+        println!(
+            "{} {} (created at {}, {} containers)",
+            snapshot.game_version,
+            snapshot.name.bold(),
+            snapshot.created_at,
+            snapshot.containers.len()
+        );
+    }
+
+    Ok(())
+}
+
+fn verify_snapshot(
+    game_dir: &Path,
+    file_name: Option<&str>,
+    name: &str,
+) -> Result<(), Error> {
+    let snapshots = KFCSnapshotManager::for_game_dir(game_dir, get_file_name(game_dir, file_name)?);
+    let snapshot = find_snapshot(&snapshots, name)?;
+
+    let issues = match snapshots.verify(&snapshot) {
+        Ok(issues) => issues,
+        Err(e) => fatal!("Failed to verify snapshot: {}", e)
+    };
+
+    if issues.is_empty() {
+        info!("Snapshot `{}` can be restored", name);
+
+        return Ok(());
+    }
+
+    for issue in &issues {
+        error!("{}", issue);
+    }
+
+    fatal!("Snapshot `{}` can not be restored, found {} issues", name, issues.len())
+}
+
+fn restore_snapshot(
+    game_dir: &Path,
+    file_name: Option<&str>,
+    name: &str,
+) -> Result<(), Error> {
+    let snapshots = KFCSnapshotManager::for_game_dir(game_dir, get_file_name(game_dir, file_name)?);
+    let snapshot = find_snapshot(&snapshots, name)?;
+
+    if let Err(e) = snapshots.restore(&snapshot) {
+        fatal!("Failed to restore snapshot `{}`: {}", name, e);
+    }
+
+    info!("Restored snapshot `{}`", name);
+
+    Ok(())
+}
+
+fn prune_snapshots(
+    game_dir: &Path,
+    file_name: Option<&str>,
+    keep: usize,
+    remove_other_versions: bool,
+) -> Result<(), Error> {
+    let snapshots = KFCSnapshotManager::for_game_dir(game_dir, get_file_name(game_dir, file_name)?);
+
+    let removed = match snapshots.prune(&KFCSnapshotPruneOptions {
+        keep_per_version: keep,
+        keep_other_versions: !remove_other_versions,
+    }) {
+        Ok(removed) => removed,
+        Err(e) => fatal!("Failed to prune snapshots: {}", e)
+    };
+
+    for snapshot in &removed {
+        info!("Removed snapshot `{}` of game version {}", snapshot.name, snapshot.game_version);
+    }
+
+    info!("Removed {} snapshots", removed.len());
+
+    Ok(())
+}
+
+fn verify(
+    game_dir: &Path,
+    file_name: Option<&str>,
+    skip_contents: bool,
+    json: bool,
+) -> Result<(), Error> {
+    let file_name = get_file_name(game_dir, file_name)?;
+    let file_path = get_file(game_dir, Some(&file_name), "kfc")?;
+
+    // the storage is verified directly, so damaged indices are reported instead of failing to open
+    let storage = DiskStorage::new(game_dir, &file_name);
+
+    info!("Verifying {}...", file_path.display());
+
+    let start = std::time::Instant::now();
+    let report = match verify_storage(&storage, &KFCVerifyOptions {
+        verify_content_hashes: !skip_contents,
+        ..Default::default()
+    }) {
+        Ok(report) => report,
+        Err(e) => fatal!("Failed to verify {}: {}", file_path.display(), e)
+    };
+    let end = std::time::Instant::now();
+
+    if json {
+        let stdout = std::io::stdout().lock();
+
+        if let Err(e) = serde_json::to_writer_pretty(stdout, &report) {
+            fatal!("Failed to write report: {}", e);
         }
     }
 
-    info!("Reverted repack successfully");
+    for issue in &report.issues {
+        match issue.severity() {
+            KFCVerifySeverity::Warning => warn!("{}", issue),
+            KFCVerifySeverity::Error => error!("{}", issue),
+        }
+    }
+
+    info!(
+        "Checked {} resources in {} chunks and {} contents in {} containers in {:?}",
+        report.resources,
+        report.resource_chunks,
+        report.contents,
+        report.containers,
+        end - start
+    );
+
+    let error_count = report.errors().count();
+
+    if error_count > 0 {
+        fatal!("Found {} errors and {} warnings", error_count, report.warnings().count());
+    }
+
+    info!("No errors found ({} warnings)", report.warnings().count());
+
+    Ok(())
+}
+
+fn analyze(
+    game_dir: &Path,
+    file_name: Option<&str>,
+    top: usize,
+    json: bool,
+) -> Result<(), Error> {
+    let file_name = get_file_name(game_dir, file_name)?;
+    let file_path = get_file(game_dir, Some(&file_name), "kfc")?;
+    let type_registry = load_type_registry(Some(game_dir), Some(&file_name), true)?;
+
+    let kfc_file = match KFCFile::from_path(&file_path, false) {
+        Ok(file) => file,
+        Err(e) => fatal!("Failed to read {}: {}", file_path.display(), e)
+    };
+
+    let analysis = analyze_file(&kfc_file, &type_registry);
+
+    if json {
+        let stdout = std::io::stdout().lock();
+
+        if let Err(e) = serde_json::to_writer_pretty(stdout, &analysis) {
+            fatal!("Failed to write report: {}", e);
+        }
+    }
+
+    let mut types = analysis.types.iter().collect::<Vec<_>>();
+    types.sort_by_key(|(_, t)| std::cmp::Reverse(t.compressed_size));
+
+    info!("Largest resource types:");
+
+    for (name, t) in types.iter().take(top) {
+        info!(
+            "  {}: {} resources, {} bytes ({} bytes compressed)",
+            name,
+            t.count,
+            t.size,
+            t.compressed_size
+        );
+    }
+
+    let resources = &analysis.resources;
+
+    info!(
+        "{} resources with {} bytes in {} chunks ({} bytes compressed, ratio {:.2})",
+        resources.count,
+        resources.size,
+        analysis.chunks.len(),
+        resources.compressed_size,
+        resources.uncompressed_size as f64 / resources.compressed_size.max(1) as f64
+    );
+    info!(
+        "{} contents with {} bytes in {} containers ({} duplicates)",
+        analysis.containers.iter().map(|c| c.count).sum::<usize>(),
+        analysis.containers.iter().map(|c| c.content_size).sum::<u64>(),
+        analysis.containers.len(),
+        analysis.duplicate_contents.len()
+    );
+    info!(
+        "Total size {} bytes, {} bytes padding, {} bytes unreferenced",
+        analysis.total_size(),
+        analysis.padding(),
+        analysis.unreferenced_size()
+    );
+
+    Ok(())
+}
+
+fn diff(
+    old_dir: &Path,
+    new_dir: &Path,
+    file_name: Option<&str>,
+    compare_data: bool,
+    output: Option<&Path>,
+) -> Result<(), Error> {
+    let old_file_name = get_file_name(old_dir, file_name)?;
+    let new_file_name = get_file_name(new_dir, file_name)?;
+    let type_registry = load_type_registry(Some(new_dir), Some(&new_file_name), true)?;
+
+    let old_reader = match KFCReader::new(old_dir, &old_file_name) {
+        Ok(reader) => reader,
+        Err(e) => fatal!("Failed to open {}: {}", old_dir.display(), e)
+    };
+
+    let new_reader = match KFCReader::new(new_dir, &new_file_name) {
+        Ok(reader) => reader,
+        Err(e) => fatal!("Failed to open {}: {}", new_dir.display(), e)
+    };
+
+    let diff = if compare_data {
+        match diff_archives(&old_reader, &new_reader, &type_registry) {
+            Ok(diff) => diff,
+            Err(e) => fatal!("Failed to compare resources: {}", e)
+        }
+    } else {
+        diff_files(old_reader.file(), new_reader.file(), &type_registry)
+    };
+
+    let result = match output {
+        Some(path) => match File::create(path) {
+            Ok(file) => serde_json::to_writer_pretty(BufWriter::new(file), &diff),
+            Err(e) => fatal!("Failed to create {}: {}", path.display(), e)
+        },
+        None => serde_json::to_writer_pretty(std::io::stdout().lock(), &diff),
+    };
+
+    if let Err(e) = result {
+        fatal!("Failed to write diff: {}", e);
+    }
+
+    info!(
+        "Found {} added, {} removed and {} changed resources in {} types",
+        diff.added_resources().count(),
+        diff.removed_resources().count(),
+        diff.changed_resources().count(),
+        diff.types.len()
+    );
+    info!(
+        "Found {} added and {} removed contents",
+        diff.added_contents.len(),
+        diff.removed_contents.len()
+    );
+
+    Ok(())
+}
+
+fn diff_types(
+    old_path: &Path,
+    new_path: &Path,
+    game_dir: Option<&Path>,
+    file_name: Option<&str>,
+    output: Option<&Path>,
+) -> Result<(), Error> {
+    let old_registry = match load_types_from_path(old_path) {
+        Ok(registry) => registry,
+        Err(e) => fatal!("Failed to load {}: {}", old_path.display(), e)
+    };
+
+    let new_registry = match load_types_from_path(new_path) {
+        Ok(registry) => registry,
+        Err(e) => fatal!("Failed to load {}: {}", new_path.display(), e)
+    };
+
+    let diff = diff_type_registries(&old_registry, &new_registry);
+
+    let result = match output {
+        Some(path) => match File::create(path) {
+            Ok(file) => serde_json::to_writer_pretty(BufWriter::new(file), &diff),
+            Err(e) => fatal!("Failed to create {}: {}", path.display(), e)
+        },
+        None => serde_json::to_writer_pretty(std::io::stdout().lock(), &diff),
+    };
+
+    if let Err(e) = result {
+        fatal!("Failed to write diff: {}", e);
+    }
+
+    info!(
+        "Found {} added, {} removed and {} changed types ({} affected)",
+        diff.added_types.len(),
+        diff.removed_types.len(),
+        diff.changed_types.len(),
+        diff.affected_types.len()
+    );
+
+    if let Some(game_dir) = game_dir {
+        let file_name = get_file_name(game_dir, file_name)?;
+        let file_path = get_file(game_dir, Some(&file_name), "kfc")?;
+
+        let kfc_file = match KFCFile::from_path(&file_path, false) {
+            Ok(file) => file,
+            Err(e) => fatal!("Failed to read {}: {}", file_path.display(), e)
+        };
+
+        let resource_types = diff.affected_resource_types(&kfc_file, &new_registry);
+
+        info!("{} resource types are affected:", resource_types.len());
+
+        for name in resource_types {
+            info!("  {}", name);
+        }
+    }
 
     Ok(())
 }
@@ -989,6 +1558,259 @@ fn extract_types(
     Ok(())
 }
 
+fn export_schema(
+    game_dir: &Path,
+    file_name: Option<&str>,
+    output_dir: &Path,
+) -> Result<(), Error> {
+    let file_name = get_file_name(game_dir, file_name)?;
+    let file_path = get_file(game_dir, Some(&file_name), "kfc")?;
+    let type_registry = load_type_registry(Some(game_dir), Some(&file_name), true)?;
+
+    let kfc_file = match KFCFile::from_path(&file_path, false) {
+        Ok(file) => file,
+        Err(e) => fatal!("Failed to read {}: {}", file_path.display(), e)
+    };
+
+    if let Err(e) = std::fs::create_dir_all(output_dir) {
+        fatal!("Failed to create {}: {}", output_dir.display(), e);
+    }
+
+    let mut count = 0;
+
+    for type_hash in kfc_file.resource_types() {
+        let Some(r#type) = type_registry.get_by_hash(LookupKey::Qualified(type_hash)) else {
+            warn!("Type not found: {:0>8x}", type_hash);
+            continue;
+        };
+
+        // named like the directories written by `unpack`
+        let path = output_dir.join(format!("{}.schema.json", r#type.name));
+        let schema = generate_resource_schema(&type_registry, r#type);
+
+        let result = match File::create(&path) {
+            Ok(file) => serde_json::to_writer_pretty(BufWriter::new(file), &schema),
+            Err(e) => fatal!("Failed to create {}: {}", path.display(), e)
+        };
+
+        if let Err(e) = result {
+            fatal!("Failed to write {}: {}", path.display(), e);
+        }
+
+        count += 1;
+    }
+
+    info!("Generated {} schemas in {}", count, output_dir.display());
+
+    Ok(())
+}
+
+fn generate_rust_types(
+    game_dir: &Path,
+    file_name: Option<&str>,
+    type_names: &[String],
+    output: Option<&Path>,
+) -> Result<(), Error> {
+    let file_name = get_file_name(game_dir, file_name)?;
+    let type_registry = load_type_registry(Some(game_dir), Some(&file_name), true)?;
+
+    let mut types = Vec::with_capacity(type_names.len());
+
+    for name in type_names {
+        match type_registry.get_by_name(LookupKey::Qualified(name)) {
+            Some(r#type) => types.push(r#type),
+            None => fatal!("Type not found: {}", name)
+        }
+    }
+
+    let code = generate_rust(&type_registry, &types);
+
+    match output {
+        Some(path) => {
+            if let Err(e) = std::fs::write(path, code) {
+                fatal!("Failed to write {}: {}", path.display(), e);
+            }
+
+            info!("Rust types have been written to {}", path.display());
+        }
+        None => print!("{}", code),
+    }
+
+    Ok(())
+}
+
+fn compact(
+    game_dir: &Path,
+    file_name: Option<&str>,
+    output_dir: Option<&Path>,
+    thread_count: u8,
+) -> Result<(), Error> {
+    let file_name = get_file_name(game_dir, file_name)?;
+    let file_path = get_file(game_dir, Some(&file_name), "kfc")?;
+    let type_registry = load_type_registry(Some(game_dir), Some(&file_name), true)?;
+
+    let kfc_reader = match KFCReader::new(game_dir, &file_name) {
+        Ok(reader) => reader,
+        Err(e) => fatal!("Failed to open {}: {}", file_path.display(), e)
+    };
+
+    // snapshots refer to the data of the archive, which is moved around by compaction
+    if output_dir.is_none() {
+        let manager = KFCSnapshotManager::for_game_dir(game_dir, &file_name);
+
+        match manager.snapshots() {
+            Ok(snapshots) if !snapshots.is_empty() => fatal!(
+                "Refusing to compact {} in place, since it has {} snapshot(s) in {}. \
+                Restore the original snapshot and remove all snapshots first, or use --output-directory.",
+                file_path.display(),
+                snapshots.len(),
+                manager.directory().display()
+            ),
+            Ok(_) => {}
+            Err(e) => fatal!("Failed to read snapshots: {}", e)
+        }
+    }
+
+    let target_dir = output_dir.unwrap_or(game_dir);
+
+    if let Err(e) = std::fs::create_dir_all(target_dir) {
+        fatal!("Failed to create directory {}: {}", target_dir.display(), e);
+    }
+
+    info!("Compacting {}...", file_path.display());
+
+    // all streams are staged, so the archive is only replaced once it was compacted completely
+    let start = std::time::Instant::now();
+    let report = match compact_archive(
+        &kfc_reader,
+        DiskStorage::new(target_dir, &file_name),
+        &type_registry,
+        KFCWriteOptions {
+            overwrite_containers: true,
+            truncate_containers: true,
+            compression_threads: thread_count as usize,
+            ..Default::default()
+        }
+    ) {
+        Ok(report) => report,
+        Err(e) => fatal!("Failed to compact {}: {}", file_path.display(), e)
+    };
+    let end = std::time::Instant::now();
+
+    if output_dir.is_none() {
+        let storage = DiskStorage::new(game_dir, &file_name);
+        let new_reader = match KFCReader::from_storage(storage.clone()) {
+            Ok(reader) => reader,
+            Err(e) => fatal!("Failed to open compacted files: {}", e)
+        };
+
+        // containers past the compacted ones are no longer referenced
+        for index in new_reader.file().containers().len()..kfc_reader.file().containers().len() {
+            let path = storage.stream_path(KFCStream::Container(index));
+
+            if let Err(e) = std::fs::remove_file(&path) {
+                warn!("Failed to remove {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    info!(
+        "Compacted {} resources and {} contents in {:?}, reclaimed {} bytes ({} -> {} bytes)",
+        report.resources,
+        report.contents,
+        end - start,
+        report.reclaimed_size(),
+        report.old_size,
+        report.new_size
+    );
+
+    Ok(())
+}
+
+fn merge(
+    inputs: &[PathBuf],
+    output: &Path,
+    conflict: MergeConflict,
+    thread_count: u8,
+) -> Result<(), Error> {
+    let (output_dir, output_name) = split_kfc_path(output)?;
+    let mut readers = Vec::with_capacity(inputs.len());
+
+    for input in inputs {
+        let (dir, file_name) = split_kfc_path(input)?;
+
+        if dir.canonicalize().ok() == output_dir.canonicalize().ok() && file_name == output_name {
+            fatal!("The output {} must not be one of the inputs", output.display());
+        }
+
+        match KFCReader::new(&dir, &file_name) {
+            Ok(reader) => readers.push(reader),
+            Err(e) => fatal!("Failed to open {}: {}", input.display(), e)
+        }
+    }
+
+    let type_registry = load_type_registry(Some(&output_dir), Some(&output_name), true)?;
+
+    if let Err(e) = std::fs::create_dir_all(&output_dir) {
+        fatal!("Failed to create directory {}: {}", output_dir.display(), e);
+    }
+
+    info!("Merging {} archives into {}...", readers.len(), output.display());
+
+    let start = std::time::Instant::now();
+    let report = match merge_archives(
+        &readers.iter().collect::<Vec<_>>(),
+        DiskStorage::new(&output_dir, &output_name),
+        &type_registry,
+        KFCMergeOptions {
+            conflict_policy: match conflict {
+                MergeConflict::First => KFCMergeConflictPolicy::FirstWins,
+                MergeConflict::Last => KFCMergeConflictPolicy::LastWins,
+                MergeConflict::Error => KFCMergeConflictPolicy::Error,
+            },
+            write_options: KFCWriteOptions {
+                overwrite_containers: true,
+                truncate_containers: true,
+                compression_threads: thread_count as usize,
+                ..Default::default()
+            },
+        }
+    ) {
+        Ok(report) => report,
+        Err(e) => fatal!("Failed to merge archives: {}", e)
+    };
+    let end = std::time::Instant::now();
+
+    for id in &report.conflicts {
+        warn!("Resource {} exists in multiple archives", id);
+    }
+
+    info!(
+        "Merged {} resources and {} contents in {:?} ({} conflicts, {} duplicate contents)",
+        report.resources,
+        report.contents,
+        end - start,
+        report.conflicts.len(),
+        report.duplicate_contents
+    );
+
+    Ok(())
+}
+
+/// Splits the path of a .kfc file into its directory and file name without extension.
+fn split_kfc_path(path: &Path) -> Result<(PathBuf, String), Error> {
+    let Some(file_name) = path.file_stem().and_then(|name| name.to_str()) else {
+        fatal!("Invalid archive path: {}", path.display());
+    };
+
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    Ok((dir, file_name.to_string()))
+}
+
 fn load_type_registry(
     game_dir: Option<&Path>,
     file_name: Option<&str>,
@@ -1067,7 +1889,7 @@ fn load_type_registry(
                 fatal!("Failed to load reflection_data.json: {}", e);
             }
         }
-        Err(TypeParseError::Json(e)) => {
+        Err(e @ (TypeParseError::Json(_) | TypeParseError::Cache(_))) => {
             if let Some(game_dir) = game_dir {
                 if retry {
                     warn!("reflection_data.json is invalid, attempting to extract types again...");
@@ -1309,17 +2131,6 @@ fn get_file(
     Ok(file)
 }
 
-fn get_file_opt(
-    game_dir: &Path,
-    file_name: Option<&str>,
-    extension: &str
-) -> Result<PathBuf, Error> {
-    let file_name = get_file_name(game_dir, file_name)?;
-    let file = game_dir.join(format!("{}.{}", file_name, extension));
-
-    Ok(file)
-}
-
 fn get_file_name(
     game_dir: &Path,
     file_name: Option<&str>
@@ -1353,11 +2164,14 @@ fn get_file_name(
 fn load_types_from_path(
     path: impl AsRef<Path>
 ) -> Result<TypeRegistry, TypeParseError> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
-    let json = serde_json::from_reader::<_, TypeRegistry>(reader)?;
+    let data = std::fs::read(path)?;
 
-    Ok(json)
+    // the binary cache of the mod loader is accepted as well
+    match TypeRegistry::read_cache(&data) {
+        Ok((type_registry, _)) => Ok(type_registry),
+        Err(TypeCacheError::InvalidMagic) => Ok(serde_json::from_slice(&data)?),
+        Err(e) => Err(e.into()),
+    }
 }
 
 fn dump_types_to_path(
@@ -1383,4 +2197,6 @@ pub enum TypeParseError {
     Io(#[from] std::io::Error),
     #[error("JSON error: {0}")]
     Json(#[from] serde_json::Error),
+    #[error("Cache error: {0}")]
+    Cache(#[from] TypeCacheError),
 }
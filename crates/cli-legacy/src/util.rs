@@ -1,27 +1,19 @@
-use std::borrow::Borrow;
+use kfc::{container::KFCFile, guid::ResourceId, reflection::{LookupKey, TypeRegistry}, resource::value::{ConversionOptions, Value}};
 
-use kfc::{container::{KFCCursor, KFCReader}, guid::ResourceId, reflection::{LookupKey, TypeRegistry}, resource::value::{ConversionOptions, Value}};
+/// Splits the given resources into batches of neighbouring resources,
+/// so each batch only touches a few resource chunks when read with `read_resources`.
+pub fn resource_batches<'a>(
+    file: &KFCFile,
+    guids: impl IntoIterator<Item = &'a ResourceId>,
+    batch_size: usize
+) -> Vec<Vec<&'a ResourceId>> {
+    let mut guids = guids.into_iter().collect::<Vec<_>>();
 
-pub fn read_descriptor_into<R>(
-    reader: &mut KFCCursor<R>,
-    type_registry: &TypeRegistry,
-    guid: &ResourceId,
-    buf: &mut Vec<u8>
-) -> anyhow::Result<Option<Value>>
-where
-    R: Borrow<KFCReader>,
-{
-    if !reader.read_resource_into(guid, buf)? {
-        return Ok(None);
-    }
-
-    let result = deserialize_descriptor(
-        type_registry,
-        guid,
-        buf
-    )?;
+    guids.sort_by_key(|guid| file.resources().get(guid).map(|entry| entry.offset));
 
-    Ok(Some(result))
+    guids.chunks(batch_size.max(1))
+        .map(|batch| batch.to_vec())
+        .collect()
 }
 
 pub fn serialize_descriptor(
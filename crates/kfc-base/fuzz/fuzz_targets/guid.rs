@@ -0,0 +1,22 @@
+#![no_main]
+
+use kfc_base::guid::{ContentHash, ResourceId};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(id) = ResourceId::read(&mut &data[..]) {
+        let mut bytes = Vec::new();
+        id.write(&mut bytes).unwrap();
+
+        assert_eq!(ResourceId::read(&mut &bytes[..]).unwrap(), id);
+        let _ = id.to_string();
+    }
+
+    if let Ok(hash) = ContentHash::read(&mut &data[..]) {
+        let mut bytes = Vec::new();
+        hash.write(&mut bytes).unwrap();
+
+        assert_eq!(ContentHash::read(&mut &bytes[..]).unwrap(), hash);
+        let _ = hash.to_string();
+    }
+});
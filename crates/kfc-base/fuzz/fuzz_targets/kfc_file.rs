@@ -0,0 +1,37 @@
+#![no_main]
+
+use std::io::Cursor;
+
+use kfc_base::container::KFCFile;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = KFCFile::read_version_tag(&mut Cursor::new(data));
+    let _ = KFCFile::from_reader(&mut Cursor::new(data), true);
+
+    // lazy lookups must not panic either, even without validated references
+    if let Ok(view) = KFCFile::view(data) {
+        for id in view.resources().keys().iter() {
+            let _ = view.resources().get(&id);
+        }
+
+        for (type_hash, _) in view.resource_bundles().iter() {
+            let _ = view.resources_by_type(type_hash).count();
+        }
+    }
+
+    if let Ok(file) = KFCFile::from_reader(&mut Cursor::new(data), false) {
+        // lookups must not panic on any file which was accepted
+        for id in file.resources().keys() {
+            assert!(file.resources().contains_key(id));
+        }
+
+        for hash in file.contents().keys() {
+            assert!(file.contents().contains_key(hash));
+        }
+
+        for type_hash in file.resource_types() {
+            let _ = file.resources_by_type(type_hash).count();
+        }
+    }
+});
@@ -0,0 +1,33 @@
+#![no_main]
+
+use kfc_base::{container::{StaticMap, StaticMapBucket}, io::ReadExt};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = data;
+
+    let (Ok(bucket_count), Ok(key_count)) = (reader.read_u8(), reader.read_u8()) else {
+        return;
+    };
+
+    let buckets = (0..bucket_count)
+        .map(|_| StaticMapBucket::read(&mut reader))
+        .collect::<Result<Vec<_>, _>>();
+    let keys = (0..key_count)
+        .map(|_| reader.read_u32())
+        .collect::<Result<Vec<_>, _>>();
+
+    let (Ok(buckets), Ok(keys)) = (buckets, keys) else {
+        return;
+    };
+
+    let values = keys.clone();
+
+    if let Ok(map) = StaticMap::from_parts(keys, values, buckets) {
+        for key in map.keys() {
+            let _ = map.get(key);
+        }
+
+        let _ = map.get(&0);
+    }
+});
@@ -0,0 +1,357 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Serialize;
+
+use crate::{guid::ContentHash, reflection::{LookupKey, TypeRegistry}};
+
+use super::{reader::ResourceSpan, writer::{CONTENT_ALIGNMENT, RESOURCE_ALIGNMENT}, KFCFile};
+
+/// A breakdown of the storage used by an archive.
+///
+/// The analysis only uses the index, so neither resources nor contents are read.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct KFCAnalysis {
+    pub version: String,
+
+    pub resources: KFCResourceAnalysis,
+    /// The resources grouped by the qualified type name.
+    pub types: BTreeMap<String, KFCTypeAnalysis>,
+    pub chunks: Vec<KFCChunkAnalysis>,
+
+    pub containers: Vec<KFCContainerAnalysis>,
+    /// Groups of contents which are stored at the same location.
+    pub duplicate_contents: Vec<KFCDuplicateContents>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct KFCResourceAnalysis {
+    pub count: usize,
+    /// The total size of all resources.
+    pub size: u64,
+
+    /// The size of the uncompressed resource stream.
+    pub uncompressed_size: u64,
+    /// The size of the compressed resource chunks, without their alignment.
+    pub compressed_size: u64,
+    /// The size of the resource chunks including their alignment.
+    pub stored_size: u64,
+
+    /// Bytes between resources which are used to align them.
+    pub padding: u64,
+    /// Bytes of the uncompressed resource stream which no resource references,
+    /// usually left behind by incremental writes.
+    pub unreferenced_size: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct KFCTypeAnalysis {
+    pub count: usize,
+    /// The total size of all resources of this type.
+    pub size: u64,
+    /// The share of the compressed chunks used by the resources of this type.
+    /// Resources are compressed together, so this is only an estimate.
+    pub compressed_size: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct KFCChunkAnalysis {
+    pub offset: u64,
+    pub size: u64,
+    pub compressed_size: u64,
+    pub uncompressed_size: u64,
+    /// The uncompressed size divided by the compressed size.
+    pub ratio: f64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct KFCContainerAnalysis {
+    pub count: usize,
+    /// The size of the container as stored in the index.
+    pub size: u64,
+    /// The total size of all contents in this container.
+    pub content_size: u64,
+
+    /// Bytes between contents which are used to align them.
+    pub padding: u64,
+    /// Bytes which no content references.
+    pub unreferenced_size: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct KFCDuplicateContents {
+    pub container: usize,
+    pub offset: u64,
+    pub contents: Vec<ContentHash>,
+}
+
+impl KFCAnalysis {
+
+    /// The size of the resource chunks and containers.
+    pub fn total_size(&self) -> u64 {
+        self.resources.stored_size + self.containers.iter().map(|container| container.size).sum::<u64>()
+    }
+
+    /// The bytes used to align resources, resource chunks and contents.
+    pub fn padding(&self) -> u64 {
+        self.resources.padding +
+            (self.resources.stored_size - self.resources.compressed_size) +
+            self.containers.iter().map(|container| container.padding).sum::<u64>()
+    }
+
+    /// The bytes which are not referenced by any resource or content.
+    pub fn unreferenced_size(&self) -> u64 {
+        self.resources.unreferenced_size +
+            self.containers.iter().map(|container| container.unreferenced_size).sum::<u64>()
+    }
+
+}
+
+impl KFCChunkAnalysis {
+
+    #[inline]
+    pub fn padding(&self) -> u64 {
+        self.size.saturating_sub(self.compressed_size)
+    }
+
+}
+
+/// Computes the storage used by the resources, chunks and contents of an archive.
+pub fn analyze_file(
+    file: &KFCFile,
+    type_registry: &TypeRegistry,
+) -> KFCAnalysis {
+    let (resources, types) = analyze_resources(file, type_registry);
+    let (containers, duplicate_contents) = analyze_contents(file);
+
+    let chunks = file.resource_chunks().iter()
+        .map(|chunk| KFCChunkAnalysis {
+            offset: chunk.offset,
+            size: chunk.size,
+            compressed_size: chunk.compressed_size,
+            uncompressed_size: chunk.uncompressed_size,
+            ratio: match chunk.compressed_size {
+                0 => 0.0,
+                size => chunk.uncompressed_size as f64 / size as f64,
+            },
+        })
+        .collect();
+
+    KFCAnalysis {
+        version: file.game_version().to_string(),
+
+        resources,
+        types,
+        chunks,
+
+        containers,
+        duplicate_contents,
+    }
+}
+
+fn analyze_resources(
+    file: &KFCFile,
+    type_registry: &TypeRegistry,
+) -> (KFCResourceAnalysis, BTreeMap<String, KFCTypeAnalysis>) {
+    let chunks = file.resource_chunks();
+    let mut types = BTreeMap::new();
+
+    for (type_hash, _) in file.resource_bundles().iter() {
+        let mut analysis = KFCTypeAnalysis::default();
+        let mut compressed_size = 0.0;
+
+        for id in file.resources_by_type(*type_hash) {
+            let Some(span) = ResourceSpan::locate(file, id) else {
+                continue;
+            };
+
+            analysis.count += 1;
+            analysis.size += span.size;
+
+            // attribute the compressed size of each chunk proportionally to the resources in it
+            for chunk in &chunks[span.chunks.clone()] {
+                let start = span.offset.max(chunk.uncompressed_offset);
+                let end = (span.offset + span.size).min(chunk.uncompressed_offset + chunk.uncompressed_size);
+
+                if end > start && chunk.uncompressed_size > 0 {
+                    compressed_size += (end - start) as f64 * chunk.compressed_size as f64 / chunk.uncompressed_size as f64;
+                }
+            }
+        }
+
+        analysis.compressed_size = compressed_size.round() as u64;
+
+        let name = type_registry.get_by_hash(LookupKey::Qualified(*type_hash))
+            .map(|t| t.qualified_name.clone())
+            .unwrap_or_else(|| format!("{type_hash:08x}"));
+
+        types.insert(name, analysis);
+    }
+
+    let stream_start = chunks.first().map(|chunk| chunk.uncompressed_offset).unwrap_or(0);
+    let stream_end = chunks.last().map(|chunk| chunk.uncompressed_offset + chunk.uncompressed_size).unwrap_or(0);
+
+    let mut spans = file.resources().values().iter()
+        .map(|entry| (entry.offset, entry.size))
+        .collect::<Vec<_>>();
+    spans.sort_unstable();
+
+    let (padding, unreferenced_size) = measure_gaps(&spans, stream_start, stream_end, RESOURCE_ALIGNMENT);
+
+    let resources = KFCResourceAnalysis {
+        count: file.resources().len(),
+        size: spans.iter().map(|(_, size)| size).sum(),
+
+        uncompressed_size: chunks.iter().map(|chunk| chunk.uncompressed_size).sum(),
+        compressed_size: chunks.iter().map(|chunk| chunk.compressed_size).sum(),
+        stored_size: chunks.iter().map(|chunk| chunk.size).sum(),
+
+        padding,
+        unreferenced_size,
+    };
+
+    (resources, types)
+}
+
+fn analyze_contents(file: &KFCFile) -> (Vec<KFCContainerAnalysis>, Vec<KFCDuplicateContents>) {
+    let mut contents = HashMap::<usize, Vec<(u64, u64, ContentHash)>>::new();
+
+    for (hash, entry) in file.contents().iter() {
+        contents.entry(entry.container_index)
+            .or_default()
+            .push((entry.offset, hash.size() as u64, *hash));
+    }
+
+    let mut containers = Vec::with_capacity(file.containers().len());
+    let mut duplicate_contents = Vec::new();
+
+    for (index, info) in file.containers().iter().enumerate() {
+        let mut entries = contents.remove(&index).unwrap_or_default();
+        entries.sort_unstable();
+
+        for group in entries.chunk_by(|a, b| a.0 == b.0) {
+            if group.len() > 1 {
+                duplicate_contents.push(KFCDuplicateContents {
+                    container: index,
+                    offset: group[0].0,
+                    contents: group.iter().map(|(_, _, hash)| *hash).collect(),
+                });
+            }
+        }
+
+        let spans = entries.iter()
+            .map(|&(offset, size, _)| (offset, size))
+            .collect::<Vec<_>>();
+        let (padding, unreferenced_size) = measure_gaps(&spans, 0, info.size, CONTENT_ALIGNMENT);
+
+        containers.push(KFCContainerAnalysis {
+            count: entries.len(),
+            size: info.size,
+            content_size: spans.iter().map(|(_, size)| size).sum(),
+
+            padding,
+            unreferenced_size,
+        });
+    }
+
+    (containers, duplicate_contents)
+}
+
+/// Sums up the gaps between the given spans, which must be sorted by their offset.
+///
+/// Gaps smaller than the alignment are padding, all other gaps are unreferenced.
+/// Returns the padding and the unreferenced size.
+fn measure_gaps(
+    spans: &[(u64, u64)],
+    start: u64,
+    end: u64,
+    alignment: u64,
+) -> (u64, u64) {
+    let mut padding = 0;
+    let mut unreferenced = 0;
+    let mut position = start;
+
+    let mut add_gap = |gap: u64| {
+        if gap < alignment {
+            padding += gap;
+        } else {
+            unreferenced += gap;
+        }
+    };
+
+    for &(offset, size) in spans {
+        if offset > position {
+            add_gap(offset - position);
+        }
+
+        position = position.max(offset.saturating_add(size));
+    }
+
+    if end > position {
+        add_gap(end - position);
+    }
+
+    (padding, unreferenced)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{container::KFCWriter, testing::*};
+
+    use super::*;
+
+    #[test]
+    fn test_measure_gaps() {
+        assert_eq!(measure_gaps(&[], 0, 0, 16), (0, 0));
+        assert_eq!(measure_gaps(&[], 0, 100, 16), (0, 100));
+
+        // padding between and after the spans
+        assert_eq!(measure_gaps(&[(0, 10), (16, 10)], 0, 32, 16), (12, 0));
+
+        // a stale span in the middle, overlapping spans are not counted twice
+        assert_eq!(measure_gaps(&[(0, 16), (64, 16), (64, 8), (70, 20)], 0, 96, 16), (6, 48));
+    }
+
+    #[test]
+    fn test_analyze_archive() -> Result<(), Box<dyn std::error::Error>> {
+        let archive = sample_archive(1);
+        let analysis = analyze_file(archive.reader()?.file(), archive.type_registry());
+
+        assert_eq!(analysis.resources.count, 32);
+        assert_eq!(analysis.resources.unreferenced_size, 0);
+        assert_eq!(analysis.types[SAMPLE_TYPE_A].count, 16);
+        assert_eq!(analysis.types[SAMPLE_TYPE_A].size, 240 * 997);
+        assert_eq!(analysis.types[SAMPLE_TYPE_B].size, 256 * 997);
+        assert!(analysis.types.values().map(|t| t.compressed_size).sum::<u64>() <= analysis.resources.compressed_size + 2);
+        assert_eq!(analysis.chunks.len(), archive.reader()?.file().resource_chunks().len());
+        assert!(analysis.chunks.iter().all(|chunk| chunk.ratio > 0.0));
+
+        // every content is padded to the next 4 KiB
+        assert_eq!(analysis.containers.iter().map(|c| c.count).sum::<usize>(), 8);
+        assert_eq!(analysis.containers.iter().map(|c| c.content_size).sum::<u64>(), 8 * 5000 + 28);
+        assert_eq!(analysis.containers.iter().map(|c| c.padding).sum::<u64>(), 8 * 8192 - (8 * 5000 + 28));
+        assert_eq!(analysis.unreferenced_size(), 0);
+        assert!(analysis.duplicate_contents.is_empty());
+
+        // replaced resources leave their old data behind
+
+        let reference = archive.reader()?.file().clone();
+        let mut writer = KFCWriter::new_incremental(
+            archive.path(),
+            archive.file_name(),
+            &reference,
+            archive.type_registry(),
+        )?;
+
+        writer.write_resource(&sample_resource_id(2, SAMPLE_TYPE_A), &sample_resource_data(3, 100))?;
+        writer.finalize()?;
+
+        let analysis = analyze_file(archive.reader()?.file(), archive.type_registry());
+
+        assert_eq!(analysis.types[SAMPLE_TYPE_A].size, 238 * 997 + 100);
+        // the padding around the old data is counted as well
+        assert!((2 * 997..2 * 997 + 32).contains(&analysis.resources.unreferenced_size));
+
+        Ok(())
+    }
+
+}
@@ -0,0 +1,186 @@
+use std::{collections::{BTreeMap, HashMap}, sync::{Arc, Mutex}};
+
+/// The default memory budget of a [`ChunkCache`] (256 MiB, 32 uncompressed chunks).
+pub const DEFAULT_CHUNK_CACHE_BUDGET: usize = 256 * 1024 * 1024;
+
+/// A thread-safe cache for decompressed resource chunks.
+///
+/// The cache holds at most `budget` bytes of chunk data and evicts the least
+/// recently used chunks once the budget is exceeded. Chunks larger than the
+/// budget are never cached.
+#[derive(Debug)]
+pub struct ChunkCache {
+    budget: usize,
+    inner: Mutex<ChunkCacheInner>,
+}
+
+#[derive(Debug, Default)]
+struct ChunkCacheInner {
+    entries: HashMap<usize, ChunkCacheEntry>,
+    /// Maps the last access tick to the chunk index.
+    recency: BTreeMap<u64, usize>,
+    tick: u64,
+    size: usize,
+}
+
+#[derive(Debug)]
+struct ChunkCacheEntry {
+    data: Arc<[u8]>,
+    last_used: u64,
+}
+
+impl Default for ChunkCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CHUNK_CACHE_BUDGET)
+    }
+}
+
+impl ChunkCache {
+
+    #[inline]
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            inner: Mutex::default(),
+        }
+    }
+
+    #[inline]
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Returns the number of bytes currently held by the cache.
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.lock().size
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.lock().entries.is_empty()
+    }
+
+    /// Returns the cached chunk and marks it as most recently used.
+    pub fn get(&self, index: usize) -> Option<Arc<[u8]>> {
+        let mut inner = self.lock();
+        let tick = inner.next_tick();
+        let entry = inner.entries.get_mut(&index)?;
+        let previous = std::mem::replace(&mut entry.last_used, tick);
+        let data = entry.data.clone();
+
+        inner.recency.remove(&previous);
+        inner.recency.insert(tick, index);
+
+        Some(data)
+    }
+
+    /// Inserts the chunk into the cache, evicting the least recently used chunks if needed.
+    pub fn insert(&self, index: usize, data: Arc<[u8]>) {
+        if data.len() > self.budget {
+            return;
+        }
+
+        let mut inner = self.lock();
+        let tick = inner.next_tick();
+
+        if let Some(previous) = inner.entries.remove(&index) {
+            inner.recency.remove(&previous.last_used);
+            inner.size -= previous.data.len();
+        }
+
+        while inner.size + data.len() > self.budget {
+            let Some((_, evicted)) = inner.recency.pop_first() else {
+                break;
+            };
+
+            if let Some(entry) = inner.entries.remove(&evicted) {
+                inner.size -= entry.data.len();
+            }
+        }
+
+        inner.size += data.len();
+        inner.recency.insert(tick, index);
+        inner.entries.insert(index, ChunkCacheEntry {
+            data,
+            last_used: tick,
+        });
+    }
+
+    pub fn clear(&self) {
+        let mut inner = self.lock();
+
+        inner.entries.clear();
+        inner.recency.clear();
+        inner.size = 0;
+    }
+
+    #[inline]
+    fn lock(&self) -> std::sync::MutexGuard<'_, ChunkCacheInner> {
+        // the cache is always left in a consistent state, so a poisoned lock can be recovered
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+}
+
+impl ChunkCacheInner {
+
+    #[inline]
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(size: usize) -> Arc<[u8]> {
+        vec![0; size].into()
+    }
+
+    #[test]
+    fn test_chunk_cache_budget() {
+        let cache = ChunkCache::new(10);
+
+        cache.insert(0, chunk(4));
+        cache.insert(1, chunk(4));
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.size(), 8);
+
+        cache.insert(2, chunk(4));
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.size(), 8);
+        assert!(cache.get(0).is_none());
+
+        cache.insert(3, chunk(11));
+
+        assert!(cache.get(3).is_none());
+    }
+
+    #[test]
+    fn test_chunk_cache_lru() {
+        let cache = ChunkCache::new(8);
+
+        cache.insert(0, chunk(4));
+        cache.insert(1, chunk(4));
+
+        assert!(cache.get(0).is_some());
+
+        cache.insert(2, chunk(4));
+
+        assert!(cache.get(0).is_some());
+        assert!(cache.get(1).is_none());
+        assert!(cache.get(2).is_some());
+    }
+
+}
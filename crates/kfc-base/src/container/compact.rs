@@ -0,0 +1,219 @@
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::reflection::TypeRegistry;
+
+use super::{KFCFile, KFCReadError, KFCReader, KFCStorage, KFCStream, KFCWriteError, KFCWriteOptions, KFCWriter};
+
+#[derive(Debug, Error)]
+pub enum KFCCompactError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Read error: {0}")]
+    Read(#[from] KFCReadError),
+    #[error("Write error: {0}")]
+    Write(#[from] KFCWriteError),
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct KFCCompactReport {
+    /// The number of resources which were copied.
+    pub resources: usize,
+    /// The number of contents which were copied.
+    pub contents: usize,
+
+    /// The size of the resource chunks and containers before compaction.
+    pub old_size: u64,
+    /// The size of the resource chunks and containers after compaction.
+    pub new_size: u64,
+}
+
+impl KFCCompactReport {
+
+    #[inline]
+    pub fn reclaimed_size(&self) -> u64 {
+        self.old_size.saturating_sub(self.new_size)
+    }
+
+}
+
+/// Rewrites an archive into the given storage, keeping only the resources and contents
+/// which are referenced by its index.
+///
+/// Data of removed resources and contents, as well as all chunks and containers
+/// which were appended by incremental writes, are merged into a minimal archive.
+/// Resources and contents keep their relative order, so their locality is preserved.
+///
+/// All streams are staged and only replace the current streams once the compacted
+/// archive is complete, so the storage may also be the storage of the reader.
+/// Streams which are no longer referenced, like surplus containers, are left untouched.
+pub fn compact_archive(
+    reader: &KFCReader,
+    storage: impl KFCStorage + 'static,
+    type_registry: &TypeRegistry,
+    options: KFCWriteOptions,
+) -> Result<KFCCompactReport, KFCCompactError> {
+    let file = reader.file();
+    let mut cursor = reader.new_cursor()?;
+
+    let old_size = archive_size(reader.storage().as_ref(), file)?;
+
+    let mut writer = KFCWriter::<KFCFile, _>::with_storage(
+        storage,
+        type_registry,
+        file.game_version(),
+        options,
+    )?;
+
+    // resources
+
+    let mut resources = file.resources().iter().collect::<Vec<_>>();
+    resources.sort_by_key(|(_, entry)| entry.offset);
+
+    let mut buffer = Vec::new();
+
+    for (id, _) in &resources {
+        buffer.clear();
+        cursor.read_resource_into(id, &mut buffer)?;
+        writer.write_resource(id, &buffer)?;
+    }
+
+    // contents
+
+    let mut contents = file.contents().iter().collect::<Vec<_>>();
+    contents.sort_by_key(|(_, entry)| (entry.container_index, entry.offset));
+
+    for (hash, entry) in &contents {
+        buffer.clear();
+        cursor.read_content_into(hash, &mut buffer)?;
+        writer.write_content_with_flags(hash, &buffer, entry.flags)?;
+    }
+
+    let storage = writer.storage().clone();
+
+    // the streams of the reader must be closed before they are replaced
+    drop(cursor);
+
+    writer.finalize()?;
+
+    let compacted = KFCReader::from_storage(storage.clone())?;
+    let new_size = archive_size(storage.as_ref(), compacted.file())?;
+
+    Ok(KFCCompactReport {
+        resources: resources.len(),
+        contents: contents.len(),
+
+        old_size,
+        new_size,
+    })
+}
+
+fn archive_size(storage: &dyn KFCStorage, file: &KFCFile) -> std::io::Result<u64> {
+    let mut size = storage.len(KFCStream::Resources)?;
+
+    for index in 0..file.containers().len() {
+        let stream = KFCStream::Container(index);
+
+        if storage.exists(stream) {
+            size += storage.len(stream)?;
+        }
+    }
+
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{container::{DiskStorage, KFCVerifyOptions, MemoryStorage}, hash::fnv, testing::*};
+
+    use super::*;
+
+    #[test]
+    fn test_compact_archive() -> Result<(), Box<dyn std::error::Error>> {
+        let archive = sample_archive(4);
+        let reference = archive.reader()?.file().clone();
+        let removed_content = *reference.contents().keys().first().expect("archive has contents");
+
+        // leave some garbage behind: removed entries and a rewritten resource
+
+        let mut writer = KFCWriter::new_incremental(
+            archive.path(),
+            archive.file_name(),
+            &reference,
+            archive.type_registry(),
+        )?;
+
+        writer.remove_resources_by_type(fnv(SAMPLE_TYPE_B));
+        writer.remove_content(&removed_content);
+        writer.write_resource(&sample_resource_id(2, SAMPLE_TYPE_A), &sample_resource_data(42, 1000))?;
+        writer.finalize()?;
+
+        let kfc_reader = archive.reader()?;
+        let storage = MemoryStorage::new();
+        let report = compact_archive(&kfc_reader, storage.clone(), archive.type_registry(), KFCWriteOptions {
+            resource_chunk_size: 4096,
+            ..Default::default()
+        })?;
+
+        assert_eq!(report.resources, 16);
+        assert_eq!(report.contents, 7);
+        assert!(report.new_size < report.old_size, "{report:?}");
+
+        let compacted = KFCReader::from_storage(storage)?;
+        let mut old_cursor = kfc_reader.new_cursor()?;
+        let mut new_cursor = compacted.new_cursor()?;
+
+        assert_eq!(compacted.file().resources().len(), 16);
+        assert_eq!(compacted.file().contents().len(), 7);
+
+        for id in kfc_reader.file().resources().keys() {
+            assert_eq!(old_cursor.read_resource(id)?, new_cursor.read_resource(id)?);
+        }
+
+        for hash in kfc_reader.file().contents().keys() {
+            assert_eq!(old_cursor.read_content(hash)?, new_cursor.read_content(hash)?);
+        }
+
+        assert!(compacted.verify(&KFCVerifyOptions::default())?.is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compact_archive_in_place() -> Result<(), Box<dyn std::error::Error>> {
+        let archive = sample_archive(4);
+        let kfc_reader = archive.reader()?;
+        let storage = DiskStorage::new(archive.path(), archive.file_name());
+
+        let mut cursor = kfc_reader.new_cursor()?;
+        let expected = kfc_reader.file().resources().keys().iter()
+            .map(|id| Ok((*id, cursor.read_resource(id)?.unwrap())))
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        drop(cursor);
+
+        compact_archive(&kfc_reader, storage.clone(), archive.type_registry(), KFCWriteOptions {
+            resource_chunk_size: 4096,
+            overwrite_containers: true,
+            truncate_containers: true,
+            ..Default::default()
+        })?;
+
+        // no staged streams are left behind
+        for stream in [KFCStream::Index, KFCStream::Resources, KFCStream::Container(0)] {
+            assert!(!storage.staged_path(stream).exists());
+        }
+
+        let compacted = archive.reader()?;
+        let mut cursor = compacted.new_cursor()?;
+
+        for (id, data) in &expected {
+            assert_eq!(cursor.read_resource(id)?.as_ref(), Some(data));
+        }
+
+        assert!(compacted.verify(&KFCVerifyOptions::default())?.is_ok());
+
+        Ok(())
+    }
+
+}
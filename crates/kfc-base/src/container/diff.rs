@@ -0,0 +1,215 @@
+use std::{collections::{BTreeMap, HashMap, HashSet}, convert::Infallible};
+
+use serde::Serialize;
+
+use crate::{guid::{ContentHash, ResourceId}, reflection::{LookupKey, TypeRegistry}, Hash32};
+
+use super::{KFCFile, KFCReadError, KFCReader, DEFAULT_CHUNK_CACHE_BUDGET};
+
+/// The differences between two versions of an archive.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct KFCDiff {
+    pub old_version: String,
+    pub new_version: String,
+
+    /// The resource changes grouped by the qualified type name.
+    pub types: BTreeMap<String, KFCTypeDiff>,
+
+    pub added_contents: Vec<ContentHash>,
+    pub removed_contents: Vec<ContentHash>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct KFCTypeDiff {
+    pub added: Vec<ResourceId>,
+    pub removed: Vec<ResourceId>,
+    pub changed: Vec<ResourceId>,
+
+    /// The total size of all resources of this type in the old archive.
+    pub old_size: u64,
+    /// The total size of all resources of this type in the new archive.
+    pub new_size: u64,
+}
+
+impl KFCDiff {
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty() &&
+            self.added_contents.is_empty() &&
+            self.removed_contents.is_empty()
+    }
+
+    pub fn added_resources(&self) -> impl Iterator<Item = &ResourceId> {
+        self.types.values().flat_map(|diff| diff.added.iter())
+    }
+
+    pub fn removed_resources(&self) -> impl Iterator<Item = &ResourceId> {
+        self.types.values().flat_map(|diff| diff.removed.iter())
+    }
+
+    pub fn changed_resources(&self) -> impl Iterator<Item = &ResourceId> {
+        self.types.values().flat_map(|diff| diff.changed.iter())
+    }
+
+}
+
+impl KFCTypeDiff {
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() &&
+            self.removed.is_empty() &&
+            self.changed.is_empty() &&
+            self.old_size == self.new_size
+    }
+
+    #[inline]
+    pub fn size_delta(&self) -> i64 {
+        self.new_size as i64 - self.old_size as i64
+    }
+
+}
+
+/// Compares the index of two archives.
+///
+/// Resources which exist in both archives are considered changed if their size differs.
+/// Use [`diff_archives`] to compare the resource data as well.
+pub fn diff_files(
+    old: &KFCFile,
+    new: &KFCFile,
+    type_registry: &TypeRegistry,
+) -> KFCDiff {
+    let Ok(diff) = diff_with(old, new, type_registry, |id| {
+        let old_entry = old.resources().get(id);
+        let new_entry = new.resources().get(id);
+
+        Ok::<_, Infallible>(old_entry.map(|e| e.size) != new_entry.map(|e| e.size))
+    });
+
+    diff
+}
+
+/// Compares two archives including the data of all resources which exist in both archives.
+pub fn diff_archives(
+    old: &KFCReader,
+    new: &KFCReader,
+    type_registry: &TypeRegistry,
+) -> Result<KFCDiff, KFCReadError> {
+    let old_cursor = old.new_shared_cursor(DEFAULT_CHUNK_CACHE_BUDGET)?;
+    let new_cursor = new.new_shared_cursor(DEFAULT_CHUNK_CACHE_BUDGET)?;
+
+    let mut old_data = Vec::new();
+    let mut new_data = Vec::new();
+
+    diff_with(old.file(), new.file(), type_registry, |id| {
+        old_data.clear();
+        new_data.clear();
+
+        old_cursor.read_resource_into(id, &mut old_data)?;
+        new_cursor.read_resource_into(id, &mut new_data)?;
+
+        Ok::<_, KFCReadError>(old_data != new_data)
+    })
+}
+
+fn diff_with<E>(
+    old: &KFCFile,
+    new: &KFCFile,
+    type_registry: &TypeRegistry,
+    mut is_changed: impl FnMut(&ResourceId) -> Result<bool, E>,
+) -> Result<KFCDiff, E> {
+    let mut diffs = HashMap::<Hash32, KFCTypeDiff>::new();
+
+    // resources
+
+    for (id, entry) in old.resources().iter() {
+        let diff = diffs.entry(id.type_hash()).or_default();
+        diff.old_size += entry.size;
+
+        if !new.resources().contains_key(id) {
+            diff.removed.push(*id);
+        }
+    }
+
+    for (id, entry) in new.resources().iter() {
+        let diff = diffs.entry(id.type_hash()).or_default();
+        diff.new_size += entry.size;
+
+        if !old.resources().contains_key(id) {
+            diff.added.push(*id);
+        } else if is_changed(id)? {
+            diff.changed.push(*id);
+        }
+    }
+
+    let types = diffs.into_iter()
+        .filter(|(_, diff)| !diff.is_empty())
+        .map(|(type_hash, mut diff)| {
+            let name = type_registry.get_by_hash(LookupKey::Qualified(type_hash))
+                .map(|t| t.qualified_name.clone())
+                .unwrap_or_else(|| format!("{type_hash:08x}"));
+
+            diff.added.sort();
+            diff.removed.sort();
+            diff.changed.sort();
+
+            (name, diff)
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    // contents
+
+    let old_contents = old.contents().keys().iter().collect::<HashSet<_>>();
+    let new_contents = new.contents().keys().iter().collect::<HashSet<_>>();
+
+    let mut added_contents = new_contents.difference(&old_contents)
+        .map(|&&hash| hash)
+        .collect::<Vec<_>>();
+    let mut removed_contents = old_contents.difference(&new_contents)
+        .map(|&&hash| hash)
+        .collect::<Vec<_>>();
+
+    added_contents.sort();
+    removed_contents.sort();
+
+    Ok(KFCDiff {
+        old_version: old.game_version().to_string(),
+        new_version: new.game_version().to_string(),
+
+        types,
+
+        added_contents,
+        removed_contents,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::*;
+
+    use super::*;
+
+    #[test]
+    fn test_diff_synthetic_archive() -> Result<(), Box<dyn std::error::Error>> {
+        let old = sample_archive(4);
+
+        let mut builder = TestArchiveBuilder::new(sample_type_registry());
+
+        builder.add_resource(sample_resource_id(0, SAMPLE_TYPE_A), sample_resource_data(0, 0));
+        builder.add_resource(sample_resource_id(1, SAMPLE_TYPE_B), sample_resource_data(1, 1));
+        builder.add_resource(sample_resource_id(200, SAMPLE_TYPE_A), sample_resource_data(200, 10));
+
+        let new = builder.build()?;
+
+        let diff = diff_files(old.reader()?.file(), new.reader()?.file(), new.type_registry());
+
+        assert_eq!(diff.added_resources().collect::<Vec<_>>(), [&sample_resource_id(200, SAMPLE_TYPE_A)]);
+        assert_eq!(diff.removed_resources().count(), 30);
+        assert_eq!(diff.changed_resources().collect::<Vec<_>>(), [&sample_resource_id(1, SAMPLE_TYPE_B)]);
+        assert_eq!(diff.removed_contents.len(), 8);
+
+        Ok(())
+    }
+
+}
@@ -0,0 +1,291 @@
+use std::{fs::File, io::{ErrorKind, Read}, path::{Path, PathBuf}, sync::Arc, time::SystemTime};
+
+use crc::{Crc, CRC_64_ECMA_182};
+
+use super::{header::ResourceChunkInfo, reader::shared_buffer, snapshot::version_dir_name};
+
+static CRC: Crc<u64> = Crc::<u64>::new(&CRC_64_ECMA_182);
+
+const CHUNK_EXTENSION: &str = "chunk";
+
+/// A persistent cache for decompressed resource chunks, which is kept across runs.
+///
+/// Chunks are stored as files in a directory per game version and index checksum,
+/// and are identified by their offset, compressed size and uncompressed size.
+/// The index checksum tells apart archives of the same game version, e.g. different snapshots.
+///
+/// The cache is best effort: chunks which can not be read or are damaged are treated as missing.
+#[derive(Debug, Clone)]
+pub struct DiskChunkCache {
+    root: PathBuf,
+    directory: PathBuf,
+}
+
+impl DiskChunkCache {
+
+    /// Creates a cache for the archive with the given game version and index checksum,
+    /// e.g. [`KFCSnapshot::index`](super::KFCSnapshot::index).
+    pub fn new(root: impl AsRef<Path>, game_version: &str, index_checksum: u64) -> Self {
+        let root = root.as_ref().to_path_buf();
+        let directory = root.join(format!("{}_{index_checksum:016x}", version_dir_name(game_version)));
+
+        Self {
+            root,
+            directory,
+        }
+    }
+
+    /// Returns the directory which holds the chunks of the archive.
+    #[inline]
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Returns the decompressed data of the chunk, if it is cached.
+    pub fn get(&self, chunk: &ResourceChunkInfo) -> Option<Arc<[u8]>> {
+        let path = self.chunk_path(chunk);
+        // the handle needs write access to update the modification time
+        let mut file = File::options()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .ok()?;
+
+        let Ok(Some(data)) = read_chunk_file(&mut file, chunk) else {
+            drop(file);
+            let _ = std::fs::remove_file(&path);
+            return None;
+        };
+
+        // the modification time marks recently used chunks for pruning
+        let _ = file.set_modified(SystemTime::now());
+
+        Some(data)
+    }
+
+    /// Stores the decompressed data of the chunk.
+    ///
+    /// The chunk is written to a temporary file first,
+    /// so concurrent runs never see a partially written chunk.
+    pub fn insert(&self, chunk: &ResourceChunkInfo, data: &[u8]) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.directory)?;
+
+        let path = self.chunk_path(chunk);
+        let temp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+
+        let mut contents = Vec::with_capacity(data.len() + 8);
+        contents.extend_from_slice(&CRC.checksum(data).to_le_bytes());
+        contents.extend_from_slice(data);
+
+        std::fs::write(&temp_path, contents)?;
+
+        if let Err(e) = std::fs::rename(&temp_path, &path) {
+            let _ = std::fs::remove_file(&temp_path);
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the size of all chunks of the archive in bytes.
+    pub fn size(&self) -> std::io::Result<u64> {
+        Ok(self.chunk_files()?.iter().map(|(_, size, _)| size).sum())
+    }
+
+    /// Removes the least recently used chunks of the archive
+    /// until at most `max_size` bytes are used.
+    /// Returns the number of bytes which were removed.
+    pub fn prune(&self, max_size: u64) -> std::io::Result<u64> {
+        let mut files = self.chunk_files()?;
+        let mut size = files.iter().map(|(_, size, _)| size).sum::<u64>();
+        let mut removed = 0;
+
+        files.sort_by_key(|(_, _, modified)| *modified);
+
+        for (path, file_size, _) in files {
+            if size <= max_size {
+                break;
+            }
+
+            std::fs::remove_file(path)?;
+            size -= file_size;
+            removed += file_size;
+        }
+
+        Ok(removed)
+    }
+
+    /// Removes the chunks of all other archives and game versions.
+    pub fn remove_others(&self) -> std::io::Result<()> {
+        let entries = match std::fs::read_dir(&self.root) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        for entry in entries {
+            let path = entry?.path();
+
+            if path.is_dir() && path != self.directory {
+                std::fs::remove_dir_all(path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Removes all chunks of the archive.
+    pub fn clear(&self) -> std::io::Result<()> {
+        match std::fs::remove_dir_all(&self.directory) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn chunk_path(&self, chunk: &ResourceChunkInfo) -> PathBuf {
+        self.directory.join(format!(
+            "{:016x}_{:08x}_{:08x}.{CHUNK_EXTENSION}",
+            chunk.offset,
+            chunk.compressed_size,
+            chunk.uncompressed_size,
+        ))
+    }
+
+    /// Returns the path, size and modification time of all chunk files.
+    fn chunk_files(&self) -> std::io::Result<Vec<(PathBuf, u64, SystemTime)>> {
+        let entries = match std::fs::read_dir(&self.directory) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut files = Vec::new();
+
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+
+            if path.extension().is_some_and(|ext| ext == CHUNK_EXTENSION) {
+                let metadata = entry.metadata()?;
+
+                files.push((path, metadata.len(), metadata.modified()?));
+            }
+        }
+
+        Ok(files)
+    }
+
+}
+
+/// Reads a chunk file, returning `None` if it does not match the chunk or its checksum.
+fn read_chunk_file(file: &mut File, chunk: &ResourceChunkInfo) -> std::io::Result<Option<Arc<[u8]>>> {
+    if file.metadata()?.len() != chunk.uncompressed_size + 8 {
+        return Ok(None);
+    }
+
+    let mut checksum = [0; 8];
+    file.read_exact(&mut checksum)?;
+
+    let mut data = shared_buffer(chunk.uncompressed_size as usize);
+    file.read_exact(Arc::get_mut(&mut data).expect("the buffer is not shared yet"))?;
+
+    if u64::from_le_bytes(checksum) != CRC.checksum(&data) {
+        return Ok(None);
+    }
+
+    Ok(Some(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{container::KFCReadError, testing::*};
+
+    use super::*;
+
+    #[test]
+    fn test_disk_chunk_cache() -> Result<(), Box<dyn std::error::Error>> {
+        let archive = sample_archive(1);
+        let kfc_reader = archive.reader()?;
+        let file = kfc_reader.file();
+        let cache_dir = archive.path().join(".cache");
+        let cache = DiskChunkCache::new(&cache_dir, file.game_version(), 1);
+
+        let ids = file.resources().keys().to_vec();
+        let read_all = |cache: &DiskChunkCache| {
+            let mut cursor = kfc_reader.new_cursor()?.with_disk_cache(cache.clone());
+
+            ids.iter()
+                .map(|id| Ok(cursor.read_resource(id)?.expect("resource exists")))
+                .collect::<Result<Vec<_>, KFCReadError>>()
+        };
+
+        let mut cursor = kfc_reader.new_cursor()?;
+        let expected = ids.iter()
+            .map(|id| cursor.read_resource(id).map(Option::unwrap))
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        // the first run fills the cache, the second one reads from it
+        assert_eq!(read_all(&cache)?, expected);
+
+        let chunk_count = std::fs::read_dir(cache.directory())?.count();
+        let uncompressed_size = file.resource_chunks().iter().map(|chunk| chunk.uncompressed_size).sum::<u64>();
+
+        assert_eq!(chunk_count, file.resource_chunks().len());
+        assert_eq!(cache.size()?, uncompressed_size + 8 * chunk_count as u64);
+        assert_eq!(read_all(&cache)?, expected);
+
+        // damaged chunks are decompressed again
+        for entry in std::fs::read_dir(cache.directory())? {
+            let path = entry?.path();
+            let mut data = std::fs::read(&path)?;
+
+            *data.last_mut().unwrap() ^= 0xFF;
+            std::fs::write(&path, data)?;
+        }
+
+        assert_eq!(read_all(&cache)?, expected);
+
+        let mut cursor = kfc_reader.new_cursor()?.with_disk_cache(cache.clone());
+        let mut batched = Vec::new();
+
+        cursor.read_resources(&ids, |_, data| {
+            batched.push(data.to_vec());
+            Ok::<_, std::io::Error>(())
+        })?;
+
+        assert_eq!(batched.len(), ids.len());
+
+        // chunks of other archives are kept apart
+        let other_version = DiskChunkCache::new(&cache_dir, "other version", 1);
+        let other_archive = DiskChunkCache::new(&cache_dir, file.game_version(), 2);
+
+        assert!(other_archive.get(&file.resource_chunks()[0]).is_none());
+
+        other_version.insert(&file.resource_chunks()[0], &[0; 16])?;
+        other_archive.insert(&file.resource_chunks()[0], &[0; 16])?;
+
+        cache.remove_others()?;
+
+        assert!(!other_version.directory().exists());
+        assert!(!other_archive.directory().exists());
+
+        // pruning keeps the most recently used chunks
+        let size = cache.size()?;
+        let newest = &file.resource_chunks()[0];
+
+        for chunk in file.resource_chunks() {
+            let path = cache.chunk_path(chunk);
+            File::options().write(true).open(path)?.set_modified(SystemTime::UNIX_EPOCH)?;
+        }
+
+        assert!(cache.get(newest).is_some());
+        assert_eq!(cache.prune(newest.uncompressed_size + 8)?, size - newest.uncompressed_size - 8);
+        assert!(cache.get(newest).is_some());
+
+        assert!(cache.prune(0)? > 0);
+        assert_eq!(cache.size()?, 0);
+
+        Ok(())
+    }
+
+}
@@ -4,9 +4,17 @@ use thiserror::Error;
 pub enum KFCWriteError {
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
+    #[error("Read error: {0}")]
+    Read(#[from] KFCReadError),
 
     #[error("Size too large: {0}")]
     SizeTooLarge(u64),
+    #[error("Resource chunk size {size} is not within {min}..={max} bytes")]
+    InvalidChunkSize {
+        size: u64,
+        min: u64,
+        max: u64,
+    },
 }
 
 #[derive(Debug, Error)]
@@ -18,6 +26,29 @@ pub enum KFCReadError {
 
     #[error("Invalid magic number: {0:X}")]
     InvalidMagic(u32),
+    #[error("Section `{section}` at offset {offset} with {count} entries exceeds the file size of {file_size} bytes")]
+    SectionOutOfBounds {
+        section: &'static str,
+        offset: u64,
+        count: usize,
+        file_size: u64,
+    },
+    #[error("Resource index {index} is out of bounds for {len} resources")]
+    ResourceIndexOutOfBounds {
+        index: usize,
+        len: usize,
+    },
+    #[error("Resource bundle {index}..{end} is out of bounds for {len} resource indices")]
+    ResourceBundleOutOfBounds {
+        index: usize,
+        end: usize,
+        len: usize,
+    },
+    #[error("Container index {index} is out of bounds for {len} containers")]
+    ContainerIndexOutOfBounds {
+        index: usize,
+        len: usize,
+    },
 }
 
 #[derive(Debug, Error)]
@@ -28,4 +59,17 @@ pub enum StaticMapError {
     BucketCountMismatch(usize, usize),
     #[error("Bucket size must be a power of 2: {0}")]
     InvalidBucketSize(usize),
+    #[error("Bucket {bucket} references keys {index}..{end}, but there are only {len} keys")]
+    BucketOutOfBounds {
+        bucket: usize,
+        index: usize,
+        end: usize,
+        len: usize,
+    },
+    #[error("Key {key} is stored in bucket {bucket}, but its hash selects bucket {expected}")]
+    MisplacedKey {
+        key: usize,
+        bucket: usize,
+        expected: usize,
+    },
 }
@@ -12,12 +12,20 @@ use crate::{
     reflection::{LookupKey, TypeRegistry},
 };
 
-use super::{KFCReadError, KFCWriteError, StaticMap, StaticMapBucket, header::*};
+use super::{KFCFileView, KFCReadError, KFCWriteError, StaticMap, StaticMapBucket, header::*};
+
+/// The value of [`KFCHeader::unk0`] in all known indices.
+const DEFAULT_HEADER_UNK0: u32 = 12;
 
 #[derive(Debug, Clone)]
 pub struct KFCFile {
     version: String,
 
+    /// The header fields of unknown purpose, which are written back as they are.
+    unk0: u32,
+    unused0: KFCLocation,
+    unused1: KFCLocation,
+
     containers: Vec<ContainerInfo>,
     resource_locations: Vec<ResourceLocation>,
     resource_chunks: Vec<ResourceChunkInfo>,
@@ -38,6 +46,10 @@ impl Default for KFCFile {
             version: String::default(),
             containers: Vec::default(),
 
+            unk0: DEFAULT_HEADER_UNK0,
+            unused0: KFCLocation::default(),
+            unused1: KFCLocation::default(),
+
             contents: StaticMap::default(),
             resources: StaticMap::default(),
 
@@ -70,17 +82,42 @@ impl KFCFile {
     ) -> Result<String, KFCReadError> {
         let file = File::open(path)?;
         let mut reader = BufReader::new(file);
-        let header = KFCHeader::read(&mut reader)?;
+        Self::read_version_tag(&mut reader)
+    }
+
+    pub fn read_version_tag<R: Read + Seek>(
+        reader: &mut R
+    ) -> Result<String, KFCReadError> {
+        let file_size = stream_len(reader)?;
+        let header = KFCHeader::read(reader)?;
+        header.version.check_bounds("version", 1, file_size)?;
         reader.seek(SeekFrom::Start(header.version.offset))?;
         let version = reader.read_string(header.version.count)?;
         Ok(version)
     }
 
+    /// Creates a lazily decoded view of an index,
+    /// which looks up entries directly in the given bytes, see [`KFCFileView`].
+    #[inline]
+    pub fn view(data: &[u8]) -> Result<KFCFileView<'_>, KFCReadError> {
+        KFCFileView::new(data)
+    }
+
     #[inline]
     pub fn resources(&self) -> &StaticMap<ResourceId, ResourceEntry> {
         &self.resources
     }
 
+    #[inline]
+    pub fn resource_locations(&self) -> &[ResourceLocation] {
+        &self.resource_locations
+    }
+
+    #[inline]
+    pub fn resource_indices(&self) -> &[u32] {
+        &self.resource_indices
+    }
+
     #[inline]
     pub fn resource_chunks(&self) -> &[ResourceChunkInfo] {
         &self.resource_chunks
@@ -176,6 +213,25 @@ impl KFCFile {
         self.version = version;
     }
 
+    /// Returns the `unk0` field and the unused locations of the header,
+    /// whose purpose is unknown.
+    #[inline]
+    pub fn unknown_header_fields(&self) -> (u32, &KFCLocation, &KFCLocation) {
+        (self.unk0, &self.unused0, &self.unused1)
+    }
+
+    /// Sets the header fields of unknown purpose, which are written as they are.
+    pub fn set_unknown_header_fields(
+        &mut self,
+        unk0: u32,
+        unused0: KFCLocation,
+        unused1: KFCLocation,
+    ) {
+        self.unk0 = unk0;
+        self.unused0 = unused0;
+        self.unused1 = unused1;
+    }
+
     fn rebuild_resource_bundles(&mut self, type_registry: &TypeRegistry) {
         let mut type_hashes = self
             .resources
@@ -226,8 +282,13 @@ impl KFCFile {
 impl KFCFile {
 
     fn read<R: Read + Seek>(reader: &mut R, skip_entries: bool) -> Result<Self, KFCReadError> {
+        let file_size = stream_len(reader)?;
         let header = KFCHeader::read(reader)?;
 
+        for (section, location, element_size) in header.sections() {
+            location.check_bounds(section, element_size, file_size)?;
+        }
+
         // version
         reader.seek(SeekFrom::Start(header.version.offset))?;
         let version = reader.read_string(header.version.count)?;
@@ -308,10 +369,14 @@ impl KFCFile {
                 .map(|_| ResourceChunkInfo::read(reader))
                 .collect::<Result<Vec<_>, _>>()?;
 
-            Ok(Self {
+            let file = Self {
                 version,
                 containers,
 
+                unk0: header.unk0,
+                unused0: header.unused0,
+                unused1: header.unused1,
+
                 resource_locations,
                 resource_indices,
 
@@ -320,11 +385,18 @@ impl KFCFile {
                 resource_bundles: StaticMap::from_parts(resource_bundle_keys, resource_bundle_values, resource_bundle_buckets)?,
 
                 resource_chunks,
-            })
+            };
+
+            file.validate_references()?;
+
+            Ok(file)
         } else {
             Ok(Self {
                 version,
                 containers: Vec::new(),
+                unk0: header.unk0,
+                unused0: header.unused0,
+                unused1: header.unused1,
                 resource_locations,
                 resource_indices: Vec::new(),
                 contents: StaticMap::default(),
@@ -335,6 +407,80 @@ impl KFCFile {
         }
     }
 
+    /// Assembles a file from its parts, which must reference each other correctly.
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn from_parts(
+        version: String,
+        containers: Vec<ContainerInfo>,
+        resource_locations: Vec<ResourceLocation>,
+        resource_chunks: Vec<ResourceChunkInfo>,
+        contents: StaticMap<ContentHash, ContentEntry>,
+        resources: StaticMap<ResourceId, ResourceEntry>,
+        resource_indices: Vec<u32>,
+        resource_bundles: StaticMap<Hash32, ResourceBundleEntry>,
+    ) -> Result<Self, KFCReadError> {
+        let file = Self {
+            version,
+
+            unk0: DEFAULT_HEADER_UNK0,
+            unused0: KFCLocation::default(),
+            unused1: KFCLocation::default(),
+
+            containers,
+            resource_locations,
+            resource_chunks,
+
+            contents,
+            resources,
+
+            resource_indices,
+            resource_bundles,
+        };
+
+        file.validate_references()?;
+
+        Ok(file)
+    }
+
+    /// Checks that all indices stored in the file point to existing entries,
+    /// so lookups can index into the entries without further checks.
+    ///
+    /// Offsets and sizes are stored as 32-bit values, so their sums can not overflow when parsed.
+    /// Files which are modified in memory are not validated, so lookups use checked arithmetic.
+    fn validate_references(&self) -> Result<(), KFCReadError> {
+        for &index in &self.resource_indices {
+            if index as usize >= self.resources.len() {
+                return Err(KFCReadError::ResourceIndexOutOfBounds {
+                    index: index as usize,
+                    len: self.resources.len(),
+                });
+            }
+        }
+
+        for bundle in self.resource_bundles.values() {
+            let end = bundle.index.saturating_add(bundle.count);
+
+            if end > self.resource_indices.len() {
+                return Err(KFCReadError::ResourceBundleOutOfBounds {
+                    index: bundle.index,
+                    end,
+                    len: self.resource_indices.len(),
+                });
+            }
+        }
+
+        for entry in self.contents.values() {
+            if entry.container_index >= self.containers.len() {
+                return Err(KFCReadError::ContainerIndexOutOfBounds {
+                    index: entry.container_index,
+                    len: self.containers.len(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
     pub(super) fn write<W: Write + Seek>(&self, writer: &mut W) -> Result<(), KFCWriteError> {
         KFCHeader::default().write(writer)?;
 
@@ -433,10 +579,11 @@ impl KFCFile {
 
         // KFCHeader
         let header = KFCHeader {
-            unused0: Default::default(),
-            unused1: Default::default(),
+            unused0: self.unused0.clone(),
+            unused1: self.unused1.clone(),
 
             size,
+            unk0: self.unk0,
 
             version: KFCLocation::new(version_offset, self.version.len()),
             containers: KFCLocation::new(containers_offset, self.containers.len()),
@@ -471,3 +618,77 @@ impl KFCFile {
     }
 
 }
+
+/// Returns the length of the stream without changing its position.
+fn stream_len<R: Seek>(reader: &mut R) -> std::io::Result<u64> {
+    let position = reader.stream_position()?;
+    let len = reader.seek(SeekFrom::End(0))?;
+
+    reader.seek(SeekFrom::Start(position))?;
+
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use crate::testing::*;
+
+    use super::*;
+
+    #[test]
+    fn test_corrupted_index() -> Result<(), Box<dyn std::error::Error>> {
+        let archive = sample_archive(1);
+        let data = std::fs::read(archive.kfc_path())?;
+
+        // truncated files are rejected, except for the trailing alignment
+
+        let header = KFCHeader::read(&mut Cursor::new(&data))?;
+        let sections_end = header.sections().iter()
+            .map(|(_, location, element_size)| location.offset + location.count as u64 * element_size)
+            .max()
+            .unwrap_or_default() as usize;
+
+        let mut original = Cursor::new(Vec::new());
+        KFCFile::from_reader(&mut Cursor::new(&data), false)?.write(&mut original)?;
+
+        for len in (0..data.len()).step_by(7) {
+            let truncated = &data[..len];
+
+            if len < sections_end {
+                assert!(KFCFile::from_reader(&mut Cursor::new(truncated), false).is_err(), "truncated at {len}");
+            } else {
+                let mut rewritten = Cursor::new(Vec::new());
+                KFCFile::from_reader(&mut Cursor::new(truncated), false)?.write(&mut rewritten)?;
+
+                assert!(rewritten.get_ref() == original.get_ref(), "truncated at {len}");
+            }
+
+            let _ = KFCFile::read_version_tag(&mut Cursor::new(truncated));
+        }
+
+        // counts are checked against the file size before anything is allocated
+
+        let mut corrupted = data.clone();
+        corrupted[100..104].copy_from_slice(&u32::MAX.to_le_bytes()); // resource_keys.count
+
+        assert!(matches!(
+            KFCFile::from_reader(&mut Cursor::new(&corrupted), false),
+            Err(KFCReadError::SectionOutOfBounds { section: "resource_keys", .. })
+        ));
+
+        // no corruption of the header may cause a panic
+
+        for i in 0..136 {
+            for mask in [0x01, 0x80, 0xFF] {
+                let mut corrupted = data.clone();
+                corrupted[i] ^= mask;
+
+                let _ = KFCFile::from_reader(&mut Cursor::new(&corrupted), false);
+            }
+        }
+
+        Ok(())
+    }
+
+}
@@ -1,8 +1,10 @@
 use std::io::{Read, Seek, Write};
 
+use serde::{Deserialize, Serialize};
+
 use crate::{io::{ReadExt, ReadSeekExt, WriteExt, WriteSeekExt}, Hash32};
 
-use super::{KFCReadError, KFCWriteError};
+use super::{KFCReadError, KFCWriteError, StaticElement};
 
 const KFC_DIR_MAGIC: u32 = 0x3343464B; // KFC3
 
@@ -38,10 +40,10 @@ const KFC_DIR_MAGIC: u32 = 0x3343464B; // KFC3
 ///     KFCLocation resource_chunks; // -> ResourceSectionInfo[count] (offset-4 has duplicate count)
 /// };
 /// ```
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct KFCHeader {
     pub size: u64,
-    // pub unk0: u32,
+    pub unk0: u32,
 
     pub version: KFCLocation,
     pub containers: KFCLocation,
@@ -77,7 +79,7 @@ impl KFCHeader {
         }
 
         let size = reader.read_u32()? as u64;
-        let _unk0 = reader.read_u32()?;
+        let unk0 = reader.read_u32()?;
 
         reader.padding(4)?;
 
@@ -106,6 +108,7 @@ impl KFCHeader {
 
         Ok(Self {
             size,
+            unk0,
 
             version,
             containers,
@@ -132,6 +135,26 @@ impl KFCHeader {
         })
     }
 
+    /// Returns the name, location and element size of every section which is read.
+    pub fn sections(&self) -> [(&'static str, &KFCLocation, u64); 14] {
+        [
+            ("version", &self.version, 1),
+            ("containers", &self.containers, 16),
+            ("resource_locations", &self.resource_locations, 12),
+            ("resource_indices", &self.resource_indices, 4),
+            ("content_buckets", &self.content_buckets, 8),
+            ("content_keys", &self.content_keys, 16),
+            ("content_values", &self.content_values, 16),
+            ("resource_buckets", &self.resource_buckets, 8),
+            ("resource_keys", &self.resource_keys, 32),
+            ("resource_values", &self.resource_values, 8),
+            ("resource_bundle_buckets", &self.resource_bundle_buckets, 8),
+            ("resource_bundle_keys", &self.resource_bundle_keys, 4),
+            ("resource_bundle_values", &self.resource_bundle_values, 12),
+            ("resource_chunks", &self.resource_chunks, 20),
+        ]
+    }
+
     pub fn write<W: Write + Seek>(&self, writer: &mut W) -> Result<(), KFCWriteError> {
         if self.size > u32::MAX as u64 {
             return Err(KFCWriteError::SizeTooLarge(self.size));
@@ -139,7 +162,7 @@ impl KFCHeader {
 
         writer.write_u32(KFC_DIR_MAGIC)?;
         writer.write_u32(self.size as u32)?;
-        writer.write_u32(12)?;
+        writer.write_u32(self.unk0)?;
         writer.padding(4)?;
 
         self.version.write(writer)?;
@@ -177,7 +200,7 @@ impl KFCHeader {
 ///     u32 count;
 /// };
 /// ```
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct KFCLocation {
     pub offset: u64,
     pub count: usize,
@@ -193,6 +216,33 @@ impl KFCLocation {
         }
     }
 
+    /// Returns the end of the section, or `None` if it overflows.
+    #[inline]
+    pub fn end(&self, element_size: u64) -> Option<u64> {
+        (self.count as u64)
+            .checked_mul(element_size)?
+            .checked_add(self.offset)
+    }
+
+    /// Checks that the section lies within the file,
+    /// so a corrupted count can not cause a huge allocation.
+    pub fn check_bounds(
+        &self,
+        section: &'static str,
+        element_size: u64,
+        file_size: u64,
+    ) -> Result<(), KFCReadError> {
+        match self.end(element_size) {
+            Some(end) if end <= file_size => Ok(()),
+            _ => Err(KFCReadError::SectionOutOfBounds {
+                section,
+                offset: self.offset,
+                count: self.count,
+                file_size,
+            }),
+        }
+    }
+
     #[inline]
     pub fn read<R: Read + Seek>(reader: &mut R) -> Result<Self, KFCReadError> {
         let offset = reader.read_u32_offset()?;
@@ -221,7 +271,7 @@ impl KFCLocation {
 ///     u64 count;
 /// };
 /// ```
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ContainerInfo {
     pub size: u64,
     pub count: usize,
@@ -250,6 +300,15 @@ impl ContainerInfo {
 
 }
 
+impl StaticElement for ContainerInfo {
+    const SIZE: usize = 16;
+
+    #[inline]
+    fn decode(bytes: &[u8]) -> Result<Self, KFCReadError> {
+        Self::read(&mut &bytes[..])
+    }
+}
+
 /// # Layout
 /// ```c
 /// struct ResourceLocation {
@@ -258,7 +317,7 @@ impl ContainerInfo {
 ///     u32 count;
 /// };
 /// ```
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ResourceLocation {
     pub uncompressed_size: u64,
     pub compressed_size: u64,
@@ -291,6 +350,15 @@ impl ResourceLocation {
 
 }
 
+impl StaticElement for ResourceLocation {
+    const SIZE: usize = 12;
+
+    #[inline]
+    fn decode(bytes: &[u8]) -> Result<Self, KFCReadError> {
+        Self::read(&mut &bytes[..])
+    }
+}
+
 /// # Layout
 /// ```c
 /// struct ContentEntry {
@@ -300,7 +368,7 @@ impl ResourceLocation {
 ///     u8 padding[8];
 /// };
 /// ```
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ContentEntry {
     pub offset: u64,
     pub flags: u16,
@@ -344,6 +412,15 @@ impl ContentEntry {
 
 }
 
+impl StaticElement for ContentEntry {
+    const SIZE: usize = 16;
+
+    #[inline]
+    fn decode(bytes: &[u8]) -> Result<Self, KFCReadError> {
+        Self::read(&mut &bytes[..])
+    }
+}
+
 /// # Layout
 /// ```c
 /// struct ResourceEntry {
@@ -351,7 +428,7 @@ impl ContentEntry {
 ///     u32 size;
 /// };
 /// ```
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ResourceEntry {
     pub offset: u64,
     pub size: u64,
@@ -388,6 +465,15 @@ impl ResourceEntry {
 
 }
 
+impl StaticElement for ResourceEntry {
+    const SIZE: usize = 8;
+
+    #[inline]
+    fn decode(bytes: &[u8]) -> Result<Self, KFCReadError> {
+        Self::read(&mut &bytes[..])
+    }
+}
+
 /// # Layout
 /// ```c
 /// struct ResourceBundleEntry {
@@ -396,7 +482,7 @@ impl ResourceEntry {
 ///     u32 count;
 /// };
 /// ```
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ResourceBundleEntry {
     pub internal_hash: Hash32,
     pub index: usize,
@@ -429,6 +515,15 @@ impl ResourceBundleEntry {
 
 }
 
+impl StaticElement for ResourceBundleEntry {
+    const SIZE: usize = 12;
+
+    #[inline]
+    fn decode(bytes: &[u8]) -> Result<Self, KFCReadError> {
+        Self::read(&mut &bytes[..])
+    }
+}
+
 /// # Layout
 /// ```c
 /// struct ResourceChunkInfo {
@@ -439,7 +534,7 @@ impl ResourceBundleEntry {
 ///     u32 uncompressed_size;
 /// };
 /// ```
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ResourceChunkInfo {
     pub offset: u64,
     pub size: u64,
@@ -479,3 +574,12 @@ impl ResourceChunkInfo {
     }
 
 }
+
+impl StaticElement for ResourceChunkInfo {
+    const SIZE: usize = 20;
+
+    #[inline]
+    fn decode(bytes: &[u8]) -> Result<Self, KFCReadError> {
+        Self::read(&mut &bytes[..])
+    }
+}
@@ -0,0 +1,456 @@
+use std::{borrow::Borrow, io::Take, path::Path};
+
+use thiserror::Error;
+
+use crate::{guid::{ContentHash, ResourceId}, reflection::TypeRegistry, Hash32};
+
+use super::{reader::StreamReader, KFCCursor, KFCFile, KFCReadError, KFCReader, KFCResourceReader, KFCStorage, KFCWriteError, KFCWriteOptions, KFCWriter};
+
+#[derive(Debug, Error)]
+pub enum KFCLayerError {
+    #[error("Overlay was created for game version {overlay}, but the base archive has version {base}")]
+    VersionMismatch {
+        base: String,
+        overlay: String,
+    },
+}
+
+/// A cursor which reads a base archive and any number of overlay archives as a single archive.
+///
+/// Overlays take precedence over the layers below them: a resource or content is read
+/// from the topmost layer whose index contains it. Overlays can only add or replace
+/// entries, they can not hide entries of the layers below them.
+pub struct KFCLayeredCursor<R> {
+    layers: Vec<KFCCursor<R>>,
+}
+
+impl<R> KFCLayeredCursor<R>
+where
+    R: Borrow<KFCReader>
+{
+
+    pub fn new(base: KFCCursor<R>) -> Self {
+        Self {
+            layers: vec![base],
+        }
+    }
+
+    /// Adds an overlay on top of all other layers.
+    /// The overlay must have been created for the game version of the base archive.
+    pub fn push_overlay(&mut self, overlay: KFCCursor<R>) -> Result<(), KFCLayerError> {
+        let base = self.base().file().game_version();
+        let version = overlay.file().game_version();
+
+        if base != version {
+            return Err(KFCLayerError::VersionMismatch {
+                base: base.to_string(),
+                overlay: version.to_string(),
+            });
+        }
+
+        self.layers.push(overlay);
+
+        Ok(())
+    }
+
+    /// Removes the topmost overlay. The base archive is never removed.
+    pub fn pop_overlay(&mut self) -> Option<KFCCursor<R>> {
+        if self.layers.len() > 1 {
+            self.layers.pop()
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    pub fn base(&self) -> &KFCCursor<R> {
+        &self.layers[0]
+    }
+
+    /// Returns all layers, starting with the base archive.
+    #[inline]
+    pub fn layers(&self) -> &[KFCCursor<R>] {
+        &self.layers
+    }
+
+    /// Returns the index of the layer the resource is read from.
+    pub fn resource_layer(&self, id: &ResourceId) -> Option<usize> {
+        self.layers.iter()
+            .rposition(|layer| layer.file().resources().contains_key(id))
+    }
+
+    /// Returns the index of the layer the content is read from.
+    pub fn content_layer(&self, hash: &ContentHash) -> Option<usize> {
+        self.layers.iter()
+            .rposition(|layer| layer.file().contents().contains_key(hash))
+    }
+
+    #[inline]
+    pub fn contains_resource(&self, id: &ResourceId) -> bool {
+        self.resource_layer(id).is_some()
+    }
+
+    #[inline]
+    pub fn contains_content(&self, hash: &ContentHash) -> bool {
+        self.content_layer(hash).is_some()
+    }
+
+    /// Returns the ids of all resources of all layers, each id only once.
+    pub fn resource_ids(&self) -> impl Iterator<Item = &ResourceId> {
+        self.layers.iter()
+            .enumerate()
+            .flat_map(move |(index, layer)| {
+                layer.file().resources().keys().iter()
+                    .filter(move |id| self.resource_layer(id) == Some(index))
+            })
+    }
+
+    pub fn resources_by_type(&self, type_hash: Hash32) -> impl Iterator<Item = &ResourceId> {
+        self.resource_ids()
+            .filter(move |id| id.type_hash() == type_hash)
+    }
+
+    /// Returns the hashes of all contents of all layers, each hash only once.
+    pub fn content_hashes(&self) -> impl Iterator<Item = &ContentHash> {
+        self.layers.iter()
+            .enumerate()
+            .flat_map(move |(index, layer)| {
+                layer.file().contents().keys().iter()
+                    .filter(move |hash| self.content_layer(hash) == Some(index))
+            })
+    }
+
+    /// Opens a streaming reader for the resource with the given id,
+    /// see [`KFCCursor::open_resource`].
+    pub fn open_resource(
+        &mut self,
+        id: &ResourceId,
+    ) -> std::io::Result<Option<KFCResourceReader<'_, R>>> {
+        match self.resource_layer(id) {
+            Some(index) => self.layers[index].open_resource(id),
+            None => Ok(None),
+        }
+    }
+
+    pub fn read_resource(
+        &mut self,
+        id: &ResourceId,
+    ) -> std::io::Result<Option<Vec<u8>>> {
+        match self.resource_layer(id) {
+            Some(index) => self.layers[index].read_resource(id),
+            None => Ok(None),
+        }
+    }
+
+    pub fn read_resource_into(
+        &mut self,
+        id: &ResourceId,
+        dst: &mut Vec<u8>,
+    ) -> std::io::Result<bool> {
+        match self.resource_layer(id) {
+            Some(index) => self.layers[index].read_resource_into(id, dst),
+            None => Ok(false),
+        }
+    }
+
+    /// Reads multiple resources and passes their data to the given callback,
+    /// see [`KFCCursor::read_resources`].
+    ///
+    /// The resources are grouped by their layer, so they are read layer by layer.
+    pub fn read_resources<'a, E>(
+        &mut self,
+        ids: impl IntoIterator<Item = &'a ResourceId>,
+        mut f: impl FnMut(&ResourceId, &[u8]) -> Result<(), E>,
+    ) -> Result<(), E>
+    where
+        E: From<std::io::Error>,
+    {
+        let mut groups = vec![Vec::new(); self.layers.len()];
+
+        for id in ids {
+            if let Some(index) = self.resource_layer(id) {
+                groups[index].push(id);
+            }
+        }
+
+        for (layer, ids) in self.layers.iter_mut().zip(groups) {
+            if !ids.is_empty() {
+                layer.read_resources(ids, &mut f)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn open_content(
+        &self,
+        hash: &ContentHash,
+    ) -> std::io::Result<Option<Take<StreamReader>>> {
+        match self.content_layer(hash) {
+            Some(index) => self.layers[index].open_content(hash),
+            None => Ok(None),
+        }
+    }
+
+    pub fn read_content(
+        &mut self,
+        hash: &ContentHash,
+    ) -> std::io::Result<Option<Vec<u8>>> {
+        match self.content_layer(hash) {
+            Some(index) => self.layers[index].read_content(hash),
+            None => Ok(None),
+        }
+    }
+
+    pub fn read_content_into(
+        &mut self,
+        hash: &ContentHash,
+        dst: &mut Vec<u8>,
+    ) -> std::io::Result<bool> {
+        match self.content_layer(hash) {
+            Some(index) => self.layers[index].read_content_into(hash, dst),
+            None => Ok(false),
+        }
+    }
+
+}
+
+/// Writes an overlay archive for a base archive.
+///
+/// An overlay is a regular archive, but it only holds the resources which were written to it
+/// and the contents which do not exist in the base archive yet.
+/// Read it together with its base archive with a [`KFCLayeredCursor`],
+/// or apply it to the base archive with [`apply_overlay`].
+///
+/// Overlays can only add or replace entries, there is no way to hide
+/// or remove the resources and contents of the base archive.
+pub struct KFCOverlayWriter<F, T> {
+    base: F,
+    writer: KFCWriter<KFCFile, T>,
+}
+
+impl<F, T> KFCOverlayWriter<F, T>
+where
+    F: Borrow<KFCFile>,
+    T: Borrow<TypeRegistry>,
+{
+
+    pub fn new(
+        path: impl AsRef<Path>,
+        file_name: impl AsRef<str>,
+        base: F,
+        type_registry: T,
+        options: KFCWriteOptions,
+    ) -> Result<Self, KFCWriteError> {
+        let writer = KFCWriter::new_with_options(
+            path,
+            file_name,
+            type_registry,
+            base.borrow().game_version(),
+            options,
+        )?;
+
+        Ok(Self {
+            base,
+            writer,
+        })
+    }
+
+    pub fn with_storage(
+        storage: impl KFCStorage + 'static,
+        base: F,
+        type_registry: T,
+        options: KFCWriteOptions,
+    ) -> Result<Self, KFCWriteError> {
+        let writer = KFCWriter::with_storage(
+            storage,
+            type_registry,
+            base.borrow().game_version(),
+            options,
+        )?;
+
+        Ok(Self {
+            base,
+            writer,
+        })
+    }
+
+    #[inline]
+    pub fn base(&self) -> &KFCFile {
+        self.base.borrow()
+    }
+
+    #[inline]
+    pub fn write_resource(
+        &mut self,
+        id: &ResourceId,
+        bytes: &[u8],
+    ) -> std::io::Result<()> {
+        self.writer.write_resource(id, bytes)
+    }
+
+    /// Writes a content, unless it already exists in the base archive.
+    #[inline]
+    pub fn write_content(
+        &mut self,
+        hash: &ContentHash,
+        data: &[u8],
+    ) -> std::io::Result<()> {
+        self.write_content_with_flags(hash, data, 0)
+    }
+
+    /// Writes a content with the given entry flags, unless it already exists in the base archive.
+    pub fn write_content_with_flags(
+        &mut self,
+        hash: &ContentHash,
+        data: &[u8],
+        flags: u16,
+    ) -> std::io::Result<()> {
+        if self.base.borrow().contents().contains_key(hash) {
+            return Ok(());
+        }
+
+        self.writer.write_content_with_flags(hash, data, flags)
+    }
+
+    #[inline]
+    pub fn finalize(self) -> Result<(), KFCWriteError> {
+        self.writer.finalize()
+    }
+
+}
+
+/// Writes all resources and contents of an overlay into the given writer,
+/// which usually is an incremental writer for the base archive of the overlay.
+///
+/// The flags of the content entries are kept. Since overlays can not hide entries,
+/// everything of the base archive stays in place unless it is replaced.
+pub fn apply_overlay<F, T>(
+    overlay: &KFCReader,
+    writer: &mut KFCWriter<F, T>,
+) -> Result<(), KFCReadError>
+where
+    F: Borrow<KFCFile>,
+    T: Borrow<TypeRegistry>,
+{
+    let file = overlay.file();
+    let mut cursor = overlay.new_cursor()?;
+
+    let mut resources = file.resources().iter().collect::<Vec<_>>();
+    resources.sort_by_key(|(_, entry)| entry.offset);
+
+    cursor.read_resources(resources.iter().map(|(id, _)| *id), |id, data| {
+        writer.write_resource(id, data).map_err(KFCReadError::from)
+    })?;
+
+    let mut buffer = Vec::new();
+
+    for (hash, entry) in file.contents().iter() {
+        buffer.clear();
+        cursor.read_content_into(hash, &mut buffer)?;
+        writer.write_content_with_flags(hash, &buffer, entry.flags)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{container::{KFCVerifyOptions, MemoryStorage}, hash::fnv, testing::*};
+
+    use super::*;
+
+    #[test]
+    fn test_layered_overlay() -> Result<(), Box<dyn std::error::Error>> {
+        let archive = sample_archive(1);
+        let base = archive.reader()?;
+
+        let storage = MemoryStorage::new();
+        let mut writer = KFCOverlayWriter::with_storage(
+            storage.clone(),
+            base.file(),
+            archive.type_registry(),
+            KFCWriteOptions::default(),
+        )?;
+
+        writer.write_resource(&sample_resource_id(2, SAMPLE_TYPE_A), &sample_resource_data(42, 10_000))?;
+        writer.write_resource(&sample_resource_id(100, SAMPLE_TYPE_A), &sample_resource_data(100, 500))?;
+        writer.write_content(&ContentHash::from_data(&sample_resource_data(3, 5003)), &sample_resource_data(3, 5003))?;
+        writer.write_content(&ContentHash::from_data(&sample_resource_data(42, 7000)), &sample_resource_data(42, 7000))?;
+        writer.write_content_with_flags(&ContentHash::from_data(&sample_resource_data(43, 6000)), &sample_resource_data(43, 6000), 3)?;
+        writer.finalize()?;
+
+        let overlay = KFCReader::from_storage(storage)?;
+
+        // contents of the base archive are not copied into the overlay
+        assert_eq!(overlay.file().resources().len(), 2);
+        assert_eq!(overlay.file().contents().len(), 2);
+
+        let mut cursor = KFCLayeredCursor::new(base.new_cursor()?);
+        cursor.push_overlay(overlay.new_cursor()?)?;
+
+        assert_eq!(cursor.read_resource(&sample_resource_id(2, SAMPLE_TYPE_A))?, Some(sample_resource_data(42, 10_000)));
+        assert_eq!(cursor.read_resource(&sample_resource_id(3, SAMPLE_TYPE_B))?, Some(sample_resource_data(3, 3 * 997)));
+        assert_eq!(cursor.read_resource(&sample_resource_id(100, SAMPLE_TYPE_A))?, Some(sample_resource_data(100, 500)));
+        assert_eq!(cursor.read_content(&ContentHash::from_data(&sample_resource_data(42, 7000)))?, Some(sample_resource_data(42, 7000)));
+        assert_eq!(cursor.read_content(&ContentHash::from_data(&sample_resource_data(3, 5003)))?, Some(sample_resource_data(3, 5003)));
+
+        assert_eq!(cursor.resource_layer(&sample_resource_id(2, SAMPLE_TYPE_A)), Some(1));
+        assert_eq!(cursor.resource_layer(&sample_resource_id(4, SAMPLE_TYPE_A)), Some(0));
+        assert_eq!(cursor.resource_ids().count(), 33);
+        assert_eq!(cursor.resources_by_type(fnv(SAMPLE_TYPE_A)).count(), 17);
+        assert_eq!(cursor.content_hashes().count(), 10);
+
+        let ids = cursor.resource_ids().copied().collect::<Vec<_>>();
+        let mut batched = std::collections::HashMap::new();
+
+        cursor.read_resources(&ids, |id, data| {
+            batched.insert(*id, data.to_vec());
+
+            Ok::<_, std::io::Error>(())
+        })?;
+
+        assert_eq!(batched.len(), 33);
+
+        for id in &ids {
+            assert_eq!(cursor.read_resource(id)?.as_ref(), batched.get(id));
+        }
+
+        // removing the overlay restores the base archive
+
+        cursor.pop_overlay();
+
+        assert!(cursor.pop_overlay().is_none());
+        assert_eq!(cursor.read_resource(&sample_resource_id(2, SAMPLE_TYPE_A))?, Some(sample_resource_data(2, 2 * 997)));
+        assert!(!cursor.contains_resource(&sample_resource_id(100, SAMPLE_TYPE_A)));
+
+        // overlays of other game versions are rejected
+
+        let storage = MemoryStorage::new();
+        KFCWriter::<KFCFile, _>::with_storage(storage.clone(), archive.type_registry(), "other", KFCWriteOptions::default())?
+            .finalize()?;
+        let other = KFCReader::from_storage(storage)?;
+
+        assert!(matches!(cursor.push_overlay(other.new_cursor()?), Err(KFCLayerError::VersionMismatch { .. })));
+
+        // applying the overlay writes its entries into the base archive
+
+        let reference = base.file().clone();
+        let mut writer = KFCWriter::new_incremental(archive.path(), archive.file_name(), &reference, archive.type_registry())?;
+
+        apply_overlay(&overlay, &mut writer)?;
+        writer.finalize()?;
+
+        let kfc_reader = archive.reader()?;
+        let mut cursor = kfc_reader.new_cursor()?;
+
+        assert_eq!(cursor.read_resource(&sample_resource_id(2, SAMPLE_TYPE_A))?, Some(sample_resource_data(42, 10_000)));
+        assert_eq!(cursor.read_resource(&sample_resource_id(100, SAMPLE_TYPE_A))?, Some(sample_resource_data(100, 500)));
+        assert_eq!(cursor.read_content(&ContentHash::from_data(&sample_resource_data(42, 7000)))?, Some(sample_resource_data(42, 7000)));
+        assert_eq!(kfc_reader.file().contents().get(&ContentHash::from_data(&sample_resource_data(43, 6000))).map(|entry| entry.flags), Some(3));
+        assert!(kfc_reader.verify(&KFCVerifyOptions::default())?.is_ok());
+
+        Ok(())
+    }
+
+}
@@ -0,0 +1,212 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{guid::{ContentHash, ResourceId}, Hash32};
+
+use super::{header::*, KFCFile, KFCReadError, KFCWriteError, StaticHash, StaticMap, StaticMapBucket, StaticMapError};
+
+#[derive(Debug, Error)]
+pub enum KFCManifestError {
+    #[error("Read error: {0}")]
+    Read(#[from] KFCReadError),
+    #[error("Write error: {0}")]
+    Write(#[from] KFCWriteError),
+    #[error("Invalid static map `{map}`: {error}")]
+    StaticMap {
+        map: &'static str,
+        error: StaticMapError,
+    },
+}
+
+/// A human-readable representation of an index (`.kfc` file).
+///
+/// Static maps keep their buckets and the exact order of their entries,
+/// so an index rebuilt from a manifest is byte-identical to the original one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KFCManifest {
+    /// The header as it was read.
+    /// Its size and section locations are recomputed when the index is rebuilt,
+    /// while `unk0` and the unused locations are written back as they are.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub header: Option<KFCHeader>,
+
+    pub version: String,
+    pub containers: Vec<ContainerInfo>,
+    pub resource_locations: Vec<ResourceLocation>,
+    pub resource_chunks: Vec<ResourceChunkInfo>,
+    pub resource_indices: Vec<u32>,
+
+    pub contents: KFCManifestMap<ContentHash, ContentEntry>,
+    pub resources: KFCManifestMap<ResourceId, ResourceEntry>,
+    pub resource_bundles: KFCManifestMap<Hash32, ResourceBundleEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KFCManifestMap<K, V> {
+    pub buckets: Vec<StaticMapBucket>,
+    pub entries: Vec<KFCManifestEntry<K, V>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KFCManifestEntry<K, V> {
+    pub key: K,
+    #[serde(flatten)]
+    pub value: V,
+}
+
+impl KFCManifest {
+
+    /// Reads an index including its header.
+    pub fn read<R: Read + Seek>(reader: &mut R) -> Result<Self, KFCReadError> {
+        let start = reader.stream_position()?;
+        let header = KFCHeader::read(reader)?;
+
+        reader.seek(SeekFrom::Start(start))?;
+
+        let file = KFCFile::from_reader(reader, false)?;
+
+        Ok(Self {
+            header: Some(header),
+            ..Self::from_file(&file)
+        })
+    }
+
+    pub fn from_file(file: &KFCFile) -> Self {
+        Self {
+            header: None,
+
+            version: file.game_version().to_string(),
+            containers: file.containers().to_vec(),
+            resource_locations: file.resource_locations().to_vec(),
+            resource_chunks: file.resource_chunks().to_vec(),
+            resource_indices: file.resource_indices().to_vec(),
+
+            contents: KFCManifestMap::from_static_map(file.contents()),
+            resources: KFCManifestMap::from_static_map(file.resources()),
+            resource_bundles: KFCManifestMap::from_static_map(file.resource_bundles()),
+        }
+    }
+
+    /// Assembles the index described by this manifest.
+    ///
+    /// All static maps and references between the sections are validated,
+    /// so a hand-edited manifest can not produce an index which fails to load.
+    pub fn to_file(&self) -> Result<KFCFile, KFCManifestError> {
+        let mut file = KFCFile::from_parts(
+            self.version.clone(),
+            self.containers.clone(),
+            self.resource_locations.clone(),
+            self.resource_chunks.clone(),
+            self.contents.to_static_map("contents")?,
+            self.resources.to_static_map("resources")?,
+            self.resource_indices.clone(),
+            self.resource_bundles.to_static_map("resource_bundles")?,
+        )?;
+
+        if let Some(header) = &self.header {
+            file.set_unknown_header_fields(header.unk0, header.unused0.clone(), header.unused1.clone());
+        }
+
+        Ok(file)
+    }
+
+    /// Writes the index described by this manifest.
+    pub fn write_index<W: Write + Seek>(&self, writer: &mut W) -> Result<(), KFCManifestError> {
+        self.to_file()?.write(writer)?;
+
+        Ok(())
+    }
+
+}
+
+impl<K, V> KFCManifestMap<K, V>
+where
+    K: PartialEq + StaticHash + Clone,
+    V: Clone,
+{
+
+    fn from_static_map(map: &StaticMap<K, V>) -> Self {
+        Self {
+            buckets: map.buckets().to_vec(),
+            entries: map.iter()
+                .map(|(key, value)| KFCManifestEntry {
+                    key: key.clone(),
+                    value: value.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    fn to_static_map(&self, map: &'static str) -> Result<StaticMap<K, V>, KFCManifestError> {
+        let (keys, values) = self.entries.iter()
+            .map(|entry| (entry.key.clone(), entry.value.clone()))
+            .unzip();
+
+        StaticMap::from_parts(keys, values, self.buckets.clone())
+            .map_err(|error| KFCManifestError::StaticMap { map, error })
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use crate::testing::*;
+
+    use super::*;
+
+    #[test]
+    fn test_manifest_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+        let archive = sample_archive(1);
+        let data = std::fs::read(archive.kfc_path())?;
+
+        let manifest = KFCManifest::read(&mut Cursor::new(&data))?;
+        let json = serde_json::to_string_pretty(&manifest)?;
+        let manifest = serde_json::from_str::<KFCManifest>(&json)?;
+
+        assert_eq!(manifest.resources.entries.len(), 32);
+        assert_eq!(manifest.header.as_ref().map(|header| header.unk0), Some(12));
+
+        let mut rebuilt = Cursor::new(Vec::new());
+        manifest.write_index(&mut rebuilt)?;
+
+        assert!(rebuilt.into_inner() == data, "rebuilt index differs from the original");
+
+        // damaged manifests are rejected
+
+        let mut damaged = manifest.clone();
+        damaged.resources.entries.pop();
+
+        assert!(matches!(damaged.to_file(), Err(KFCManifestError::StaticMap { map: "resources", .. })));
+
+        // the header fields of unknown purpose round-trip as well
+
+        let mut unknown = manifest.clone();
+
+        if let Some(header) = &mut unknown.header {
+            header.unk0 = 13;
+            header.unused1 = KFCLocation::new(header.version.offset, 3);
+        }
+
+        let mut rebuilt = Cursor::new(Vec::new());
+        unknown.write_index(&mut rebuilt)?;
+
+        let rebuilt = rebuilt.into_inner();
+        let reread = KFCManifest::read(&mut Cursor::new(&rebuilt))?;
+        let header = reread.header.as_ref().expect("read manifests have a header");
+
+        assert_eq!(header.unk0, 13);
+        assert_eq!((header.unused1.offset, header.unused1.count), (manifest.header.as_ref().unwrap().version.offset, 3));
+        assert_eq!(reread.to_file()?.unknown_header_fields().0, 13);
+
+        let mut rewritten = Cursor::new(Vec::new());
+        reread.write_index(&mut rewritten)?;
+
+        assert!(rewritten.into_inner() == rebuilt, "rebuilt index differs from the modified one");
+
+        Ok(())
+    }
+
+}
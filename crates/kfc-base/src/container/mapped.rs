@@ -0,0 +1,316 @@
+use std::{borrow::{Borrow, Cow}, fs::File, io::Read, ops::Deref, sync::Arc};
+
+use memmap2::Mmap;
+
+use crate::{container::KFCReadError, guid::{ContentHash, ResourceId}};
+
+use super::{reader::{decompress_chunk_data, read_resources_with, ResourceSpan}, ChunkCache, KFCFile, KFCReader, KFCStorage, KFCStream};
+
+/// A cursor which memory-maps the `.kfc_resources` and `.dat` files instead of
+/// reading them through buffered file handles.
+/// Streams of storages which are not backed by files are loaded into memory instead.
+///
+/// Contents and single-chunk resources are returned as slices borrowed from the
+/// mapping or the current chunk, so no copies are made unless a resource spans
+/// multiple chunks. Decompressed chunks are kept in a [`ChunkCache`] with a bounded
+/// memory budget.
+///
+/// # Safety considerations
+/// The mapped files must not be modified or truncated while the cursor is alive.
+/// Doing so is undefined behavior and may crash the process (e.g. with `SIGBUS`).
+/// Do not use this mode while a [`KFCWriter`](super::KFCWriter) targets the same files.
+pub struct KFCMappedCursor<R> {
+    kfc_reader: R,
+    archive: MappedArchive,
+
+    chunk_cache: ChunkCache,
+    /// The most recently decompressed chunk, which borrowed data is returned from.
+    current_chunk: Option<(usize, Arc<[u8]>)>,
+}
+
+/// The memory-mapped resource and container files of an archive.
+pub(super) struct MappedArchive {
+    resources: MappedStream,
+    containers: Vec<MappedStream>,
+}
+
+enum MappedStream {
+    Mapped(Mmap),
+    Loaded(Vec<u8>),
+}
+
+impl Deref for MappedStream {
+    type Target = [u8];
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Mapped(mmap) => mmap,
+            Self::Loaded(data) => data,
+        }
+    }
+}
+
+impl MappedArchive {
+
+    pub fn open(reader: &KFCReader) -> std::io::Result<Self> {
+        let storage = reader.storage();
+        let resources = map_stream(storage, KFCStream::Resources)?;
+        let containers = (0..reader.file().containers().len())
+            .map(|index| map_stream(storage, KFCStream::Container(index)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            resources,
+            containers,
+        })
+    }
+
+    pub fn compressed_chunk<'a>(
+        &'a self,
+        file: &KFCFile,
+        index: usize,
+    ) -> std::io::Result<Option<&'a [u8]>> {
+        let chunk = match file.resource_chunks().get(index) {
+            Some(chunk) => chunk,
+            None => return Ok(None),
+        };
+
+        let start = chunk.offset as usize;
+        let end = start.checked_add(chunk.compressed_size as usize)
+            .ok_or_else(|| out_of_bounds("resource chunk", index))?;
+
+        match self.resources.get(start..end) {
+            Some(data) => Ok(Some(data)),
+            None => Err(out_of_bounds("resource chunk", index)),
+        }
+    }
+
+    pub fn decompress_chunk(
+        &self,
+        file: &KFCFile,
+        index: usize,
+    ) -> std::io::Result<Arc<[u8]>> {
+        let compressed = self.compressed_chunk(file, index)?
+            .ok_or_else(|| out_of_bounds("resource chunk", index))?;
+
+        decompress_chunk_data(compressed, &file.resource_chunks()[index])
+    }
+
+    pub fn content<'a>(
+        &'a self,
+        file: &KFCFile,
+        hash: &ContentHash,
+    ) -> std::io::Result<Option<&'a [u8]>> {
+        let entry = match file.contents().get(hash) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        let container = self.containers.get(entry.container_index)
+            .ok_or_else(|| out_of_bounds("container", entry.container_index))?;
+
+        let start = entry.offset as usize;
+        let end = start.checked_add(hash.size() as usize)
+            .ok_or_else(|| out_of_bounds("container", entry.container_index))?;
+
+        match container.get(start..end) {
+            Some(data) => Ok(Some(data)),
+            None => Err(out_of_bounds("container", entry.container_index)),
+        }
+    }
+
+}
+
+impl<R> KFCMappedCursor<R>
+where
+    R: Borrow<KFCReader>
+{
+
+    pub(super) fn new(
+        kfc_reader: R,
+    ) -> Result<Self, KFCReadError> {
+        let archive = MappedArchive::open(kfc_reader.borrow())?;
+
+        Ok(Self {
+            kfc_reader,
+            archive,
+
+            chunk_cache: ChunkCache::default(),
+            current_chunk: None,
+        })
+    }
+
+    #[inline]
+    pub fn file(&self) -> &KFCFile {
+        self.kfc_reader.borrow().file()
+    }
+
+    /// Returns the compressed bytes of the resource chunk at the given index.
+    pub fn compressed_chunk(
+        &self,
+        index: usize,
+    ) -> std::io::Result<Option<&[u8]>> {
+        self.archive.compressed_chunk(self.file(), index)
+    }
+
+    /// Returns the decompressed bytes of the resource chunk at the given index.
+    ///
+    /// The chunk is decompressed directly from the mapping and kept in the chunk cache.
+    pub fn decompress_chunk(
+        &mut self,
+        index: usize,
+    ) -> std::io::Result<&[u8]> {
+        if !matches!(&self.current_chunk, Some((current, _)) if *current == index) {
+            let data = match self.chunk_cache.get(index) {
+                Some(data) => data,
+                None => {
+                    let data = self.archive.decompress_chunk(self.file(), index)?;
+
+                    self.chunk_cache.insert(index, data.clone());
+                    data
+                }
+            };
+
+            self.current_chunk = Some((index, data));
+        }
+
+        let (_, data) = self.current_chunk.as_ref().unwrap();
+
+        Ok(data)
+    }
+
+    /// Uses a chunk cache with the given memory budget in bytes.
+    #[inline]
+    pub fn with_cache_budget(mut self, budget: usize) -> Self {
+        self.chunk_cache = ChunkCache::new(budget);
+        self
+    }
+
+    #[inline]
+    pub fn chunk_cache(&self) -> &ChunkCache {
+        &self.chunk_cache
+    }
+
+    /// Reads the resource with the given id.
+    ///
+    /// If the resource is contained in a single chunk, the returned data is borrowed
+    /// from the current chunk. Otherwise, the parts are copied into an owned buffer.
+    pub fn read_resource(
+        &mut self,
+        id: &ResourceId,
+    ) -> std::io::Result<Option<Cow<'_, [u8]>>> {
+        let span = match ResourceSpan::locate(self.file(), id) {
+            Some(span) => span,
+            None => return Ok(None),
+        };
+
+        if span.is_single_chunk() {
+            let index = *span.chunks.start();
+            let chunk = self.file().resource_chunks()[index].clone();
+            let chunk_data = self.decompress_chunk(index)?;
+            let range = span.range_in_chunk(&chunk, chunk_data.len());
+
+            return Ok(Some(Cow::Borrowed(&chunk_data[range])));
+        }
+
+        let mut data = Vec::new();
+
+        self.read_span_into(&span, &mut data)?;
+
+        Ok(Some(Cow::Owned(data)))
+    }
+
+    pub fn read_resource_into(
+        &mut self,
+        id: &ResourceId,
+        dst: &mut Vec<u8>,
+    ) -> std::io::Result<bool> {
+        let span = match ResourceSpan::locate(self.file(), id) {
+            Some(span) => span,
+            None => return Ok(false),
+        };
+
+        self.read_span_into(&span, dst)?;
+
+        Ok(true)
+    }
+
+    /// Reads multiple resources and passes their data to the given callback,
+    /// see [`KFCCursor::read_resources`](super::KFCCursor::read_resources).
+    ///
+    /// Chunks are decompressed directly from the mapping and are not kept in the chunk cache.
+    pub fn read_resources<'a, E>(
+        &self,
+        ids: impl IntoIterator<Item = &'a ResourceId>,
+        f: impl FnMut(&ResourceId, &[u8]) -> Result<(), E>,
+    ) -> Result<(), E>
+    where
+        E: From<std::io::Error>,
+    {
+        read_resources_with(
+            self.file(),
+            ids,
+            |index| self.archive.decompress_chunk(self.file(), index),
+            f,
+        )
+    }
+
+    fn read_span_into(
+        &mut self,
+        span: &ResourceSpan,
+        dst: &mut Vec<u8>,
+    ) -> std::io::Result<()> {
+        dst.reserve_exact(span.size.saturating_sub(dst.len() as u64) as usize);
+
+        for i in span.chunks.clone() {
+            let chunk = self.file().resource_chunks()[i].clone();
+            let chunk_data = self.decompress_chunk(i)?;
+
+            dst.extend_from_slice(&chunk_data[span.range_in_chunk(&chunk, chunk_data.len())]);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the content with the given hash as a slice of the mapped container.
+    pub fn read_content(
+        &self,
+        hash: &ContentHash,
+    ) -> std::io::Result<Option<&[u8]>> {
+        self.archive.content(self.file(), hash)
+    }
+
+    /// Drops all cached decompressed chunks.
+    #[inline]
+    pub fn clear_cache(&mut self) {
+        self.chunk_cache.clear();
+        self.current_chunk = None;
+    }
+
+}
+
+fn map_stream(storage: &dyn KFCStorage, stream: KFCStream) -> std::io::Result<MappedStream> {
+    let path = match storage.path(stream) {
+        Some(path) => path,
+        None => {
+            let mut data = Vec::new();
+            storage.open_read(stream)?.read_to_end(&mut data)?;
+
+            return Ok(MappedStream::Loaded(data));
+        }
+    };
+
+    let file = File::open(path)?;
+
+    // SAFETY: the users of mapped cursors are responsible for not modifying
+    // the underlying files while they are mapped, see `KFCMappedCursor`.
+    Ok(MappedStream::Mapped(unsafe { Mmap::map(&file) }?))
+}
+
+fn out_of_bounds(kind: &str, index: usize) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::UnexpectedEof,
+        format!("{kind} {index} is out of bounds of the mapped file")
+    )
+}
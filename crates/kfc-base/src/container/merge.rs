@@ -0,0 +1,266 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{guid::ResourceId, reflection::TypeRegistry};
+
+use super::{KFCFile, KFCReadError, KFCReader, KFCStorage, KFCWriteError, KFCWriteOptions, KFCWriter};
+
+#[derive(Debug, Error)]
+pub enum KFCMergeError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Read error: {0}")]
+    Read(#[from] KFCReadError),
+    #[error("Write error: {0}")]
+    Write(#[from] KFCWriteError),
+    #[error("No archives to merge")]
+    NoArchives,
+    #[error("Archive {archive} was created for game version {actual}, but the first archive has version {expected}")]
+    VersionMismatch {
+        archive: usize,
+        expected: String,
+        actual: String,
+    },
+    #[error("Resource {resource} exists in archive {first} and archive {second}")]
+    Conflict {
+        resource: ResourceId,
+        first: usize,
+        second: usize,
+    },
+}
+
+/// Decides which archive a resource is taken from if it exists in multiple archives.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KFCMergeConflictPolicy {
+    /// The resource of the first archive is kept.
+    FirstWins,
+    /// The resource of the last archive is kept, so later archives override earlier ones.
+    #[default]
+    LastWins,
+    /// The merge fails with [`KFCMergeError::Conflict`].
+    Error,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct KFCMergeOptions {
+    pub conflict_policy: KFCMergeConflictPolicy,
+    pub write_options: KFCWriteOptions,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct KFCMergeReport {
+    /// The number of resources in the merged archive.
+    pub resources: usize,
+    /// The number of contents in the merged archive.
+    pub contents: usize,
+
+    /// The resources which exist in multiple archives.
+    pub conflicts: Vec<ResourceId>,
+    /// The number of contents which were skipped, because an earlier archive already had them.
+    pub duplicate_contents: usize,
+}
+
+/// Merges multiple archives into a new archive in the given storage.
+///
+/// Resources which exist in multiple archives are resolved with the conflict policy,
+/// contents are unique by their hash, so each content is only written once.
+/// The containers are assigned by the writer, so the merged archive always has a valid
+/// container layout regardless of the layouts of the input archives.
+///
+/// All archives must have the same game version. The storage must not be the storage
+/// of any of the readers, since they are read while writing.
+pub fn merge_archives(
+    readers: &[&KFCReader],
+    storage: impl KFCStorage + 'static,
+    type_registry: &TypeRegistry,
+    options: KFCMergeOptions,
+) -> Result<KFCMergeReport, KFCMergeError> {
+    let Some(first) = readers.first() else {
+        return Err(KFCMergeError::NoArchives);
+    };
+
+    let game_version = first.file().game_version();
+
+    for (index, reader) in readers.iter().enumerate() {
+        if reader.file().game_version() != game_version {
+            return Err(KFCMergeError::VersionMismatch {
+                archive: index,
+                expected: game_version.to_string(),
+                actual: reader.file().game_version().to_string(),
+            });
+        }
+    }
+
+    let (sources, conflicts) = resolve_resources(readers, options.conflict_policy)?;
+
+    let mut writer = KFCWriter::<KFCFile, _>::with_storage(
+        storage,
+        type_registry,
+        game_version,
+        options.write_options,
+    )?;
+
+    // resources
+
+    for (index, reader) in readers.iter().enumerate() {
+        let mut resources = reader.file().resources().iter()
+            .filter(|(id, _)| sources.get(id) == Some(&index))
+            .collect::<Vec<_>>();
+        resources.sort_by_key(|(_, entry)| entry.offset);
+
+        let mut cursor = reader.new_cursor()?;
+
+        cursor.read_resources(resources.iter().map(|(id, _)| *id), |id, data| {
+            writer.write_resource(id, data).map_err(KFCReadError::from)
+        })?;
+    }
+
+    // contents
+
+    let mut written_contents = HashSet::new();
+    let mut duplicate_contents = 0;
+    let mut buffer = Vec::new();
+
+    for reader in readers {
+        let mut contents = reader.file().contents().iter().collect::<Vec<_>>();
+        contents.sort_by_key(|(_, entry)| (entry.container_index, entry.offset));
+
+        let mut cursor = reader.new_cursor()?;
+
+        for (hash, entry) in contents {
+            if !written_contents.insert(*hash) {
+                duplicate_contents += 1;
+                continue;
+            }
+
+            buffer.clear();
+            cursor.read_content_into(hash, &mut buffer)?;
+            writer.write_content_with_flags(hash, &buffer, entry.flags)?;
+        }
+    }
+
+    writer.finalize()?;
+
+    Ok(KFCMergeReport {
+        resources: sources.len(),
+        contents: written_contents.len(),
+
+        conflicts,
+        duplicate_contents,
+    })
+}
+
+/// Returns the index of the archive each resource is taken from
+/// and the resources which exist in multiple archives.
+fn resolve_resources(
+    readers: &[&KFCReader],
+    policy: KFCMergeConflictPolicy,
+) -> Result<(HashMap<ResourceId, usize>, Vec<ResourceId>), KFCMergeError> {
+    let mut sources = HashMap::<ResourceId, usize>::new();
+    let mut conflicts = Vec::new();
+
+    for (index, reader) in readers.iter().enumerate() {
+        for id in reader.file().resources().keys() {
+            let Some(&previous) = sources.get(id) else {
+                sources.insert(*id, index);
+                continue;
+            };
+
+            match policy {
+                KFCMergeConflictPolicy::FirstWins => {},
+                KFCMergeConflictPolicy::LastWins => {
+                    sources.insert(*id, index);
+                },
+                KFCMergeConflictPolicy::Error => {
+                    return Err(KFCMergeError::Conflict {
+                        resource: *id,
+                        first: previous,
+                        second: index,
+                    });
+                },
+            }
+
+            conflicts.push(*id);
+        }
+    }
+
+    conflicts.sort();
+    conflicts.dedup();
+
+    Ok((sources, conflicts))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{container::{KFCVerifyOptions, MemoryStorage}, guid::ContentHash, hash::fnv, testing::*};
+
+    use super::*;
+
+    #[test]
+    fn test_merge_archives() -> Result<(), Box<dyn std::error::Error>> {
+        let base = sample_archive(1);
+
+        let mut builder = TestArchiveBuilder::new(sample_type_registry());
+
+        builder.add_resource(sample_resource_id(2, SAMPLE_TYPE_A), sample_resource_data(42, 100));
+        builder.add_resource(sample_resource_id(100, SAMPLE_TYPE_B), sample_resource_data(100, 200));
+        builder.add_content(sample_resource_data(0, 5000));
+        builder.add_content(sample_resource_data(100, 300));
+
+        let extra = builder.build()?;
+
+        let base_reader = base.reader()?;
+        let extra_reader = extra.reader()?;
+        let readers = [&base_reader, &extra_reader];
+
+        let merge = |conflict_policy| {
+            let storage = MemoryStorage::new();
+            let report = merge_archives(&readers, storage.clone(), base.type_registry(), KFCMergeOptions {
+                conflict_policy,
+                ..Default::default()
+            })?;
+
+            Ok::<_, KFCMergeError>((KFCReader::from_storage(storage)?, report))
+        };
+
+        // last wins
+
+        let (merged, report) = merge(KFCMergeConflictPolicy::LastWins)?;
+        let mut cursor = merged.new_cursor()?;
+
+        assert_eq!(report.resources, 33);
+        assert_eq!(report.contents, 9);
+        assert_eq!(report.conflicts, [sample_resource_id(2, SAMPLE_TYPE_A)]);
+        assert_eq!(report.duplicate_contents, 1);
+
+        assert_eq!(cursor.read_resource(&sample_resource_id(2, SAMPLE_TYPE_A))?, Some(sample_resource_data(42, 100)));
+        assert_eq!(cursor.read_resource(&sample_resource_id(100, SAMPLE_TYPE_B))?, Some(sample_resource_data(100, 200)));
+        assert_eq!(cursor.read_resource(&sample_resource_id(3, SAMPLE_TYPE_B))?, Some(sample_resource_data(3, 3 * 997)));
+        assert_eq!(merged.file().resources_by_type(fnv(SAMPLE_TYPE_B)).count(), 17);
+
+        let content = ContentHash::from_data(&sample_resource_data(100, 300));
+
+        assert_eq!(cursor.read_content(&content)?, Some(sample_resource_data(100, 300)));
+        assert!(merged.file().containers().len().is_power_of_two());
+        assert!(merged.verify(&KFCVerifyOptions::default())?.is_ok());
+
+        // first wins
+
+        let (merged, _) = merge(KFCMergeConflictPolicy::FirstWins)?;
+
+        assert_eq!(merged.new_cursor()?.read_resource(&sample_resource_id(2, SAMPLE_TYPE_A))?, Some(sample_resource_data(2, 2 * 997)));
+
+        // error
+
+        assert!(matches!(
+            merge(KFCMergeConflictPolicy::Error),
+            Err(KFCMergeError::Conflict { first: 0, second: 1, .. })
+        ));
+
+        Ok(())
+    }
+
+}
@@ -1,12 +1,40 @@
 mod file;
+mod view;
 mod header;
 mod static_map;
 mod error;
 mod reader;
+mod mapped;
+mod shared;
+mod layered;
+mod cache;
+mod disk_cache;
+mod storage;
 mod writer;
+mod verify;
+mod diff;
+mod compact;
+mod analyze;
+mod merge;
+mod manifest;
+mod snapshot;
 
 pub use file::*;
+pub use view::*;
 pub use static_map::*;
 pub use error::*;
 pub use reader::*;
+pub use mapped::*;
+pub use shared::*;
+pub use layered::*;
+pub use cache::*;
+pub use disk_cache::*;
+pub use storage::*;
 pub use writer::*;
+pub use verify::*;
+pub use diff::*;
+pub use compact::*;
+pub use analyze::*;
+pub use merge::*;
+pub use manifest::*;
+pub use snapshot::*;
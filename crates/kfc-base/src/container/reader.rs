@@ -1,17 +1,12 @@
-use std::{borrow::Borrow, collections::HashMap, fs::File, io::{BufReader, Read, Seek, SeekFrom, Take}, path::{Path, PathBuf}};
+use std::{borrow::Borrow, io::{BufReader, ErrorKind, Read, Seek, SeekFrom, Take}, ops::{Deref, Range, RangeInclusive}, path::Path, sync::Arc};
 
-use crate::{container::KFCReadError, guid::{ContentHash, ResourceId}};
+use crate::{container::KFCReadError, guid::{ContentHash, ResourceId}, io::ReadExt};
 
-use super::KFCFile;
+use super::{header::ResourceChunkInfo, verify_archive, ChunkCache, DiskChunkCache, DiskStorage, KFCFile, KFCMappedCursor, KFCReadStream, KFCSharedCursor, KFCStorage, KFCStream, KFCVerifyOptions, KFCVerifyReport};
 
 pub struct KFCReader {
     file: KFCFile,
-
-    path: PathBuf,
-    file_name: String,
-    kfc_path: PathBuf,
-    dat_extension: String,
-    resource_path: PathBuf,
+    storage: Arc<dyn KFCStorage>,
 }
 
 #[derive(Debug)]
@@ -49,46 +44,38 @@ impl KFCReader {
         file_name: impl AsRef<str>,
         options: KFCReaderOptions,
     ) -> Result<Self, KFCReadError> {
-        let path = path.as_ref();
-        let file_name = file_name.as_ref();
+        let storage = DiskStorage::new(path, file_name)
+            .with_extensions(
+                options.kfc_extension,
+                options.resource_extension,
+                options.dat_extension,
+            );
 
-        let kfc_path = path.join(format!(
-            "{}.{}",
-            file_name,
-            options.kfc_extension
-        ));
-        let file = KFCFile::from_path(&kfc_path, false)?;
+        Self::from_storage(storage)
+    }
 
-        let resource_path = path.join(format!(
-            "{}.{}",
-            file_name,
-            options.resource_extension
-        ));
+    /// Reads the archive from the given storage.
+    pub fn from_storage(
+        storage: impl KFCStorage + 'static,
+    ) -> Result<Self, KFCReadError> {
+        let storage: Arc<dyn KFCStorage> = Arc::new(storage);
+        let mut reader = BufReader::new(storage.open_read(KFCStream::Index)?);
+        let file = KFCFile::from_reader(&mut reader, false)?;
 
         Ok(Self {
             file,
-
-            path: path.to_path_buf(),
-            file_name: file_name.to_string(),
-            kfc_path,
-            dat_extension: options.dat_extension.to_string(),
-            resource_path,
+            storage,
         })
     }
 
-    #[inline]
-    pub fn path(&self) -> &Path {
-        &self.path
-    }
-
     #[inline]
     pub fn file(&self) -> &KFCFile {
         self.file.borrow()
     }
 
     #[inline]
-    pub fn kfc_path(&self) -> &Path {
-        &self.kfc_path
+    pub fn storage(&self) -> &Arc<dyn KFCStorage> {
+        &self.storage
     }
 
     #[inline]
@@ -101,14 +88,185 @@ impl KFCReader {
         KFCCursor::new(self)
     }
 
+    /// Creates a cursor which memory-maps the resource and container files.
+    ///
+    /// See [`KFCMappedCursor`] for the safety considerations of this mode.
+    #[inline]
+    pub fn new_mapped_cursor(&self) -> Result<KFCMappedCursor<&Self>, KFCReadError> {
+        KFCMappedCursor::new(self)
+    }
+
+    /// Creates a cursor which memory-maps the resource and container files.
+    ///
+    /// See [`KFCMappedCursor`] for the safety considerations of this mode.
+    #[inline]
+    pub fn into_mapped_cursor(self) -> Result<KFCMappedCursor<Self>, KFCReadError> {
+        KFCMappedCursor::new(self)
+    }
+
+    /// Creates a cursor which can be shared between threads.
+    ///
+    /// Decompressed chunks are cached until `cache_budget` bytes are used,
+    /// see [`DEFAULT_CHUNK_CACHE_BUDGET`](super::DEFAULT_CHUNK_CACHE_BUDGET) for a sensible default.
+    #[inline]
+    pub fn new_shared_cursor(&self, cache_budget: usize) -> Result<KFCSharedCursor<&Self>, KFCReadError> {
+        KFCSharedCursor::new(self, cache_budget)
+    }
+
+    /// Creates a cursor which can be shared between threads.
+    ///
+    /// Decompressed chunks are cached until `cache_budget` bytes are used,
+    /// see [`DEFAULT_CHUNK_CACHE_BUDGET`](super::DEFAULT_CHUNK_CACHE_BUDGET) for a sensible default.
+    #[inline]
+    pub fn into_shared_cursor(self, cache_budget: usize) -> Result<KFCSharedCursor<Self>, KFCReadError> {
+        KFCSharedCursor::new(self, cache_budget)
+    }
+
+    /// Checks the archive for consistency, see [`verify_archive`].
+    #[inline]
+    pub fn verify(&self, options: &KFCVerifyOptions) -> Result<KFCVerifyReport, KFCReadError> {
+        verify_archive(self, options)
+    }
+
+}
+
+/// The location of a resource within the uncompressed resource stream.
+#[derive(Debug, Clone)]
+pub(super) struct ResourceSpan {
+    pub offset: u64,
+    pub size: u64,
+    pub chunks: RangeInclusive<usize>,
+}
+
+impl ResourceSpan {
+
+    pub fn locate(
+        file: &KFCFile,
+        id: &ResourceId,
+    ) -> Option<Self> {
+        let resource = file.resources().get(id)?;
+
+        // TEMPORARY: until the game devs fix this resource size issue
+        const RID: ResourceId = ResourceId::parse_qualified("509feadb-4c60-425f-9c7c-deeefd9b6920_21b2a090_3").unwrap();
+
+        let resource_size = if id == &RID && resource.size < 0x1000000 {
+            resource.size + 0x1000000
+        } else {
+            resource.size
+        };
+        // END TEMPORARY
+
+        let chunks = file.resource_chunks();
+        let chunk_start = chunk_index_at(chunks, resource.offset)?;
+
+        // the last chunk is the one containing the last byte of the resource
+        let chunk_end = match resource_size {
+            0 => chunk_start,
+            size => chunk_index_at(chunks, resource.offset.checked_add(size - 1)?)?,
+        };
+
+        Some(Self {
+            offset: resource.offset,
+            size: resource_size,
+            chunks: chunk_start..=chunk_end,
+        })
+    }
+
+    /// Returns the range of the decompressed chunk data which belongs to this resource.
+    pub fn range_in_chunk(
+        &self,
+        chunk: &ResourceChunkInfo,
+        chunk_len: usize,
+    ) -> Range<usize> {
+        let base_offset = chunk.uncompressed_offset;
+        let start = self.offset.saturating_sub(base_offset);
+        let end = self.offset.saturating_add(self.size).saturating_sub(base_offset).min(chunk_len as u64);
+
+        // chunks which decompress to less data than announced must not cause a panic
+        start.min(end) as usize..end as usize
+    }
+
+    #[inline]
+    pub fn is_single_chunk(&self) -> bool {
+        self.chunks.start() == self.chunks.end()
+    }
+
+}
+
+/// Returns the index of the chunk containing the given uncompressed offset.
+///
+/// Chunks are sorted by their uncompressed offset, so this is a binary search.
+pub(super) fn chunk_index_at(
+    chunks: &[ResourceChunkInfo],
+    offset: u64,
+) -> Option<usize> {
+    // the end of a chunk may not be representable in files which were not validated
+    let index = chunks.partition_point(|chunk| chunk.uncompressed_offset.saturating_add(chunk.uncompressed_size) <= offset);
+    let chunk = chunks.get(index)?;
+
+    offset.checked_sub(chunk.uncompressed_offset)
+        .is_some_and(|relative| relative < chunk.uncompressed_size)
+        .then_some(index)
+}
+
+/// Reads the given resources ordered by their offset,
+/// so each chunk is decompressed at most once.
+///
+/// Unknown resources are skipped.
+pub(super) fn read_resources_with<'a, C, E>(
+    file: &KFCFile,
+    ids: impl IntoIterator<Item = &'a ResourceId>,
+    mut decompress_chunk: impl FnMut(usize) -> std::io::Result<C>,
+    mut f: impl FnMut(&ResourceId, &[u8]) -> Result<(), E>,
+) -> Result<(), E>
+where
+    C: Deref<Target = [u8]>,
+    E: From<std::io::Error>,
+{
+    let mut spans = ids.into_iter()
+        .filter_map(|id| Some((id, ResourceSpan::locate(file, id)?)))
+        .collect::<Vec<_>>();
+
+    spans.sort_by_key(|(_, span)| span.offset);
+
+    let mut current_chunk: Option<(usize, C)> = None;
+    let mut buffer = Vec::new();
+
+    for (id, span) in spans {
+        buffer.clear();
+
+        for index in span.chunks.clone() {
+            if !matches!(&current_chunk, Some((current, _)) if *current == index) {
+                current_chunk = Some((index, decompress_chunk(index)?));
+            }
+
+            let (_, data) = current_chunk.as_ref().unwrap();
+            let range = span.range_in_chunk(&file.resource_chunks()[index], data.len());
+
+            if span.is_single_chunk() {
+                f(id, &data[range])?;
+            } else {
+                buffer.extend_from_slice(&data[range]);
+            }
+        }
+
+        if !span.is_single_chunk() {
+            f(id, &buffer)?;
+        }
+    }
+
+    Ok(())
 }
 
+pub(super) type StreamReader = BufReader<Box<dyn KFCReadStream>>;
+
 pub struct KFCCursor<R> {
     kfc_reader: R,
-    reader: BufReader<File>,
-    container_readers: Vec<Option<BufReader<File>>>,
+    reader: StreamReader,
+    container_readers: Vec<Option<StreamReader>>,
 
-    chunk_cache: HashMap<usize, Vec<u8>>,
+    chunk_cache: ChunkCache,
+    disk_cache: Option<DiskChunkCache>,
     buffer: Vec<u8>,
 }
 
@@ -121,44 +279,57 @@ where
         kfc_reader: R,
     ) -> Result<Self, KFCReadError> {
         let reader = kfc_reader.borrow();
-        let reader = BufReader::new(File::open(&reader.resource_path)?);
+        let reader = BufReader::new(reader.storage.open_read(KFCStream::Resources)?);
 
         Ok(Self {
             kfc_reader,
             reader,
             container_readers: Vec::new(),
 
-            chunk_cache: HashMap::new(),
+            chunk_cache: ChunkCache::default(),
+            disk_cache: None,
             buffer: Vec::new(),
         })
     }
 
+    /// Keeps decompressed chunks in the given persistent cache,
+    /// so later runs can skip the decompression.
+    #[inline]
+    pub fn with_disk_cache(mut self, disk_cache: DiskChunkCache) -> Self {
+        self.disk_cache = Some(disk_cache);
+        self
+    }
+
+    #[inline]
+    pub fn disk_cache(&self) -> Option<&DiskChunkCache> {
+        self.disk_cache.as_ref()
+    }
+
     #[inline]
     pub fn file(&self) -> &KFCFile {
         self.kfc_reader.borrow().file()
     }
 
-    // pub fn open_resource(
-    //     &self,
-    //     id: &ResourceId,
-    // ) -> std::io::Result<Option<Take<BufReader<File>>>> {
-    //     let kfc_reader = self.kfc_reader.borrow();
-    //     let file = &kfc_reader.file;
-    //     let resource = match file.resources().get(id) {
-    //         Some(resource) => resource,
-    //         None => return Ok(None),
-    //     };
-    //
-    //     let offset = file.data_offset() + resource.offset;
-    //     let size = resource.size;
-    //
-    //     let path = &kfc_reader.kfc_path;
-    //     let mut reader = BufReader::new(File::open(path)?);
-    //
-    //     reader.seek(SeekFrom::Start(offset))?;
-    //
-    //     Ok(Some(reader.take(size)))
-    // }
+    /// Opens a streaming reader for the resource with the given id.
+    ///
+    /// Unlike [`read_resource`](Self::read_resource), the resource is never loaded
+    /// as a whole. Chunks are decompressed on demand while reading.
+    pub fn open_resource(
+        &mut self,
+        id: &ResourceId,
+    ) -> std::io::Result<Option<KFCResourceReader<'_, R>>> {
+        let span = match ResourceSpan::locate(self.file(), id) {
+            Some(span) => span,
+            None => return Ok(None),
+        };
+
+        Ok(Some(KFCResourceReader {
+            cursor: self,
+            span,
+            position: 0,
+            current_chunk: None,
+        }))
+    }
 
     pub fn read_resource(
         &mut self,
@@ -178,122 +349,71 @@ where
         guid: &ResourceId,
         dst: &mut Vec<u8>
     ) -> std::io::Result<bool> {
-        let kfc_reader = self.kfc_reader.borrow();
-        let file = &kfc_reader.file;
-        let resource = match file.resources().get(guid) {
-            Some(resource) => resource,
+        let span = match ResourceSpan::locate(self.file(), guid) {
+            Some(span) => span,
             None => return Ok(false),
         };
 
-        // TEMPORARY: until the game devs fix this resource size issue
-        const RID: ResourceId = ResourceId::parse_qualified("509feadb-4c60-425f-9c7c-deeefd9b6920_21b2a090_3").unwrap();
-
-        let resource_size = if guid == &RID && resource.size < 0x1000000 {
-            resource.size + 0x1000000
-        } else {
-            resource.size
-        };
-        // END TEMPORARY
-
-        let chunk_start = file.resource_chunks()
-            .iter()
-            .position(|chunk| {
-                (chunk.uncompressed_offset..chunk.uncompressed_offset + chunk.uncompressed_size)
-                    .contains(&resource.offset)
-            });
-
-        let chunk_start = match chunk_start {
-            Some(chunk) => chunk,
-            None => return Ok(false),
-        };
-
-        let chunk_end = file.resource_chunks()
-            .iter()
-            .rposition(|chunk| {
-                (chunk.uncompressed_offset..=chunk.uncompressed_offset + chunk.uncompressed_size)
-                    .contains(&(resource.offset + resource_size))
-            });
-
-        let chunk_end = match chunk_end {
-            Some(chunk) => chunk,
-            None => return Ok(false),
-        };
-
-        dst.reserve_exact(resource_size.saturating_sub(dst.len() as u64) as usize);
-
-        let mut remaining_size = resource_size;
-        let resource_offset = resource.offset;
-
-        for i in chunk_start..=chunk_end {
-            let base_offset = self.kfc_reader.borrow()
-                .file()
-                .resource_chunks()[i]
-                .uncompressed_offset;
+        dst.reserve_exact(span.size.saturating_sub(dst.len() as u64) as usize);
 
+        for i in span.chunks.clone() {
+            let chunk = self.file().resource_chunks()[i].clone();
             let chunk_data = self.decompress_chunk(i)?;
 
-            let size = if resource_offset > base_offset {
-                let available = chunk_data.len() as u64 - (resource_offset - base_offset);
-                std::cmp::min(available, remaining_size)
-            } else {
-                let available = chunk_data.len() as u64;
-                std::cmp::min(available, remaining_size)
-            };
-
-            let offset = if resource_offset > base_offset {
-                (resource_offset - base_offset) as usize
-            } else {
-                0
-            };
-
-            dst.extend_from_slice(&chunk_data[offset .. offset + size as usize]);
-            remaining_size -= size;
+            dst.extend_from_slice(&chunk_data[span.range_in_chunk(&chunk, chunk_data.len())]);
         }
 
         Ok(true)
     }
 
-    fn decompress_chunk(
+    /// Reads multiple resources and passes their data to the given callback.
+    ///
+    /// The resources are read in the order of their data instead of the given order,
+    /// so each chunk is decompressed only once. Unknown resources are skipped.
+    pub fn read_resources<'a, E>(
         &mut self,
-        index: usize
-    ) -> std::io::Result<&[u8]> {
-        self.decompress_chunk_impl(index)?;
-        Ok(self.chunk_cache.get(&index).unwrap())
+        ids: impl IntoIterator<Item = &'a ResourceId>,
+        f: impl FnMut(&ResourceId, &[u8]) -> Result<(), E>,
+    ) -> Result<(), E>
+    where
+        E: From<std::io::Error>,
+    {
+        let kfc_reader = self.kfc_reader.borrow();
+
+        read_resources_with(
+            &kfc_reader.file,
+            ids,
+            |index| decompress_chunk_with(&mut self.reader, &mut self.buffer, self.disk_cache.as_ref(), &kfc_reader.file, index),
+            f,
+        )
     }
 
-    fn decompress_chunk_impl(
+    fn decompress_chunk(
         &mut self,
         index: usize
-    ) -> std::io::Result<()> {
-        match self.chunk_cache.get(&index) {
-            Some(_) => Ok(()),
-            None => {
-                let kfc_reader = self.kfc_reader.borrow();
-                let chunk = &kfc_reader.file.resource_chunks()[index];
-
-                self.buffer.clear();
-                self.buffer.resize(chunk.compressed_size as usize, 0);
-
-                self.reader.seek(SeekFrom::Start(chunk.offset))?;
-                self.reader.read_exact(&mut self.buffer[..chunk.compressed_size as usize])?;
+    ) -> std::io::Result<Arc<[u8]>> {
+        if let Some(data) = self.chunk_cache.get(index) {
+            return Ok(data);
+        }
 
-                let mut decompressed_data = Vec::with_capacity(chunk.uncompressed_size as usize);
-                zstd::stream::copy_decode(
-                    &mut &self.buffer[..],
-                    &mut decompressed_data
-                )?;
+        let kfc_reader = self.kfc_reader.borrow();
+        let data = decompress_chunk_with(
+            &mut self.reader,
+            &mut self.buffer,
+            self.disk_cache.as_ref(),
+            &kfc_reader.file,
+            index,
+        )?;
 
-                self.chunk_cache.insert(index, decompressed_data);
+        self.chunk_cache.insert(index, data.clone());
 
-                Ok(())
-            }
-        }
+        Ok(data)
     }
 
     pub fn open_content(
         &self,
         hash: &ContentHash
-    ) -> std::io::Result<Option<Take<BufReader<File>>>> {
+    ) -> std::io::Result<Option<Take<StreamReader>>> {
         let kfc_reader = self.kfc_reader.borrow();
         let entry = match kfc_reader.file.contents().get(hash) {
             Some(entry) => entry,
@@ -347,27 +467,14 @@ where
     }
 
     #[inline]
-    pub fn kfc_path(&self) -> &Path {
-        self.kfc_reader.borrow().kfc_path.as_ref()
-    }
-
-    pub fn dat_path(&self, index: usize) -> PathBuf {
-        let kfc_reader = self.kfc_reader.borrow();
-
-        // Format: FILE_NAME_{INDEX}.dat where INDEX is 3 digits with leading zeros
-        let name = format!(
-            "{}_{:03}.{}",
-            kfc_reader.file_name,
-            index,
-            kfc_reader.dat_extension
-        );
-        kfc_reader.path.join(name)
+    pub fn storage(&self) -> &Arc<dyn KFCStorage> {
+        self.kfc_reader.borrow().storage()
     }
 
     fn get_container_reader(
         &mut self,
         index: usize
-    ) -> std::io::Result<&mut BufReader<File>> {
+    ) -> std::io::Result<&mut StreamReader> {
         if index >= self.container_readers.len() {
             self.container_readers.resize_with(index + 1, || None);
         }
@@ -384,8 +491,338 @@ where
     fn open_container_reader(
         &self,
         index: usize
-    ) -> std::io::Result<BufReader<File>> {
-        Ok(BufReader::new(File::open(self.dat_path(index))?))
+    ) -> std::io::Result<StreamReader> {
+        Ok(BufReader::new(self.storage().open_read(KFCStream::Container(index))?))
+    }
+
+}
+
+/// Chunks are never larger than a few MiB, so larger sizes are not trusted for preallocation.
+const MAX_CHUNK_PREALLOCATION: u64 = 64 * 1024 * 1024;
+
+#[inline]
+fn decompressed_capacity(chunk: &ResourceChunkInfo) -> usize {
+    chunk.uncompressed_size.min(MAX_CHUNK_PREALLOCATION) as usize
+}
+
+/// Decompresses a chunk, unless the persistent cache already holds it.
+fn decompress_chunk_with(
+    reader: &mut StreamReader,
+    buffer: &mut Vec<u8>,
+    disk_cache: Option<&DiskChunkCache>,
+    file: &KFCFile,
+    index: usize,
+) -> std::io::Result<Arc<[u8]>> {
+    let Some(disk_cache) = disk_cache else {
+        return decompress_chunk_uncached(reader, buffer, file, index);
+    };
+
+    let chunk = &file.resource_chunks()[index];
+
+    if let Some(data) = disk_cache.get(chunk) {
+        return Ok(data);
+    }
+
+    let data = decompress_chunk_uncached(reader, buffer, file, index)?;
+
+    // a chunk which could not be cached is simply decompressed again next time
+    let _ = disk_cache.insert(chunk, &data);
+
+    Ok(data)
+}
+
+fn decompress_chunk_uncached(
+    reader: &mut StreamReader,
+    buffer: &mut Vec<u8>,
+    file: &KFCFile,
+    index: usize,
+) -> std::io::Result<Arc<[u8]>> {
+    let chunk = &file.resource_chunks()[index];
+
+    buffer.clear();
+
+    // the buffer only grows as far as the file allows, regardless of the stored size
+    reader.seek(SeekFrom::Start(chunk.offset))?;
+    reader.read_exact_n(chunk.compressed_size as usize, buffer)?;
+
+    decompress_chunk_data(buffer, chunk)
+}
+
+/// Allocates a zeroed buffer which can be filled through [`Arc::get_mut`],
+/// so chunks can be shared without copying them.
+#[inline]
+pub(super) fn shared_buffer(size: usize) -> Arc<[u8]> {
+    std::iter::repeat_n(0, size).collect()
+}
+
+/// Decompresses the data of a chunk directly into a shared buffer.
+pub(super) fn decompress_chunk_data(
+    compressed: &[u8],
+    chunk: &ResourceChunkInfo,
+) -> std::io::Result<Arc<[u8]>> {
+    if chunk.uncompressed_size <= MAX_CHUNK_PREALLOCATION {
+        let mut data = shared_buffer(chunk.uncompressed_size as usize);
+        let buffer = Arc::get_mut(&mut data).expect("the buffer is not shared yet");
+
+        // chunks which do not match their stored size are decoded by the streaming decoder below
+        match zstd::bulk::decompress_to_buffer(compressed, buffer) {
+            Ok(size) if size == buffer.len() => return Ok(data),
+            Ok(size) => return Ok(buffer[..size].into()),
+            Err(_) => {}
+        }
+    }
+
+    let mut decompressed_data = Vec::with_capacity(decompressed_capacity(chunk));
+    zstd::stream::copy_decode(compressed, &mut decompressed_data)?;
+
+    Ok(decompressed_data.into())
+}
+
+/// A streaming reader for a single resource, created by [`KFCCursor::open_resource`].
+///
+/// Positions are relative to the start of the resource.
+pub struct KFCResourceReader<'a, R> {
+    cursor: &'a mut KFCCursor<R>,
+    span: ResourceSpan,
+    position: u64,
+
+    current_chunk: Option<(usize, Arc<[u8]>)>,
+}
+
+impl<R> KFCResourceReader<'_, R>
+where
+    R: Borrow<KFCReader>
+{
+
+    /// Returns the size of the resource in bytes.
+    #[inline]
+    pub fn size(&self) -> u64 {
+        self.span.size
+    }
+
+    #[inline]
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    fn chunk_at(&mut self, offset: u64) -> std::io::Result<(&ResourceChunkInfo, &[u8])> {
+        let index = chunk_index_at(self.cursor.file().resource_chunks(), offset)
+            .filter(|index| self.span.chunks.contains(index))
+            .ok_or_else(|| std::io::Error::new(
+                ErrorKind::UnexpectedEof,
+                format!("no resource chunk contains offset {offset}")
+            ))?;
+
+        if !matches!(&self.current_chunk, Some((current, _)) if *current == index) {
+            let data = self.cursor.decompress_chunk(index)?;
+
+            self.current_chunk = Some((index, data));
+        }
+
+        let (_, data) = self.current_chunk.as_ref().unwrap();
+
+        Ok((&self.cursor.file().resource_chunks()[index], data))
+    }
+
+}
+
+impl<R> Read for KFCResourceReader<'_, R>
+where
+    R: Borrow<KFCReader>
+{
+
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() || self.position >= self.span.size {
+            return Ok(0);
+        }
+
+        let offset = self.span.offset + self.position;
+        let remaining = self.span.size - self.position;
+
+        let (chunk, data) = self.chunk_at(offset)?;
+        let start = (offset - chunk.uncompressed_offset) as usize;
+
+        if start >= data.len() {
+            return Err(std::io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "resource chunk is smaller than expected"
+            ));
+        }
+
+        let len = buf.len()
+            .min(data.len() - start)
+            .min(remaining.try_into().unwrap_or(usize::MAX));
+
+        buf[..len].copy_from_slice(&data[start..start + len]);
+        self.position += len as u64;
+
+        Ok(len)
+    }
+
+}
+
+impl<R> Seek for KFCResourceReader<'_, R>
+where
+    R: Borrow<KFCReader>
+{
+
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.span.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+
+        match position {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            },
+            None => Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position"
+            )),
+        }
+    }
+
+    #[inline]
+    fn stream_position(&mut self) -> std::io::Result<u64> {
+        Ok(self.position)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{container::header::ResourceEntry, hash::fnv, testing::*};
+
+    use super::*;
+
+    #[test]
+    fn test_locate_overflowing_entries() -> Result<(), Box<dyn std::error::Error>> {
+        let archive = sample_archive(1);
+        let mut file = archive.reader()?.file().clone();
+
+        // chunks and resources which end beyond `u64::MAX` can only be created in memory
+        let mut chunks = file.resource_chunks().to_vec();
+        let last = chunks.len() - 1;
+        chunks[last].uncompressed_offset = u64::MAX - 1;
+        file.set_resource_chunks(chunks);
+
+        let mut resources = file.resources().as_builder();
+        let id = sample_resource_id(31, sample_resource_type(31));
+        resources.insert(id, ResourceEntry::new(u64::MAX - 1, 16));
+        file.set_resources(resources.build(), archive.type_registry());
+
+        assert!(ResourceSpan::locate(&file, &id).is_none());
+        assert_eq!(chunk_index_at(file.resource_chunks(), u64::MAX - 1), Some(last));
+
+        for id in file.resources().keys() {
+            let _ = ResourceSpan::locate(&file, id);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_synthetic_archive() -> Result<(), Box<dyn std::error::Error>> {
+        let archive = sample_archive(4);
+        let kfc_reader = archive.reader()?;
+
+        let mut cursor = kfc_reader.new_cursor()?;
+        let mut mapped_cursor = kfc_reader.new_mapped_cursor()?.with_cache_budget(16 * 1024);
+        let shared_cursor = kfc_reader.new_shared_cursor(16 * 1024)?;
+
+        assert_eq!(kfc_reader.file().resources().len(), 32);
+        assert_eq!(kfc_reader.file().contents().len(), 8);
+        assert_eq!(kfc_reader.file().resources_by_type(fnv(SAMPLE_TYPE_A)).count(), 16);
+
+        for i in 0..32 {
+            let type_name = sample_resource_type(i);
+            let id = sample_resource_id(i, type_name);
+            let expected = sample_resource_data(i, i as usize * 997);
+
+            assert_eq!(cursor.read_resource(&id)?.as_deref(), Some(&expected[..]));
+            assert_eq!(mapped_cursor.read_resource(&id)?.as_deref(), Some(&expected[..]));
+            assert_eq!(shared_cursor.read_resource(&id)?.as_deref(), Some(&expected[..]));
+
+            let mut streamed = Vec::new();
+            let mut resource = cursor.open_resource(&id)?.expect("resource exists");
+
+            resource.seek(SeekFrom::Start(expected.len() as u64 / 3))?;
+            resource.read_to_end(&mut streamed)?;
+
+            assert_eq!(&expected[expected.len() / 3..], streamed);
+        }
+
+        for i in 0..8 {
+            let data = sample_resource_data(i, 5000 + i as usize);
+            let hash = ContentHash::from_data(&data);
+
+            assert_eq!(cursor.read_content(&hash)?.as_deref(), Some(&data[..]));
+            assert_eq!(mapped_cursor.read_content(&hash)?, Some(&data[..]));
+        }
+
+        assert!(cursor.read_resource(&sample_resource_id(100, SAMPLE_TYPE_A))?.is_none());
+        assert!(mapped_cursor.chunk_cache().size() <= 16 * 1024);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_resources_batched() -> Result<(), Box<dyn std::error::Error>> {
+        let archive = sample_archive(4);
+        let kfc_reader = archive.reader()?;
+
+        let mut ids = (0..32)
+            .map(|i| sample_resource_id(i, sample_resource_type(i)))
+            .rev()
+            .collect::<Vec<_>>();
+
+        ids.push(sample_resource_id(100, SAMPLE_TYPE_A));
+
+        let expected = |id: &ResourceId| {
+            let index = id.guid().data()[0];
+            sample_resource_data(index, index as usize * 997)
+        };
+
+        let mut cursor = kfc_reader.new_cursor()?;
+        let mapped_cursor = kfc_reader.new_mapped_cursor()?;
+        let shared_cursor = kfc_reader.new_shared_cursor(16 * 1024)?;
+
+        let mut read = Vec::new();
+
+        cursor.read_resources(&ids, |id, data| {
+            assert_eq!(data, expected(id));
+            read.push(*id);
+            Ok::<_, std::io::Error>(())
+        })?;
+
+        // resources are read in the order of their data and unknown ones are skipped
+        let offsets = read.iter()
+            .map(|id| kfc_reader.file().resources().get(id).unwrap().offset)
+            .collect::<Vec<_>>();
+
+        assert_eq!(read.len(), 32);
+        assert!(offsets.is_sorted());
+
+        let mut count = 0;
+
+        mapped_cursor.read_resources(&ids, |id, data| {
+            assert_eq!(data, expected(id));
+            count += 1;
+            Ok::<_, std::io::Error>(())
+        })?;
+
+        shared_cursor.read_resources(&ids, |id, data| {
+            assert_eq!(data, expected(id));
+            count += 1;
+            Ok::<_, std::io::Error>(())
+        })?;
+
+        assert_eq!(count, 64);
+
+        Ok(())
     }
 
 }
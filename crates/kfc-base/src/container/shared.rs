@@ -0,0 +1,181 @@
+use std::{borrow::Borrow, sync::Arc};
+
+use crate::{container::KFCReadError, guid::{ContentHash, ResourceId}};
+
+use super::{mapped::MappedArchive, reader::{read_resources_with, ResourceSpan}, ChunkCache, KFCFile, KFCReader};
+
+/// A cursor which can be shared between multiple threads.
+///
+/// Like [`KFCMappedCursor`](super::KFCMappedCursor), it memory-maps the resource and
+/// container files and is subject to the same safety considerations.
+/// Decompressed chunks are kept in a [`ChunkCache`] with a bounded memory budget,
+/// so a chunk decompressed by one thread can be reused by all other threads.
+pub struct KFCSharedCursor<R> {
+    kfc_reader: R,
+    archive: MappedArchive,
+
+    chunk_cache: ChunkCache,
+}
+
+impl<R> KFCSharedCursor<R>
+where
+    R: Borrow<KFCReader>
+{
+
+    pub(super) fn new(
+        kfc_reader: R,
+        cache_budget: usize,
+    ) -> Result<Self, KFCReadError> {
+        let archive = MappedArchive::open(kfc_reader.borrow())?;
+
+        Ok(Self {
+            kfc_reader,
+            archive,
+
+            chunk_cache: ChunkCache::new(cache_budget),
+        })
+    }
+
+    #[inline]
+    pub fn file(&self) -> &KFCFile {
+        self.kfc_reader.borrow().file()
+    }
+
+    #[inline]
+    pub fn chunk_cache(&self) -> &ChunkCache {
+        &self.chunk_cache
+    }
+
+    /// Returns the decompressed bytes of the resource chunk at the given index.
+    pub fn decompress_chunk(
+        &self,
+        index: usize,
+    ) -> std::io::Result<Arc<[u8]>> {
+        if let Some(data) = self.chunk_cache.get(index) {
+            return Ok(data);
+        }
+
+        let data = self.archive.decompress_chunk(self.file(), index)?;
+
+        self.chunk_cache.insert(index, data.clone());
+
+        Ok(data)
+    }
+
+    pub fn read_resource(
+        &self,
+        id: &ResourceId,
+    ) -> std::io::Result<Option<Vec<u8>>> {
+        let mut data = Vec::new();
+
+        if !self.read_resource_into(id, &mut data)? {
+            return Ok(None);
+        }
+
+        Ok(Some(data))
+    }
+
+    pub fn read_resource_into(
+        &self,
+        id: &ResourceId,
+        dst: &mut Vec<u8>,
+    ) -> std::io::Result<bool> {
+        let span = match ResourceSpan::locate(self.file(), id) {
+            Some(span) => span,
+            None => return Ok(false),
+        };
+
+        dst.reserve_exact(span.size.saturating_sub(dst.len() as u64) as usize);
+
+        for i in span.chunks.clone() {
+            let chunk = &self.file().resource_chunks()[i];
+            let chunk_data = self.decompress_chunk(i)?;
+
+            dst.extend_from_slice(&chunk_data[span.range_in_chunk(chunk, chunk_data.len())]);
+        }
+
+        Ok(true)
+    }
+
+    /// Reads multiple resources and passes their data to the given callback,
+    /// see [`KFCCursor::read_resources`](super::KFCCursor::read_resources).
+    ///
+    /// Decompressed chunks go through the shared chunk cache,
+    /// so batches of different threads can reuse each other's chunks.
+    pub fn read_resources<'a, E>(
+        &self,
+        ids: impl IntoIterator<Item = &'a ResourceId>,
+        f: impl FnMut(&ResourceId, &[u8]) -> Result<(), E>,
+    ) -> Result<(), E>
+    where
+        E: From<std::io::Error>,
+    {
+        read_resources_with(self.file(), ids, |index| self.decompress_chunk(index), f)
+    }
+
+    /// Returns the content with the given hash as a slice of the mapped container.
+    #[inline]
+    pub fn read_content(
+        &self,
+        hash: &ContentHash,
+    ) -> std::io::Result<Option<&[u8]>> {
+        self.archive.content(self.file(), hash)
+    }
+
+    pub fn read_content_into(
+        &self,
+        hash: &ContentHash,
+        dst: &mut Vec<u8>,
+    ) -> std::io::Result<bool> {
+        match self.read_content(hash)? {
+            Some(data) => {
+                dst.clear();
+                dst.extend_from_slice(data);
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::*;
+
+    #[test]
+    fn test_shared_cursor_threads() -> Result<(), Box<dyn std::error::Error>> {
+        let archive = sample_archive(1);
+        let kfc_reader = archive.reader()?;
+        let cursor = kfc_reader.new_shared_cursor(64 * 1024)?;
+
+        // every thread reads all resources in a different order, so chunks are decompressed
+        // by one thread and taken from the shared cache by the others
+        std::thread::scope(|scope| {
+            let handles = (0..4u8)
+                .map(|thread| {
+                    let cursor = &cursor;
+
+                    scope.spawn(move || -> std::io::Result<()> {
+                        for i in 0..32 {
+                            let i = (i + thread * 8) % 32;
+                            let id = sample_resource_id(i, sample_resource_type(i));
+                            let expected = sample_resource_data(i, i as usize * 997);
+
+                            assert_eq!(cursor.read_resource(&id)?.as_deref(), Some(&expected[..]));
+                        }
+
+                        Ok(())
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            handles.into_iter()
+                .try_for_each(|handle| handle.join().expect("reader thread panicked"))
+        })?;
+
+        assert!(cursor.chunk_cache().size() <= cursor.chunk_cache().budget());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,805 @@
+use std::{collections::BTreeMap, fs::File, io::{BufReader, Read, Seek, SeekFrom, Write}, path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
+
+use crc::{Crc, CRC_64_ECMA_182};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::{DiskStorage, KFCFile, KFCReadError, KFCStorage, KFCStream};
+
+/// The name of the snapshot of the unmodified archive, which is used by the mod loaders.
+pub const ORIGINAL_SNAPSHOT_NAME: &str = "original";
+
+const MANIFEST_FILE_NAME: &str = "snapshot.json";
+const SNAPSHOT_FILE_NAME: &str = "snapshot";
+/// How many bytes at the end of a stream are covered by its tail checksum.
+const TAIL_SIZE: u64 = 64 * 1024;
+
+static CRC: Crc<u64> = Crc::<u64>::new(&CRC_64_ECMA_182);
+
+#[derive(Debug, Error)]
+pub enum KFCSnapshotError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Read error: {0}")]
+    Read(#[from] KFCReadError),
+    #[error("Invalid manifest: {0}")]
+    Manifest(#[from] serde_json::Error),
+
+    #[error("Invalid snapshot name: `{0}`")]
+    InvalidName(String),
+    #[error("Snapshot `{name}` already exists for game version {game_version}")]
+    AlreadyExists {
+        name: String,
+        game_version: String,
+    },
+    #[error("Snapshot `{name}` can not be restored: {}", format_issues(.issues))]
+    NotRestorable {
+        name: String,
+        issues: Vec<KFCSnapshotIssue>,
+    },
+    #[error("The original snapshot of game version {game_version} does not match the archive: {}", format_issues(.issues))]
+    OriginalMismatch {
+        game_version: String,
+        issues: Vec<KFCSnapshotIssue>,
+    },
+}
+
+/// A reason why a snapshot can not be restored.
+#[derive(Debug, Clone, PartialEq, Eq, Error, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum KFCSnapshotIssue {
+    #[error("The copy of the {stream:?} stream is missing")]
+    MissingStream {
+        stream: KFCStream,
+    },
+    #[error("The copy of the {stream:?} stream does not match its checksum")]
+    ChecksumMismatch {
+        stream: KFCStream,
+    },
+    #[error("The {stream:?} stream has {actual} bytes, but the snapshot requires at least {expected}")]
+    StreamTooSmall {
+        stream: KFCStream,
+        expected: u64,
+        actual: u64,
+    },
+    #[error("The {stream:?} stream was modified after the snapshot was created")]
+    StreamModified {
+        stream: KFCStream,
+    },
+    #[error("Container {container} is missing")]
+    MissingContainer {
+        container: usize,
+    },
+    #[error("Container {container} has {actual} bytes, but the snapshot requires at least {expected}")]
+    ContainerTooSmall {
+        container: usize,
+        expected: u64,
+        actual: u64,
+    },
+    #[error("Container {container} was modified after the snapshot was created")]
+    ContainerModified {
+        container: usize,
+    },
+}
+
+/// The manifest of a snapshot.
+///
+/// Only the index is copied into the snapshot, while the resource stream and containers are
+/// only recorded by their size, since writers never modify them before that size.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KFCSnapshot {
+    pub name: String,
+    pub game_version: String,
+    /// Seconds since the unix epoch.
+    pub created_at: u64,
+
+    pub index: KFCSnapshotStream,
+    pub resources: KFCSnapshotStream,
+    pub containers: Vec<KFCSnapshotStream>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KFCSnapshotStream {
+    pub size: u64,
+    /// The CRC-64 of the stream.
+    /// For the resource stream and containers, only the last 64 KiB before `size` are covered.
+    pub checksum: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct KFCSnapshotPruneOptions {
+    /// How many of the newest snapshots are kept for each game version,
+    /// not counting the original snapshot.
+    pub keep_per_version: usize,
+    /// Whether snapshots of other game versions than the current one are kept.
+    /// This includes the original snapshots of other game versions.
+    pub keep_other_versions: bool,
+}
+
+impl Default for KFCSnapshotPruneOptions {
+    fn default() -> Self {
+        Self {
+            keep_per_version: 3,
+            keep_other_versions: true,
+        }
+    }
+}
+
+/// Creates, verifies and restores named snapshots of an archive.
+///
+/// Snapshots are grouped by the game version of the archive at the time they were created,
+/// so the same name can be used for every game version.
+pub struct KFCSnapshotManager<S> {
+    archive: S,
+    directory: PathBuf,
+}
+
+impl KFCSnapshotManager<DiskStorage> {
+
+    /// Manages the snapshots of the archive with the given name in a game directory.
+    /// The snapshots are stored in `<game_dir>/kfc_snapshots/<file_name>`.
+    pub fn for_game_dir(
+        game_dir: impl AsRef<Path>,
+        file_name: impl AsRef<str>,
+    ) -> Self {
+        let game_dir = game_dir.as_ref();
+        let file_name = file_name.as_ref();
+
+        Self::new(
+            DiskStorage::new(game_dir, file_name),
+            game_dir.join("kfc_snapshots").join(file_name),
+        )
+    }
+
+    /// Returns the original snapshot of the current game version, creating it if necessary.
+    ///
+    /// The legacy index backup is imported if it exists, see [`KFCSnapshotManager::migrate_legacy_backup`].
+    /// An existing original snapshot which does not match the archive is an error,
+    /// since a new snapshot would capture the modified files instead of the original ones.
+    pub fn ensure_original(&self) -> Result<KFCSnapshot, KFCSnapshotError> {
+        let game_version = self.game_version()?;
+
+        if let Some(snapshot) = self.get(&game_version, ORIGINAL_SNAPSHOT_NAME)? {
+            let issues = self.verify(&snapshot)?;
+
+            if !issues.is_empty() {
+                return Err(KFCSnapshotError::OriginalMismatch {
+                    game_version,
+                    issues,
+                });
+            }
+
+            return Ok(snapshot);
+        }
+
+        match self.migrate_legacy_backup()? {
+            Some(snapshot) => Ok(snapshot),
+            None => self.create(ORIGINAL_SNAPSHOT_NAME),
+        }
+    }
+
+    /// Imports the `<file_name>.kfc.bak` index backup of older versions as the original snapshot.
+    ///
+    /// Nothing is imported if the backup belongs to another game version
+    /// or if the original snapshot already exists.
+    pub fn migrate_legacy_backup(&self) -> Result<Option<KFCSnapshot>, KFCSnapshotError> {
+        let legacy_path = self.archive.directory()
+            .join(format!("{}.kfc.bak", self.archive.file_name()));
+
+        if !legacy_path.is_file() {
+            return Ok(None);
+        }
+
+        let game_version = self.game_version()?;
+
+        if KFCFile::get_version_tag(&legacy_path)? != game_version ||
+            self.get(&game_version, ORIGINAL_SNAPSHOT_NAME)?.is_some()
+        {
+            return Ok(None);
+        }
+
+        let index = BufReader::new(File::open(&legacy_path)?);
+
+        Ok(Some(self.create_with_index(ORIGINAL_SNAPSHOT_NAME, index)?))
+    }
+
+}
+
+impl<S: KFCStorage> KFCSnapshotManager<S> {
+
+    pub fn new(
+        archive: S,
+        directory: impl AsRef<Path>,
+    ) -> Self {
+        Self {
+            archive,
+            directory: directory.as_ref().to_path_buf(),
+        }
+    }
+
+    #[inline]
+    pub fn archive(&self) -> &S {
+        &self.archive
+    }
+
+    #[inline]
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Returns the game version of the archive.
+    pub fn game_version(&self) -> Result<String, KFCSnapshotError> {
+        let mut reader = BufReader::new(self.archive.open_read(KFCStream::Index)?);
+
+        Ok(KFCFile::read_version_tag(&mut reader)?)
+    }
+
+    /// Returns the storage holding the copied index of the snapshot.
+    ///
+    /// Layer it on top of the archive with an [`OverlayStorage`](super::OverlayStorage)
+    /// to read the archive as it was when the snapshot was created.
+    pub fn storage(&self, snapshot: &KFCSnapshot) -> DiskStorage {
+        DiskStorage::new(
+            self.snapshot_dir(&snapshot.game_version, &snapshot.name),
+            SNAPSHOT_FILE_NAME,
+        )
+    }
+
+    /// Returns all complete snapshots, ordered by game version and creation time.
+    pub fn snapshots(&self) -> Result<Vec<KFCSnapshot>, KFCSnapshotError> {
+        let mut snapshots = Vec::new();
+
+        for version_dir in read_dirs(&self.directory)? {
+            for snapshot_dir in read_dirs(&version_dir)? {
+                if let Some(snapshot) = read_manifest(&snapshot_dir)? {
+                    snapshots.push(snapshot);
+                }
+            }
+        }
+
+        snapshots.sort_by(|a, b| {
+            (&a.game_version, a.created_at, &a.name).cmp(&(&b.game_version, b.created_at, &b.name))
+        });
+
+        Ok(snapshots)
+    }
+
+    pub fn get(
+        &self,
+        game_version: &str,
+        name: &str,
+    ) -> Result<Option<KFCSnapshot>, KFCSnapshotError> {
+        validate_name(name)?;
+
+        read_manifest(&self.snapshot_dir(game_version, name))
+    }
+
+    /// Returns the snapshot with the given name for the current game version
+    /// or creates it if it does not exist.
+    pub fn get_or_create(&self, name: &str) -> Result<KFCSnapshot, KFCSnapshotError> {
+        match self.get(&self.game_version()?, name)? {
+            Some(snapshot) => Ok(snapshot),
+            None => self.create(name),
+        }
+    }
+
+    /// Creates a snapshot of the current state of the archive.
+    ///
+    /// Incremental writes replace the resource chunks after their reference file,
+    /// so snapshots of a modified archive stop being restorable once the archive is patched again.
+    pub fn create(&self, name: &str) -> Result<KFCSnapshot, KFCSnapshotError> {
+        let index = self.archive.open_read(KFCStream::Index)?;
+
+        self.create_with_index(name, index)
+    }
+
+    /// Creates a snapshot of the archive, but with the given index instead of the current one.
+    ///
+    /// This is useful to import a backup of the index, which was taken before the archive
+    /// was modified. All data the index points to must still be unchanged.
+    pub fn create_with_index(
+        &self,
+        name: &str,
+        mut index: impl Read,
+    ) -> Result<KFCSnapshot, KFCSnapshotError> {
+        validate_name(name)?;
+
+        // copy the index first, since the game version is read from it
+
+        let staging_dir = self.directory.join(format!(".{name}.staging"));
+
+        std::fs::create_dir_all(&staging_dir)?;
+
+        let staging = DiskStorage::new(&staging_dir, SNAPSHOT_FILE_NAME);
+        let mut index_copy = staging.create(KFCStream::Index)?;
+        let index = copy_with_checksum(&mut index, &mut index_copy)?;
+
+        index_copy.seek(SeekFrom::Start(0))?;
+
+        let game_version = KFCFile::read_version_tag(&mut BufReader::new(&mut index_copy))?;
+
+        drop(index_copy);
+
+        let snapshot_dir = self.snapshot_dir(&game_version, name);
+
+        if snapshot_dir.join(MANIFEST_FILE_NAME).exists() {
+            std::fs::remove_dir_all(&staging_dir)?;
+
+            return Err(KFCSnapshotError::AlreadyExists {
+                name: name.to_string(),
+                game_version,
+            });
+        }
+
+        staging.sync(KFCStream::Index)?;
+
+        let resources = self.tail_stream(KFCStream::Resources)?;
+
+        let mut containers = Vec::new();
+
+        while self.archive.exists(KFCStream::Container(containers.len())) {
+            containers.push(self.tail_stream(KFCStream::Container(containers.len()))?);
+        }
+
+        let snapshot = KFCSnapshot {
+            name: name.to_string(),
+            game_version,
+            created_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+
+            index,
+            resources,
+            containers,
+        };
+
+        // the manifest is written last, so incomplete snapshots are never listed
+
+        std::fs::write(staging_dir.join(MANIFEST_FILE_NAME), serde_json::to_vec_pretty(&snapshot)?)?;
+
+        if snapshot_dir.exists() {
+            std::fs::remove_dir_all(&snapshot_dir)?;
+        }
+
+        std::fs::create_dir_all(snapshot_dir.parent().unwrap_or(&self.directory))?;
+        std::fs::rename(&staging_dir, &snapshot_dir)?;
+
+        Ok(snapshot)
+    }
+
+    /// Checks whether the snapshot can be restored exactly.
+    pub fn verify(&self, snapshot: &KFCSnapshot) -> Result<Vec<KFCSnapshotIssue>, KFCSnapshotError> {
+        let mut issues = Vec::new();
+        let storage = self.storage(snapshot);
+
+        if !storage.exists(KFCStream::Index) {
+            issues.push(KFCSnapshotIssue::MissingStream { stream: KFCStream::Index });
+        } else if copy_with_checksum(&mut storage.open_read(KFCStream::Index)?, &mut std::io::sink())? != snapshot.index {
+            issues.push(KFCSnapshotIssue::ChecksumMismatch { stream: KFCStream::Index });
+        }
+
+        let stream = KFCStream::Resources;
+        let expected = snapshot.resources;
+        let actual = if self.archive.exists(stream) { self.archive.len(stream)? } else { 0 };
+
+        if actual < expected.size {
+            issues.push(KFCSnapshotIssue::StreamTooSmall {
+                stream,
+                expected: expected.size,
+                actual,
+            });
+        } else if tail_checksum(&self.archive, stream, expected.size)? != expected.checksum {
+            issues.push(KFCSnapshotIssue::StreamModified { stream });
+        }
+
+        for (container, expected) in snapshot.containers.iter().enumerate() {
+            let stream = KFCStream::Container(container);
+
+            if !self.archive.exists(stream) {
+                issues.push(KFCSnapshotIssue::MissingContainer { container });
+                continue;
+            }
+
+            let actual = self.archive.len(stream)?;
+
+            if actual < expected.size {
+                issues.push(KFCSnapshotIssue::ContainerTooSmall {
+                    container,
+                    expected: expected.size,
+                    actual,
+                });
+            } else if tail_checksum(&self.archive, stream, expected.size)? != expected.checksum {
+                issues.push(KFCSnapshotIssue::ContainerModified { container });
+            }
+        }
+
+        Ok(issues)
+    }
+
+    /// Restores the archive to the exact state of the snapshot.
+    ///
+    /// The resource stream and containers are truncated to their recorded size,
+    /// then the index is replaced by its copy.
+    /// Containers which were created after the snapshot are left untouched,
+    /// since the restored index does not reference them.
+    pub fn restore(&self, snapshot: &KFCSnapshot) -> Result<(), KFCSnapshotError> {
+        let issues = self.verify(snapshot)?;
+
+        if !issues.is_empty() {
+            return Err(KFCSnapshotError::NotRestorable {
+                name: snapshot.name.clone(),
+                issues,
+            });
+        }
+
+        for (container, expected) in snapshot.containers.iter().enumerate() {
+            let stream = KFCStream::Container(container);
+
+            if self.archive.len(stream)? != expected.size {
+                self.archive.set_len(stream, expected.size)?;
+                self.archive.sync(stream)?;
+            }
+        }
+
+        if self.archive.len(KFCStream::Resources)? != snapshot.resources.size {
+            self.archive.set_len(KFCStream::Resources, snapshot.resources.size)?;
+            self.archive.sync(KFCStream::Resources)?;
+        }
+
+        let mut src = self.storage(snapshot).open_read(KFCStream::Index)?;
+        let mut dst = self.archive.create_staged(KFCStream::Index)?;
+
+        std::io::copy(&mut src, &mut dst)?;
+        dst.flush()?;
+
+        drop(dst);
+
+        self.archive.commit_staged(KFCStream::Index)?;
+
+        Ok(())
+    }
+
+    pub fn remove(&self, snapshot: &KFCSnapshot) -> Result<(), KFCSnapshotError> {
+        let snapshot_dir = self.snapshot_dir(&snapshot.game_version, &snapshot.name);
+
+        std::fs::remove_dir_all(&snapshot_dir)?;
+
+        // the version directory is only removed if it is empty
+        if let Some(version_dir) = snapshot_dir.parent() {
+            let _ = std::fs::remove_dir(version_dir);
+        }
+
+        Ok(())
+    }
+
+    /// Removes old snapshots and returns the removed ones.
+    ///
+    /// The original snapshot of the current game version is never removed,
+    /// since it is the only way to undo all modifications.
+    pub fn prune(&self, options: &KFCSnapshotPruneOptions) -> Result<Vec<KFCSnapshot>, KFCSnapshotError> {
+        let game_version = self.game_version()?;
+        let mut versions = BTreeMap::<String, Vec<KFCSnapshot>>::new();
+
+        for snapshot in self.snapshots()? {
+            if snapshot.name == ORIGINAL_SNAPSHOT_NAME &&
+                (snapshot.game_version == game_version || options.keep_other_versions)
+            {
+                continue;
+            }
+
+            versions.entry(snapshot.game_version.clone())
+                .or_default()
+                .push(snapshot);
+        }
+
+        let mut removed = Vec::new();
+
+        for (version, mut snapshots) in versions {
+            let keep = if version == game_version || options.keep_other_versions {
+                options.keep_per_version
+            } else {
+                0
+            };
+
+            // newest first
+            snapshots.reverse();
+
+            for snapshot in snapshots.into_iter().skip(keep) {
+                self.remove(&snapshot)?;
+                removed.push(snapshot);
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Records a stream of the archive by its size and tail checksum.
+    fn tail_stream(&self, stream: KFCStream) -> std::io::Result<KFCSnapshotStream> {
+        let size = self.archive.len(stream)?;
+
+        Ok(KFCSnapshotStream {
+            size,
+            checksum: tail_checksum(&self.archive, stream, size)?,
+        })
+    }
+
+    fn snapshot_dir(&self, game_version: &str, name: &str) -> PathBuf {
+        self.directory.join(version_dir_name(game_version)).join(name)
+    }
+
+}
+
+/// Returns a directory name for the given game version,
+/// since game versions are not guaranteed to be valid file names.
+pub(super) fn version_dir_name(game_version: &str) -> String {
+    game_version.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') { c } else { '_' })
+        .collect()
+}
+
+fn validate_name(name: &str) -> Result<(), KFCSnapshotError> {
+    let is_valid = !name.is_empty() &&
+        !name.starts_with('.') &&
+        name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'));
+
+    if !is_valid {
+        return Err(KFCSnapshotError::InvalidName(name.to_string()));
+    }
+
+    Ok(())
+}
+
+fn read_dirs(path: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let entries = match std::fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut dirs = Vec::new();
+
+    for entry in entries {
+        let entry = entry?;
+
+        if entry.file_type()?.is_dir() && !entry.file_name().to_string_lossy().starts_with('.') {
+            dirs.push(entry.path());
+        }
+    }
+
+    Ok(dirs)
+}
+
+fn read_manifest(snapshot_dir: &Path) -> Result<Option<KFCSnapshot>, KFCSnapshotError> {
+    let data = match std::fs::read(snapshot_dir.join(MANIFEST_FILE_NAME)) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    Ok(Some(serde_json::from_slice(&data)?))
+}
+
+fn copy_with_checksum(
+    src: &mut impl Read,
+    dst: &mut impl Write,
+) -> std::io::Result<KFCSnapshotStream> {
+    let mut digest = CRC.digest();
+    let mut buffer = vec![0; 64 * 1024];
+    let mut size = 0;
+
+    loop {
+        let len = match src.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        digest.update(&buffer[..len]);
+        dst.write_all(&buffer[..len])?;
+        size += len as u64;
+    }
+
+    dst.flush()?;
+
+    Ok(KFCSnapshotStream {
+        size,
+        checksum: digest.finalize(),
+    })
+}
+
+fn tail_checksum(
+    storage: &impl KFCStorage,
+    stream: KFCStream,
+    size: u64,
+) -> std::io::Result<u64> {
+    let start = size.saturating_sub(TAIL_SIZE);
+    let mut reader = storage.open_read(stream)?;
+
+    reader.seek(SeekFrom::Start(start))?;
+
+    let tail = copy_with_checksum(&mut reader.take(size - start), &mut std::io::sink())?;
+
+    Ok(tail.checksum)
+}
+
+fn format_issues(issues: &[KFCSnapshotIssue]) -> String {
+    issues.iter()
+        .map(|issue| issue.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{container::{KFCVerifyOptions, KFCWriteOptions, KFCWriter}, guid::ContentHash, testing::*};
+
+    use super::*;
+
+    #[test]
+    fn test_snapshot_restore() -> Result<(), Box<dyn std::error::Error>> {
+        let archive = sample_archive(4);
+        let reference = archive.reader()?.file().clone();
+        let snapshots = KFCSnapshotManager::for_game_dir(archive.path(), archive.file_name());
+
+        let original = read_dir_files(archive.path())?;
+        let snapshot = snapshots.create("original")?;
+
+        assert_eq!(snapshot.containers.len(), reference.containers().len());
+        assert!(snapshots.verify(&snapshot)?.is_empty());
+        assert!(matches!(snapshots.create("original"), Err(KFCSnapshotError::AlreadyExists { .. })));
+        assert!(matches!(snapshots.create("../original"), Err(KFCSnapshotError::InvalidName(_))));
+
+        let mut writer = KFCWriter::new_incremental_with_options(
+            archive.path(),
+            archive.file_name(),
+            &reference,
+            archive.type_registry(),
+            KFCWriteOptions {
+                resource_chunk_size: 4096,
+                ..Default::default()
+            },
+        )?;
+
+        writer.write_resource(&sample_resource_id(2, SAMPLE_TYPE_A), &sample_resource_data(42, 10_000))?;
+        writer.write_content(&ContentHash::from_data(&sample_resource_data(42, 7000)), &sample_resource_data(42, 7000))?;
+        writer.finalize()?;
+
+        assert!(original != read_dir_files(archive.path())?);
+        assert!(snapshots.verify(&snapshot)?.is_empty());
+
+        snapshots.restore(&snapshot)?;
+
+        // containers created after the snapshot are not part of the restored archive
+        let restored = read_dir_files(archive.path())?;
+
+        for (name, data) in &original {
+            assert!(restored.get(name) == Some(data), "{name} was not restored");
+        }
+
+        assert!(archive.reader()?.verify(&KFCVerifyOptions::default())?.is_ok());
+
+        // truncated containers can not be restored
+
+        let storage = DiskStorage::new(archive.path(), archive.file_name());
+        let size = storage.len(KFCStream::Container(0))?;
+
+        storage.set_len(KFCStream::Container(0), size - 1)?;
+
+        assert_eq!(snapshots.verify(&snapshot)?, vec![KFCSnapshotIssue::ContainerTooSmall {
+            container: 0,
+            expected: size,
+            actual: size - 1,
+        }]);
+        assert!(matches!(snapshots.restore(&snapshot), Err(KFCSnapshotError::NotRestorable { .. })));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_verify() -> Result<(), Box<dyn std::error::Error>> {
+        let archive = sample_archive(4);
+        let snapshots = KFCSnapshotManager::for_game_dir(archive.path(), archive.file_name());
+        let snapshot = snapshots.create(ORIGINAL_SNAPSHOT_NAME)?;
+
+        // only the index is copied
+        assert!(snapshots.storage(&snapshot).exists(KFCStream::Index));
+        assert!(!snapshots.storage(&snapshot).exists(KFCStream::Resources));
+        assert!(snapshots.verify(&snapshot)?.is_empty());
+
+        // modified resources are detected by their tail
+
+        let storage = DiskStorage::new(archive.path(), archive.file_name());
+        let mut writer = storage.open_write(KFCStream::Resources)?;
+        let mut last = [0];
+
+        writer.seek(SeekFrom::End(-1))?;
+        writer.read_exact(&mut last)?;
+        writer.seek(SeekFrom::End(-1))?;
+        writer.write_all(&[!last[0]])?;
+        drop(writer);
+
+        assert_eq!(snapshots.verify(&snapshot)?, vec![KFCSnapshotIssue::StreamModified { stream: KFCStream::Resources }]);
+        assert!(matches!(snapshots.restore(&snapshot), Err(KFCSnapshotError::NotRestorable { .. })));
+
+        storage.set_len(KFCStream::Resources, snapshot.resources.size - 1)?;
+
+        assert_eq!(snapshots.verify(&snapshot)?, vec![KFCSnapshotIssue::StreamTooSmall {
+            stream: KFCStream::Resources,
+            expected: snapshot.resources.size,
+            actual: snapshot.resources.size - 1,
+        }]);
+
+        // a modified index copy is detected as well
+
+        let mut writer = snapshots.storage(&snapshot).open_write(KFCStream::Index)?;
+
+        writer.seek(SeekFrom::End(-1))?;
+        writer.write_all(&[0xFF])?;
+        drop(writer);
+
+        assert!(snapshots.verify(&snapshot)?.contains(&KFCSnapshotIssue::ChecksumMismatch { stream: KFCStream::Index }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ensure_original() -> Result<(), Box<dyn std::error::Error>> {
+        let archive = sample_archive(1);
+        let snapshots = KFCSnapshotManager::for_game_dir(archive.path(), archive.file_name());
+
+        let original = snapshots.ensure_original()?;
+
+        assert_eq!(original.name, ORIGINAL_SNAPSHOT_NAME);
+        assert_eq!(snapshots.ensure_original()?, original);
+
+        // a mismatching original snapshot is neither reused nor replaced
+        let storage = DiskStorage::new(archive.path(), archive.file_name());
+        let size = storage.len(KFCStream::Container(0))?;
+
+        storage.set_len(KFCStream::Container(0), size - 1)?;
+
+        assert!(matches!(snapshots.ensure_original(), Err(KFCSnapshotError::OriginalMismatch { .. })));
+        assert_eq!(snapshots.snapshots()?, vec![original]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_prune() -> Result<(), Box<dyn std::error::Error>> {
+        let archive = sample_archive(1);
+        let snapshots = KFCSnapshotManager::for_game_dir(archive.path(), archive.file_name());
+
+        for name in [ORIGINAL_SNAPSHOT_NAME, "a", "b", "c"] {
+            snapshots.create(name)?;
+        }
+
+        assert_eq!(snapshots.snapshots()?.len(), 4);
+        assert!(snapshots.get(&snapshots.game_version()?, "b")?.is_some());
+
+        let removed = snapshots.prune(&KFCSnapshotPruneOptions {
+            keep_per_version: 1,
+            ..Default::default()
+        })?;
+
+        assert_eq!(removed.len(), 2);
+        assert!(removed.iter().all(|snapshot| snapshot.name != ORIGINAL_SNAPSHOT_NAME));
+        assert_eq!(snapshots.snapshots()?.len(), 2);
+        assert!(snapshots.get(&snapshots.game_version()?, ORIGINAL_SNAPSHOT_NAME)?.is_some());
+
+        // the original snapshot is kept even if nothing else is
+
+        let removed = snapshots.prune(&KFCSnapshotPruneOptions {
+            keep_per_version: 0,
+            keep_other_versions: false,
+        })?;
+
+        assert_eq!(removed.len(), 1);
+        assert_eq!(snapshots.snapshots()?.len(), 1);
+        assert!(snapshots.get(&snapshots.game_version()?, ORIGINAL_SNAPSHOT_NAME)?.is_some());
+
+        Ok(())
+    }
+
+}
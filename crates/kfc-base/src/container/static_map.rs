@@ -1,15 +1,26 @@
 // TODO: Implement better Debug trait representation for StaticMap and StaticMapBuilder
 
-use std::{cmp::{Eq, Ord}, collections::HashMap, hash::Hash, io::{Read, Write}};
+use std::{cmp::{Eq, Ord}, collections::HashMap, hash::Hash, io::{Read, Write}, marker::PhantomData};
+
+use serde::{Deserialize, Serialize};
 
 use crate::io::{ReadExt, WriteExt};
 
-use super::StaticMapError;
+use super::{KFCReadError, StaticMapError};
 
 pub trait StaticHash {
     fn static_hash(&self) -> u32;
 }
 
+/// A value which is stored with a fixed size, so it can be decoded in place.
+pub trait StaticElement: Sized {
+    /// The size of the value in bytes.
+    const SIZE: usize;
+
+    /// Decodes the value from exactly [`Self::SIZE`] bytes.
+    fn decode(bytes: &[u8]) -> Result<Self, KFCReadError>;
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct StaticMap<K, V> {
     keys: Vec<K>,
@@ -24,20 +35,48 @@ impl<K: std::cmp::PartialEq + StaticHash, V> StaticMap<K, V> {
         values: Vec<V>,
         buckets: Vec<StaticMapBucket>,
     ) -> Result<Self, StaticMapError> {
-        let bucket_ref_count = buckets.iter().map(|b| b.count).sum();
-
         if keys.len() != values.len() {
             return Err(StaticMapError::LengthMismatch(keys.len(), values.len()));
         }
 
-        if keys.len() != bucket_ref_count {
-            return Err(StaticMapError::BucketCountMismatch(keys.len(), bucket_ref_count));
-        }
-
         if !buckets.is_empty() && buckets.len().count_ones() != 1 {
             return Err(StaticMapError::InvalidBucketSize(buckets.len()));
         }
 
+        let mut bucket_ref_count = 0usize;
+
+        for (i, bucket) in buckets.iter().enumerate() {
+            let end = bucket.index.saturating_add(bucket.count);
+
+            if end > keys.len() {
+                return Err(StaticMapError::BucketOutOfBounds {
+                    bucket: i,
+                    index: bucket.index,
+                    end,
+                    len: keys.len(),
+                });
+            }
+
+            // lookups only search the bucket selected by the hash, so other keys could not be found
+            for (key, key_index) in keys[bucket.index..end].iter().zip(bucket.index..) {
+                let expected = key.static_hash() as usize % buckets.len();
+
+                if expected != i {
+                    return Err(StaticMapError::MisplacedKey {
+                        key: key_index,
+                        bucket: i,
+                        expected,
+                    });
+                }
+            }
+
+            bucket_ref_count = bucket_ref_count.saturating_add(bucket.count);
+        }
+
+        if keys.len() != bucket_ref_count {
+            return Err(StaticMapError::BucketCountMismatch(keys.len(), bucket_ref_count));
+        }
+
         Ok(Self {
             keys,
             values,
@@ -46,6 +85,10 @@ impl<K: std::cmp::PartialEq + StaticHash, V> StaticMap<K, V> {
     }
 
     pub fn get(&self, key: &K) -> Option<&V> {
+        if self.buckets.is_empty() {
+            return None;
+        }
+
         let hash = key.static_hash();
         let bucket_index = hash as usize % self.buckets.len();
         let bucket = &self.buckets[bucket_index];
@@ -116,7 +159,216 @@ impl<K: Eq + Hash + Ord + StaticHash + Clone, V: Clone> StaticMap<K, V> {
 
 }
 
-#[derive(Debug, Clone, Default)]
+/// A borrowed array of fixed size values, which are decoded when they are accessed.
+pub struct StaticSlice<'a, T> {
+    bytes: &'a [u8],
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<'a, T: StaticElement> StaticSlice<'a, T> {
+
+    /// Creates a slice of `count` values from the start of the given bytes.
+    /// Returns `None` if there are not enough bytes.
+    pub fn new(bytes: &'a [u8], count: usize) -> Option<Self> {
+        let len = count.checked_mul(T::SIZE)?;
+
+        Some(Self {
+            bytes: bytes.get(..len)?,
+            _marker: PhantomData,
+        })
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.bytes.len() / T::SIZE
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Decodes the value at the given index.
+    /// Returns `None` if the index is out of bounds or the value can not be decoded.
+    #[inline]
+    pub fn get(&self, index: usize) -> Option<T> {
+        let start = index.checked_mul(T::SIZE)?;
+        let bytes = self.bytes.get(start..start + T::SIZE)?;
+
+        T::decode(bytes).ok()
+    }
+
+    /// Decodes all values, values which can not be decoded are skipped.
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = T> + 'a {
+        self.bytes.chunks_exact(T::SIZE)
+            .filter_map(|bytes| T::decode(bytes).ok())
+    }
+
+    /// Decodes all values into a vector.
+    pub fn to_vec(&self) -> Result<Vec<T>, KFCReadError> {
+        self.bytes.chunks_exact(T::SIZE)
+            .map(T::decode)
+            .collect()
+    }
+
+}
+
+impl<T> Clone for StaticSlice<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for StaticSlice<'_, T> {}
+
+impl<T> std::fmt::Debug for StaticSlice<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StaticSlice")
+            .field("size", &self.bytes.len())
+            .finish()
+    }
+}
+
+/// A borrowed [`StaticMap`], which looks up entries directly in its encoded buckets, keys and values.
+///
+/// Creating a view only checks the buckets, keys and values are decoded on lookup,
+/// so no memory is allocated for the entries.
+pub struct StaticMapView<'a, K, V> {
+    keys: StaticSlice<'a, K>,
+    values: StaticSlice<'a, V>,
+    buckets: StaticSlice<'a, StaticMapBucket>,
+}
+
+impl<'a, K, V> StaticMapView<'a, K, V>
+where
+    K: PartialEq + StaticHash + StaticElement,
+    V: StaticElement,
+{
+
+    pub fn new(
+        keys: StaticSlice<'a, K>,
+        values: StaticSlice<'a, V>,
+        buckets: StaticSlice<'a, StaticMapBucket>,
+    ) -> Result<Self, StaticMapError> {
+        if keys.len() != values.len() {
+            return Err(StaticMapError::LengthMismatch(keys.len(), values.len()));
+        }
+
+        if !buckets.is_empty() && buckets.len().count_ones() != 1 {
+            return Err(StaticMapError::InvalidBucketSize(buckets.len()));
+        }
+
+        let mut bucket_ref_count = 0usize;
+
+        for (i, bucket) in buckets.iter().enumerate() {
+            let end = bucket.index.saturating_add(bucket.count);
+
+            if end > keys.len() {
+                return Err(StaticMapError::BucketOutOfBounds {
+                    bucket: i,
+                    index: bucket.index,
+                    end,
+                    len: keys.len(),
+                });
+            }
+
+            bucket_ref_count = bucket_ref_count.saturating_add(bucket.count);
+        }
+
+        if keys.len() != bucket_ref_count {
+            return Err(StaticMapError::BucketCountMismatch(keys.len(), bucket_ref_count));
+        }
+
+        Ok(Self {
+            keys,
+            values,
+            buckets,
+        })
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.position(key).and_then(|index| self.values.get(index))
+    }
+
+    #[inline]
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.position(key).is_some()
+    }
+
+    /// Returns the index of the given key within the keys and values.
+    pub fn position(&self, key: &K) -> Option<usize> {
+        if self.buckets.is_empty() {
+            return None;
+        }
+
+        let hash = key.static_hash();
+        let bucket = self.buckets.get(hash as usize % self.buckets.len())?;
+
+        (bucket.index..bucket.index + bucket.count)
+            .find(|&i| self.keys.get(i).is_some_and(|k| k == *key))
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (K, V)> + 'a {
+        self.keys.iter().zip(self.values.iter())
+    }
+
+    #[inline]
+    pub fn keys(&self) -> StaticSlice<'a, K> {
+        self.keys
+    }
+
+    #[inline]
+    pub fn values(&self) -> StaticSlice<'a, V> {
+        self.values
+    }
+
+    #[inline]
+    pub fn buckets(&self) -> StaticSlice<'a, StaticMapBucket> {
+        self.buckets
+    }
+
+    /// Decodes all entries into an owned map.
+    pub fn to_static_map(&self) -> Result<StaticMap<K, V>, KFCReadError> {
+        Ok(StaticMap::from_parts(
+            self.keys.to_vec()?,
+            self.values.to_vec()?,
+            self.buckets.to_vec()?,
+        )?)
+    }
+
+}
+
+impl<K, V> Clone for StaticMapView<'_, K, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K, V> Copy for StaticMapView<'_, K, V> {}
+
+impl<K, V> std::fmt::Debug for StaticMapView<'_, K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StaticMapView")
+            .field("keys", &self.keys)
+            .field("values", &self.values)
+            .field("buckets", &self.buckets)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct StaticMapBucket {
     index: usize,
     count: usize,
@@ -124,6 +376,18 @@ pub struct StaticMapBucket {
 
 impl StaticMapBucket {
 
+    /// The index of the first key in this bucket.
+    #[inline]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// The number of keys in this bucket.
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
     #[inline]
     pub fn read<R: Read>(reader: &mut R) -> std::io::Result<Self> {
         let index = reader.read_u32()? as usize;
@@ -142,6 +406,15 @@ impl StaticMapBucket {
 
 }
 
+impl StaticElement for StaticMapBucket {
+    const SIZE: usize = 8;
+
+    #[inline]
+    fn decode(bytes: &[u8]) -> Result<Self, KFCReadError> {
+        Ok(Self::read(&mut &bytes[..])?)
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct StaticMapBuilder<K, V> {
     entries: HashMap<K, V>,
@@ -159,6 +432,16 @@ impl<K: Eq + Hash + Ord + StaticHash, V> StaticMapBuilder<K, V> {
         self.entries.get(key)
     }
 
+    #[inline]
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.entries.remove(key)
+    }
+
+    #[inline]
+    pub fn retain(&mut self, f: impl FnMut(&K, &mut V) -> bool) {
+        self.entries.retain(f);
+    }
+
     #[inline]
     pub fn contains_key(&self, key: &K) -> bool {
         self.entries.contains_key(key)
@@ -271,4 +554,84 @@ mod tests {
         assert_eq!(map.get(&4), None);
     }
 
+    #[test]
+    fn test_static_map_view() {
+        let mut builder = StaticMapBuilder::<u32, u32>::default();
+
+        for i in 0..100 {
+            builder.insert(i * 7, i);
+        }
+
+        let map = builder.build();
+
+        let mut keys = Vec::new();
+        let mut values = Vec::new();
+        let mut buckets = Vec::new();
+
+        for (key, value) in map.iter() {
+            keys.extend_from_slice(&key.to_le_bytes());
+            values.extend_from_slice(&value.to_le_bytes());
+        }
+
+        for bucket in map.buckets() {
+            bucket.write(&mut buckets).unwrap();
+        }
+
+        let view = StaticMapView::<u32, u32>::new(
+            StaticSlice::new(&keys, map.len()).unwrap(),
+            StaticSlice::new(&values, map.len()).unwrap(),
+            StaticSlice::new(&buckets, map.buckets().len()).unwrap(),
+        ).unwrap();
+
+        assert_eq!(view.len(), 100);
+
+        for (key, value) in map.iter() {
+            assert_eq!(view.get(key), Some(*value));
+        }
+
+        assert_eq!(view.get(&1), None);
+        assert_eq!(view.iter().count(), 100);
+
+        // too few bytes for the keys
+        assert!(StaticSlice::<u32>::new(&keys[1..], map.len()).is_none());
+
+        // the bucket of the first key references one key too many
+        let mut broken = buckets.clone();
+        broken[4..8].copy_from_slice(&(map.buckets()[0].count() as u32 + 1).to_le_bytes());
+
+        assert!(StaticMapView::<u32, u32>::new(
+            StaticSlice::new(&keys, map.len()).unwrap(),
+            StaticSlice::new(&values, map.len()).unwrap(),
+            StaticSlice::new(&broken, map.buckets().len()).unwrap(),
+        ).is_err());
+    }
+
+    #[test]
+    fn test_static_map_from_invalid_parts() {
+        let bucket = |index, count| StaticMapBucket { index, count };
+
+        assert!(matches!(
+            StaticMap::<u32, u32>::from_parts(vec![1], vec![1], vec![bucket(1, 1)]),
+            Err(StaticMapError::BucketOutOfBounds { bucket: 0, index: 1, end: 2, len: 1 })
+        ));
+        assert!(matches!(
+            StaticMap::<u32, u32>::from_parts(vec![1], vec![1], vec![bucket(0, usize::MAX)]),
+            Err(StaticMapError::BucketOutOfBounds { .. })
+        ));
+        assert!(matches!(
+            StaticMap::<u32, u32>::from_parts(vec![1], vec![1], vec![]),
+            Err(StaticMapError::BucketCountMismatch(1, 0))
+        ));
+
+        // key 1 belongs to bucket 1, but is stored in bucket 0
+        assert!(matches!(
+            StaticMap::<u32, u32>::from_parts(vec![2, 1], vec![0, 0], vec![bucket(0, 2), bucket(2, 0)]),
+            Err(StaticMapError::MisplacedKey { key: 1, bucket: 0, expected: 1 })
+        ));
+
+        let empty = StaticMap::<u32, u32>::from_parts(vec![], vec![], vec![]).unwrap();
+
+        assert!(!empty.contains_key(&1));
+    }
+
 }
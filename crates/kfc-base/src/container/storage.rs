@@ -0,0 +1,700 @@
+use std::{collections::HashMap, fs::File, io::{Read, Seek, SeekFrom, Write}, path::{Path, PathBuf}, sync::{Arc, Mutex, RwLock}};
+
+use serde::{Deserialize, Serialize};
+
+/// The role of a stream within an archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KFCStream {
+    /// The `.kfc` file containing the index.
+    Index,
+    /// The `.kfc_resources` file containing the compressed resource chunks.
+    Resources,
+    /// A `.dat` file containing contents.
+    Container(usize),
+}
+
+pub trait KFCReadStream: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> KFCReadStream for T {}
+
+pub trait KFCWriteStream: Read + Write + Seek + Send {}
+
+impl<T: Read + Write + Seek + Send> KFCWriteStream for T {}
+
+/// Opens the streams of a single archive by their role.
+pub trait KFCStorage: Send + Sync {
+
+    /// Opens an existing stream for reading.
+    fn open_read(&self, stream: KFCStream) -> std::io::Result<Box<dyn KFCReadStream>>;
+
+    /// Opens an existing stream for reading and writing without truncating it.
+    fn open_write(&self, stream: KFCStream) -> std::io::Result<Box<dyn KFCWriteStream>>;
+
+    /// Creates a stream for reading and writing, truncating it if it already exists.
+    fn create(&self, stream: KFCStream) -> std::io::Result<Box<dyn KFCWriteStream>>;
+
+    fn exists(&self, stream: KFCStream) -> bool;
+
+    fn len(&self, stream: KFCStream) -> std::io::Result<u64> {
+        self.open_read(stream)?.seek(SeekFrom::End(0))
+    }
+
+    /// Truncates or extends an existing stream to the given length.
+    fn set_len(&self, stream: KFCStream, len: u64) -> std::io::Result<()>;
+
+    /// Makes sure all data written to the stream is durably stored.
+    fn sync(&self, stream: KFCStream) -> std::io::Result<()>;
+
+    /// Creates a staged version of the stream, which is invisible to readers until it is committed.
+    /// Any previously staged version is truncated.
+    fn create_staged(&self, stream: KFCStream) -> std::io::Result<Box<dyn KFCWriteStream>>;
+
+    /// Durably replaces the stream with its staged version.
+    fn commit_staged(&self, stream: KFCStream) -> std::io::Result<()>;
+
+    /// Removes the staged version of the stream, if any.
+    fn discard_staged(&self, stream: KFCStream) -> std::io::Result<()>;
+
+    /// Returns the path of the stream if it is backed by a file,
+    /// which allows it to be memory-mapped.
+    fn path(&self, _stream: KFCStream) -> Option<PathBuf> {
+        None
+    }
+
+}
+
+impl<S: KFCStorage + ?Sized> KFCStorage for Arc<S> {
+
+    #[inline]
+    fn open_read(&self, stream: KFCStream) -> std::io::Result<Box<dyn KFCReadStream>> {
+        (**self).open_read(stream)
+    }
+
+    #[inline]
+    fn open_write(&self, stream: KFCStream) -> std::io::Result<Box<dyn KFCWriteStream>> {
+        (**self).open_write(stream)
+    }
+
+    #[inline]
+    fn create(&self, stream: KFCStream) -> std::io::Result<Box<dyn KFCWriteStream>> {
+        (**self).create(stream)
+    }
+
+    #[inline]
+    fn exists(&self, stream: KFCStream) -> bool {
+        (**self).exists(stream)
+    }
+
+    #[inline]
+    fn len(&self, stream: KFCStream) -> std::io::Result<u64> {
+        (**self).len(stream)
+    }
+
+    #[inline]
+    fn set_len(&self, stream: KFCStream, len: u64) -> std::io::Result<()> {
+        (**self).set_len(stream, len)
+    }
+
+    #[inline]
+    fn sync(&self, stream: KFCStream) -> std::io::Result<()> {
+        (**self).sync(stream)
+    }
+
+    #[inline]
+    fn create_staged(&self, stream: KFCStream) -> std::io::Result<Box<dyn KFCWriteStream>> {
+        (**self).create_staged(stream)
+    }
+
+    #[inline]
+    fn commit_staged(&self, stream: KFCStream) -> std::io::Result<()> {
+        (**self).commit_staged(stream)
+    }
+
+    #[inline]
+    fn discard_staged(&self, stream: KFCStream) -> std::io::Result<()> {
+        (**self).discard_staged(stream)
+    }
+
+    #[inline]
+    fn path(&self, stream: KFCStream) -> Option<PathBuf> {
+        (**self).path(stream)
+    }
+
+}
+
+/// Stores an archive as files in a directory, which is the layout used by the game.
+#[derive(Debug, Clone)]
+pub struct DiskStorage {
+    path: PathBuf,
+    file_name: String,
+
+    kfc_extension: String,
+    resource_extension: String,
+    dat_extension: String,
+}
+
+impl DiskStorage {
+
+    pub fn new(
+        path: impl AsRef<Path>,
+        file_name: impl AsRef<str>,
+    ) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            file_name: file_name.as_ref().to_string(),
+
+            kfc_extension: "kfc".to_string(),
+            resource_extension: "kfc_resources".to_string(),
+            dat_extension: "dat".to_string(),
+        }
+    }
+
+    pub fn with_extensions(
+        mut self,
+        kfc_extension: impl Into<String>,
+        resource_extension: impl Into<String>,
+        dat_extension: impl Into<String>,
+    ) -> Self {
+        self.kfc_extension = kfc_extension.into();
+        self.resource_extension = resource_extension.into();
+        self.dat_extension = dat_extension.into();
+        self
+    }
+
+    #[inline]
+    pub fn directory(&self) -> &Path {
+        &self.path
+    }
+
+    #[inline]
+    pub fn file_name(&self) -> &str {
+        &self.file_name
+    }
+
+    pub fn stream_path(&self, stream: KFCStream) -> PathBuf {
+        let name = match stream {
+            KFCStream::Index => format!("{}.{}", self.file_name, self.kfc_extension),
+            KFCStream::Resources => format!("{}.{}", self.file_name, self.resource_extension),
+            // Format: FILE_NAME_{INDEX}.dat where INDEX is 3 digits with leading zeros
+            KFCStream::Container(index) => format!("{}_{:03}.{}", self.file_name, index, self.dat_extension),
+        };
+
+        self.path.join(name)
+    }
+
+    /// Returns the path of the staged version of the stream.
+    pub fn staged_path(&self, stream: KFCStream) -> PathBuf {
+        let mut path = self.stream_path(stream).into_os_string();
+        path.push(".tmp");
+        path.into()
+    }
+
+}
+
+impl KFCStorage for DiskStorage {
+
+    fn open_read(&self, stream: KFCStream) -> std::io::Result<Box<dyn KFCReadStream>> {
+        Ok(Box::new(File::open(self.stream_path(stream))?))
+    }
+
+    fn open_write(&self, stream: KFCStream) -> std::io::Result<Box<dyn KFCWriteStream>> {
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .open(self.stream_path(stream))?;
+
+        Ok(Box::new(file))
+    }
+
+    fn create(&self, stream: KFCStream) -> std::io::Result<Box<dyn KFCWriteStream>> {
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(self.stream_path(stream))?;
+
+        Ok(Box::new(file))
+    }
+
+    fn exists(&self, stream: KFCStream) -> bool {
+        self.stream_path(stream).exists()
+    }
+
+    fn len(&self, stream: KFCStream) -> std::io::Result<u64> {
+        Ok(std::fs::metadata(self.stream_path(stream))?.len())
+    }
+
+    fn set_len(&self, stream: KFCStream, len: u64) -> std::io::Result<()> {
+        File::options()
+            .write(true)
+            .open(self.stream_path(stream))?
+            .set_len(len)
+    }
+
+    fn sync(&self, stream: KFCStream) -> std::io::Result<()> {
+        // Windows requires write access to flush a file
+        File::options()
+            .write(true)
+            .open(self.stream_path(stream))?
+            .sync_all()
+    }
+
+    fn create_staged(&self, stream: KFCStream) -> std::io::Result<Box<dyn KFCWriteStream>> {
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(self.staged_path(stream))?;
+
+        Ok(Box::new(file))
+    }
+
+    fn commit_staged(&self, stream: KFCStream) -> std::io::Result<()> {
+        let staged_path = self.staged_path(stream);
+
+        File::options()
+            .write(true)
+            .open(&staged_path)?
+            .sync_all()?;
+
+        std::fs::rename(&staged_path, self.stream_path(stream))?;
+
+        sync_dir(&self.path)
+    }
+
+    fn discard_staged(&self, stream: KFCStream) -> std::io::Result<()> {
+        match std::fs::remove_file(self.staged_path(stream)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn path(&self, stream: KFCStream) -> Option<PathBuf> {
+        Some(self.stream_path(stream))
+    }
+
+}
+
+/// Makes renames within the directory durable.
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> std::io::Result<()> {
+    let path = if path.as_os_str().is_empty() { Path::new(".") } else { path };
+
+    File::open(path)?.sync_all()
+}
+
+/// Directories can not be opened as files on Windows,
+/// where renames are flushed with the file system metadata.
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+type MemoryStreams = HashMap<KFCStream, Arc<RwLock<Vec<u8>>>>;
+
+/// Stores an archive in memory.
+///
+/// Clones share the same streams.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    streams: Arc<Mutex<MemoryStreams>>,
+    staged: Arc<Mutex<MemoryStreams>>,
+}
+
+impl MemoryStorage {
+
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the data of the given stream.
+    pub fn insert(&self, stream: KFCStream, data: Vec<u8>) {
+        self.lock().insert(stream, Arc::new(RwLock::new(data)));
+    }
+
+    /// Returns a copy of the data of the given stream.
+    pub fn get(&self, stream: KFCStream) -> Option<Vec<u8>> {
+        let data = self.lock().get(&stream).cloned()?;
+        let data = data.read().unwrap_or_else(|e| e.into_inner());
+
+        Some(data.clone())
+    }
+
+    pub fn remove(&self, stream: KFCStream) -> Option<Vec<u8>> {
+        let data = self.lock().remove(&stream)?;
+        let mut data = data.write().unwrap_or_else(|e| e.into_inner());
+
+        Some(std::mem::take(&mut *data))
+    }
+
+    fn open(&self, stream: KFCStream) -> std::io::Result<MemoryStream> {
+        match self.lock().get(&stream) {
+            Some(data) => Ok(MemoryStream::new(data.clone())),
+            None => Err(not_found(stream)),
+        }
+    }
+
+    #[inline]
+    fn lock(&self) -> std::sync::MutexGuard<'_, MemoryStreams> {
+        self.streams.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    #[inline]
+    fn lock_staged(&self) -> std::sync::MutexGuard<'_, MemoryStreams> {
+        self.staged.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+}
+
+impl KFCStorage for MemoryStorage {
+
+    fn open_read(&self, stream: KFCStream) -> std::io::Result<Box<dyn KFCReadStream>> {
+        Ok(Box::new(self.open(stream)?))
+    }
+
+    fn open_write(&self, stream: KFCStream) -> std::io::Result<Box<dyn KFCWriteStream>> {
+        Ok(Box::new(self.open(stream)?))
+    }
+
+    fn create(&self, stream: KFCStream) -> std::io::Result<Box<dyn KFCWriteStream>> {
+        let data = Arc::new(RwLock::new(Vec::new()));
+
+        self.lock().insert(stream, data.clone());
+
+        Ok(Box::new(MemoryStream::new(data)))
+    }
+
+    fn exists(&self, stream: KFCStream) -> bool {
+        self.lock().contains_key(&stream)
+    }
+
+    fn len(&self, stream: KFCStream) -> std::io::Result<u64> {
+        let data = self.lock().get(&stream).cloned().ok_or_else(|| not_found(stream))?;
+        let len = data.read().unwrap_or_else(|e| e.into_inner()).len();
+
+        Ok(len as u64)
+    }
+
+    fn set_len(&self, stream: KFCStream, len: u64) -> std::io::Result<()> {
+        let data = self.lock().get(&stream).cloned().ok_or_else(|| not_found(stream))?;
+
+        data.write().unwrap_or_else(|e| e.into_inner()).resize(len as usize, 0);
+
+        Ok(())
+    }
+
+    fn sync(&self, stream: KFCStream) -> std::io::Result<()> {
+        match self.exists(stream) {
+            true => Ok(()),
+            false => Err(not_found(stream)),
+        }
+    }
+
+    fn create_staged(&self, stream: KFCStream) -> std::io::Result<Box<dyn KFCWriteStream>> {
+        let data = Arc::new(RwLock::new(Vec::new()));
+
+        self.lock_staged().insert(stream, data.clone());
+
+        Ok(Box::new(MemoryStream::new(data)))
+    }
+
+    fn commit_staged(&self, stream: KFCStream) -> std::io::Result<()> {
+        let data = self.lock_staged().remove(&stream).ok_or_else(|| not_found(stream))?;
+
+        self.lock().insert(stream, data);
+
+        Ok(())
+    }
+
+    fn discard_staged(&self, stream: KFCStream) -> std::io::Result<()> {
+        self.lock_staged().remove(&stream);
+
+        Ok(())
+    }
+
+}
+
+/// A read and write cursor over a stream of a [`MemoryStorage`].
+struct MemoryStream {
+    data: Arc<RwLock<Vec<u8>>>,
+    position: u64,
+}
+
+impl MemoryStream {
+
+    #[inline]
+    fn new(data: Arc<RwLock<Vec<u8>>>) -> Self {
+        Self {
+            data,
+            position: 0,
+        }
+    }
+
+}
+
+impl Read for MemoryStream {
+
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let data = self.data.read().unwrap_or_else(|e| e.into_inner());
+        let start = (self.position as usize).min(data.len());
+        let len = buf.len().min(data.len() - start);
+
+        buf[..len].copy_from_slice(&data[start..start + len]);
+        self.position += len as u64;
+
+        Ok(len)
+    }
+
+}
+
+impl Write for MemoryStream {
+
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut data = self.data.write().unwrap_or_else(|e| e.into_inner());
+        let start = self.position as usize;
+        let end = start + buf.len();
+
+        if data.len() < end {
+            data.resize(end, 0);
+        }
+
+        data[start..end].copy_from_slice(buf);
+        self.position = end as u64;
+
+        Ok(buf.len())
+    }
+
+    #[inline]
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+}
+
+impl Seek for MemoryStream {
+
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let len = self.data.read().unwrap_or_else(|e| e.into_inner()).len() as u64;
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+
+        match position {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            },
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position"
+            )),
+        }
+    }
+
+}
+
+/// Layers a writable storage on top of a read-only base storage.
+///
+/// Streams are read from the overlay if they exist there and from the base otherwise.
+/// All writes go to the overlay, so the base is never modified.
+/// Opening a base stream for writing copies it into the overlay first.
+pub struct OverlayStorage<B, O> {
+    base: B,
+    overlay: O,
+}
+
+impl<B, O> OverlayStorage<B, O>
+where
+    B: KFCStorage,
+    O: KFCStorage,
+{
+
+    #[inline]
+    pub fn new(base: B, overlay: O) -> Self {
+        Self {
+            base,
+            overlay,
+        }
+    }
+
+    #[inline]
+    pub fn base(&self) -> &B {
+        &self.base
+    }
+
+    #[inline]
+    pub fn overlay(&self) -> &O {
+        &self.overlay
+    }
+
+}
+
+impl<B, O> KFCStorage for OverlayStorage<B, O>
+where
+    B: KFCStorage,
+    O: KFCStorage,
+{
+
+    fn open_read(&self, stream: KFCStream) -> std::io::Result<Box<dyn KFCReadStream>> {
+        if self.overlay.exists(stream) {
+            self.overlay.open_read(stream)
+        } else {
+            self.base.open_read(stream)
+        }
+    }
+
+    fn open_write(&self, stream: KFCStream) -> std::io::Result<Box<dyn KFCWriteStream>> {
+        if !self.overlay.exists(stream) {
+            let mut src = self.base.open_read(stream)?;
+            let mut dst = self.overlay.create(stream)?;
+
+            std::io::copy(&mut src, &mut dst)?;
+            dst.flush()?;
+        }
+
+        self.overlay.open_write(stream)
+    }
+
+    #[inline]
+    fn create(&self, stream: KFCStream) -> std::io::Result<Box<dyn KFCWriteStream>> {
+        self.overlay.create(stream)
+    }
+
+    #[inline]
+    fn exists(&self, stream: KFCStream) -> bool {
+        self.overlay.exists(stream) || self.base.exists(stream)
+    }
+
+    fn len(&self, stream: KFCStream) -> std::io::Result<u64> {
+        if self.overlay.exists(stream) {
+            self.overlay.len(stream)
+        } else {
+            self.base.len(stream)
+        }
+    }
+
+    fn set_len(&self, stream: KFCStream, len: u64) -> std::io::Result<()> {
+        drop(self.open_write(stream)?);
+
+        self.overlay.set_len(stream, len)
+    }
+
+    fn sync(&self, stream: KFCStream) -> std::io::Result<()> {
+        // streams which are not in the overlay were never written
+        if self.overlay.exists(stream) {
+            self.overlay.sync(stream)
+        } else {
+            Ok(())
+        }
+    }
+
+    #[inline]
+    fn create_staged(&self, stream: KFCStream) -> std::io::Result<Box<dyn KFCWriteStream>> {
+        self.overlay.create_staged(stream)
+    }
+
+    #[inline]
+    fn commit_staged(&self, stream: KFCStream) -> std::io::Result<()> {
+        self.overlay.commit_staged(stream)
+    }
+
+    #[inline]
+    fn discard_staged(&self, stream: KFCStream) -> std::io::Result<()> {
+        self.overlay.discard_staged(stream)
+    }
+
+    fn path(&self, stream: KFCStream) -> Option<PathBuf> {
+        if self.overlay.exists(stream) {
+            self.overlay.path(stream)
+        } else {
+            self.base.path(stream)
+        }
+    }
+
+}
+
+fn not_found(stream: KFCStream) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("stream {stream:?} does not exist")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{container::{KFCReader, KFCVerifyOptions, KFCWriteOptions, KFCWriter}, testing::*};
+
+    use super::*;
+
+    #[test]
+    fn test_memory_storage() -> Result<(), Box<dyn std::error::Error>> {
+        let storage = MemoryStorage::new();
+        let mut builder = TestArchiveBuilder::new(sample_type_registry());
+
+        builder.add_resource(sample_resource_id(0, SAMPLE_TYPE_A), sample_resource_data(0, 5000));
+        let hash = builder.add_content(sample_resource_data(1, 6000));
+
+        builder.build_in_storage(storage.clone())?;
+
+        let kfc_reader = KFCReader::from_storage(storage.clone())?;
+
+        assert!(kfc_reader.storage().path(KFCStream::Index).is_none());
+        assert!(storage.exists(KFCStream::Container(0)));
+
+        let mut cursor = kfc_reader.new_cursor()?;
+        let mapped_cursor = kfc_reader.new_mapped_cursor()?;
+
+        assert_eq!(cursor.read_resource(&sample_resource_id(0, SAMPLE_TYPE_A))?.as_deref(), Some(&sample_resource_data(0, 5000)[..]));
+        assert_eq!(mapped_cursor.read_content(&hash)?, Some(&sample_resource_data(1, 6000)[..]));
+        assert!(kfc_reader.verify(&KFCVerifyOptions::default())?.is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_overlay_storage() -> Result<(), Box<dyn std::error::Error>> {
+        let base = sample_archive(4);
+        let base_storage = DiskStorage::new(base.path(), base.file_name());
+        let base_index = std::fs::read(base.kfc_path())?;
+        let reference = base.reader()?.file().clone();
+
+        let overlay = MemoryStorage::new();
+        let storage = Arc::new(OverlayStorage::new(base_storage, overlay.clone()));
+
+        let mut writer = KFCWriter::new_incremental_with_storage(
+            storage.clone(),
+            &reference,
+            base.type_registry(),
+            KFCWriteOptions {
+                resource_chunk_size: 4096,
+                ..Default::default()
+            },
+        )?;
+
+        writer.write_resource(&sample_resource_id(200, SAMPLE_TYPE_A), &sample_resource_data(200, 3000))?;
+        writer.finalize()?;
+
+        // the base archive is untouched, all changes live in the overlay
+
+        assert_eq!(std::fs::read(base.kfc_path())?, base_index);
+        assert!(overlay.exists(KFCStream::Index));
+        assert!(overlay.exists(KFCStream::Resources));
+
+        let kfc_reader = KFCReader::from_storage(storage)?;
+        let mut cursor = kfc_reader.new_cursor()?;
+
+        assert_eq!(kfc_reader.file().resources().len(), 33);
+        assert_eq!(cursor.read_resource(&sample_resource_id(200, SAMPLE_TYPE_A))?.as_deref(), Some(&sample_resource_data(200, 3000)[..]));
+        assert_eq!(cursor.read_resource(&sample_resource_id(3, SAMPLE_TYPE_B))?.as_deref(), Some(&sample_resource_data(3, 3 * 997)[..]));
+        assert_eq!(base.reader()?.file().resources().len(), 32);
+
+        Ok(())
+    }
+
+}
@@ -0,0 +1,640 @@
+use std::{collections::HashMap, fmt::Display, io::{BufReader, Cursor, Read, Seek, SeekFrom}};
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::{guid::{ContentHash, ResourceId}, Hash32};
+
+use super::{header::{KFCHeader, KFCLocation}, reader::ResourceSpan, view::section, KFCFile, KFCReadError, KFCReader, KFCStorage, KFCStream, StaticElement, StaticHash, StaticMapBucket};
+
+#[derive(Debug, Clone)]
+pub struct KFCVerifyOptions {
+    /// Decompresses every resource chunk and checks its size.
+    pub verify_resource_chunks: bool,
+    /// Reads every content and checks it against its hash.
+    /// **NOTE:** This reads all .dat files and is by far the slowest check.
+    pub verify_content_hashes: bool,
+}
+
+impl Default for KFCVerifyOptions {
+    fn default() -> Self {
+        Self {
+            verify_resource_chunks: true,
+            verify_content_hashes: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KFCVerifySeverity {
+    /// The archive is unusual, but the game can still read it.
+    Warning,
+    /// The archive is broken and will likely crash the game.
+    Error,
+}
+
+#[derive(Debug, Clone, Error, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum KFCVerifyIssue {
+    #[error("Header size {size} exceeds the file size {file_size}")]
+    HeaderSizeOutOfBounds {
+        size: u64,
+        file_size: u64,
+    },
+    #[error("Header location `{name}` ({offset:#X}, {size} bytes) exceeds the file size {file_size}")]
+    LocationOutOfBounds {
+        name: &'static str,
+        offset: u64,
+        size: u64,
+        file_size: u64,
+    },
+
+    #[error("{map} map: bucket {bucket} ({index}..{end}) exceeds the key count {len}")]
+    BucketOutOfBounds {
+        map: &'static str,
+        bucket: usize,
+        index: usize,
+        end: usize,
+        len: usize,
+    },
+    #[error("{map} map: key {key} is stored in bucket {bucket}, but belongs to bucket {expected}")]
+    MisplacedKey {
+        map: &'static str,
+        key: String,
+        bucket: usize,
+        expected: usize,
+    },
+
+    #[error("Resource chunk {chunk} starts at uncompressed offset {uncompressed_offset}, but the previous chunk ends at {expected}")]
+    ChunkNotContiguous {
+        chunk: usize,
+        uncompressed_offset: u64,
+        expected: u64,
+    },
+    #[error("Resource chunk {chunk} (uncompressed {uncompressed_offset:#X}, {uncompressed_size} bytes) ends beyond the addressable range")]
+    ChunkOverflow {
+        chunk: usize,
+        uncompressed_offset: u64,
+        uncompressed_size: u64,
+    },
+    #[error("Resource chunk {chunk} ({offset:#X}, {size} bytes) exceeds the resource file size {file_size}")]
+    ChunkOutOfBounds {
+        chunk: usize,
+        offset: u64,
+        size: u64,
+        file_size: u64,
+    },
+    #[error("Resource chunk {chunk} could not be decompressed: {message}")]
+    ChunkDecompression {
+        chunk: usize,
+        message: String,
+    },
+    #[error("Resource chunk {chunk} decompressed to {actual} bytes, but expected {expected}")]
+    ChunkSizeMismatch {
+        chunk: usize,
+        expected: u64,
+        actual: u64,
+    },
+    #[error("Resource {resource} ({offset:#X}, {size} bytes) is not covered by the resource chunks")]
+    ResourceNotCovered {
+        resource: ResourceId,
+        offset: u64,
+        size: u64,
+    },
+
+    #[error("Container {container} is missing")]
+    MissingContainer {
+        container: usize,
+        expected_size: u64,
+    },
+    #[error("Container {container} has {actual} bytes, but expected {expected}")]
+    ContainerSizeMismatch {
+        container: usize,
+        expected: u64,
+        actual: u64,
+    },
+    #[error("Container {container} holds {actual} contents, but expected {expected}")]
+    ContainerCountMismatch {
+        container: usize,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("Content {content} references the unknown container {container}")]
+    UnknownContainer {
+        content: ContentHash,
+        container: usize,
+    },
+    #[error("Content {content} ({offset:#X}, {size} bytes) exceeds the size {container_size} of container {container}")]
+    ContentOutOfBounds {
+        content: ContentHash,
+        container: usize,
+        offset: u64,
+        size: u64,
+        container_size: u64,
+    },
+    #[error("Content {content} does not match its data, which hashes to {actual}")]
+    ContentHashMismatch {
+        content: ContentHash,
+        actual: ContentHash,
+    },
+}
+
+impl KFCVerifyIssue {
+
+    pub fn severity(&self) -> KFCVerifySeverity {
+        match self {
+            // leftover data from previous writes is ignored by the game
+            Self::ContainerSizeMismatch { expected, actual, .. } if actual > expected => KFCVerifySeverity::Warning,
+            // the writer pads the container count with empty files
+            Self::MissingContainer { expected_size: 0, .. } => KFCVerifySeverity::Warning,
+            _ => KFCVerifySeverity::Error,
+        }
+    }
+
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct KFCVerifyReport {
+    pub resources: usize,
+    pub resource_chunks: usize,
+    pub contents: usize,
+    pub containers: usize,
+
+    pub issues: Vec<KFCVerifyIssue>,
+}
+
+impl KFCVerifyReport {
+
+    /// Returns true if no errors were found. Warnings are ignored.
+    #[inline]
+    pub fn is_ok(&self) -> bool {
+        self.errors().next().is_none()
+    }
+
+    #[inline]
+    pub fn errors(&self) -> impl Iterator<Item = &KFCVerifyIssue> {
+        self.issues.iter().filter(|issue| issue.severity() == KFCVerifySeverity::Error)
+    }
+
+    #[inline]
+    pub fn warnings(&self) -> impl Iterator<Item = &KFCVerifyIssue> {
+        self.issues.iter().filter(|issue| issue.severity() == KFCVerifySeverity::Warning)
+    }
+
+}
+
+/// Checks the archive of the given reader for consistency.
+///
+/// Problems with the archive are collected in the report,
+/// only I/O errors abort the verification.
+pub fn verify_archive(
+    reader: &KFCReader,
+    options: &KFCVerifyOptions,
+) -> Result<KFCVerifyReport, KFCReadError> {
+    let mut issues = Vec::new();
+
+    verify_index(&read_index(reader.storage().as_ref())?, &mut issues)?;
+
+    verify_file(reader.file(), reader.storage().as_ref(), options, issues)
+}
+
+/// Checks the archive in the given storage for consistency, see [`verify_archive`].
+///
+/// Unlike [`verify_archive`], this also reports problems with the index
+/// which prevent it from being parsed, in which case only the index is checked.
+pub fn verify_storage(
+    storage: &dyn KFCStorage,
+    options: &KFCVerifyOptions,
+) -> Result<KFCVerifyReport, KFCReadError> {
+    let data = read_index(storage)?;
+    let mut issues = Vec::new();
+
+    verify_index(&data, &mut issues)?;
+
+    let file = match KFCFile::from_reader(&mut Cursor::new(&data), false) {
+        Ok(file) => file,
+        Err(_) if !issues.is_empty() => {
+            return Ok(KFCVerifyReport {
+                issues,
+                ..Default::default()
+            });
+        },
+        Err(e) => return Err(e),
+    };
+
+    verify_file(&file, storage, options, issues)
+}
+
+fn read_index(storage: &dyn KFCStorage) -> Result<Vec<u8>, KFCReadError> {
+    let mut data = Vec::new();
+
+    storage.open_read(KFCStream::Index)?.read_to_end(&mut data)?;
+
+    Ok(data)
+}
+
+fn verify_file(
+    file: &KFCFile,
+    storage: &dyn KFCStorage,
+    options: &KFCVerifyOptions,
+    issues: Vec<KFCVerifyIssue>,
+) -> Result<KFCVerifyReport, KFCReadError> {
+    let mut report = KFCVerifyReport {
+        resources: file.resources().len(),
+        resource_chunks: file.resource_chunks().len(),
+        contents: file.contents().len(),
+        containers: file.containers().len(),
+        issues,
+    };
+
+    verify_resources(file, storage, options, &mut report.issues)?;
+    verify_contents(file, storage, options, &mut report.issues)?;
+
+    Ok(report)
+}
+
+/// Checks the header and static maps of the raw index,
+/// since the parser rejects indices with these problems.
+fn verify_index(
+    data: &[u8],
+    issues: &mut Vec<KFCVerifyIssue>,
+) -> Result<(), KFCReadError> {
+    let file_size = data.len() as u64;
+    let header = KFCHeader::read(&mut Cursor::new(data))?;
+
+    if header.size > file_size {
+        issues.push(KFCVerifyIssue::HeaderSizeOutOfBounds {
+            size: header.size,
+            file_size,
+        });
+    }
+
+    for (name, location, element_size) in header.sections() {
+        let size = (location.count as u64).saturating_mul(element_size);
+
+        if location.end(element_size).is_none_or(|end| end > file_size) {
+            issues.push(KFCVerifyIssue::LocationOutOfBounds {
+                name,
+                offset: location.offset,
+                size,
+                file_size,
+            });
+        }
+    }
+
+    verify_static_map::<ContentHash>("content", data, &header.content_keys, &header.content_buckets, issues);
+    verify_static_map::<ResourceId>("resource", data, &header.resource_keys, &header.resource_buckets, issues);
+    verify_static_map::<Hash32>("resource bundle", data, &header.resource_bundle_keys, &header.resource_bundle_buckets, issues);
+
+    Ok(())
+}
+
+fn verify_static_map<K>(
+    map: &'static str,
+    data: &[u8],
+    keys: &KFCLocation,
+    buckets: &KFCLocation,
+    issues: &mut Vec<KFCVerifyIssue>,
+)
+where
+    K: StaticElement + StaticHash + Display,
+{
+    // sections outside of the file were already reported
+    let (Ok(keys), Ok(buckets)) = (
+        section::<K>(data, map, keys),
+        section::<StaticMapBucket>(data, map, buckets),
+    ) else {
+        return;
+    };
+
+    for (bucket_index, bucket) in buckets.iter().enumerate() {
+        let end = bucket.index().saturating_add(bucket.count());
+
+        if end > keys.len() {
+            issues.push(KFCVerifyIssue::BucketOutOfBounds {
+                map,
+                bucket: bucket_index,
+                index: bucket.index(),
+                end,
+                len: keys.len(),
+            });
+            continue;
+        }
+
+        for key in (bucket.index()..end).filter_map(|index| keys.get(index)) {
+            let expected = key.static_hash() as usize % buckets.len();
+
+            if expected != bucket_index {
+                issues.push(KFCVerifyIssue::MisplacedKey {
+                    map,
+                    key: key.to_string(),
+                    bucket: bucket_index,
+                    expected,
+                });
+            }
+        }
+    }
+}
+
+fn verify_resources(
+    file: &KFCFile,
+    storage: &dyn KFCStorage,
+    options: &KFCVerifyOptions,
+    issues: &mut Vec<KFCVerifyIssue>,
+) -> Result<(), KFCReadError> {
+    // chunks must form a contiguous uncompressed stream
+
+    let mut expected_offset = file.resource_chunks()
+        .first()
+        .map(|chunk| chunk.uncompressed_offset)
+        .unwrap_or(0);
+
+    for (index, chunk) in file.resource_chunks().iter().enumerate() {
+        if chunk.uncompressed_offset != expected_offset {
+            issues.push(KFCVerifyIssue::ChunkNotContiguous {
+                chunk: index,
+                uncompressed_offset: chunk.uncompressed_offset,
+                expected: expected_offset,
+            });
+        }
+
+        expected_offset = match chunk.uncompressed_offset.checked_add(chunk.uncompressed_size) {
+            Some(end) => end,
+            None => {
+                issues.push(KFCVerifyIssue::ChunkOverflow {
+                    chunk: index,
+                    uncompressed_offset: chunk.uncompressed_offset,
+                    uncompressed_size: chunk.uncompressed_size,
+                });
+                u64::MAX
+            },
+        };
+    }
+
+    for (id, entry) in file.resources().iter() {
+        if ResourceSpan::locate(file, id).is_none() {
+            issues.push(KFCVerifyIssue::ResourceNotCovered {
+                resource: *id,
+                offset: entry.offset,
+                size: entry.size,
+            });
+        }
+    }
+
+    // chunks must be stored inside the resource file
+
+    let mut resource_reader = BufReader::new(storage.open_read(KFCStream::Resources)?);
+    let file_size = resource_reader.seek(SeekFrom::End(0))?;
+    let mut buffer = Vec::new();
+
+    for (index, chunk) in file.resource_chunks().iter().enumerate() {
+        if chunk.offset.checked_add(chunk.compressed_size).is_none_or(|end| end > file_size) {
+            issues.push(KFCVerifyIssue::ChunkOutOfBounds {
+                chunk: index,
+                offset: chunk.offset,
+                size: chunk.compressed_size,
+                file_size,
+            });
+            continue;
+        }
+
+        if !options.verify_resource_chunks {
+            continue;
+        }
+
+        buffer.resize(chunk.compressed_size as usize, 0);
+        resource_reader.seek(SeekFrom::Start(chunk.offset))?;
+        resource_reader.read_exact(&mut buffer)?;
+
+        match zstd::stream::decode_all(&buffer[..]) {
+            Ok(data) if data.len() as u64 != chunk.uncompressed_size => {
+                issues.push(KFCVerifyIssue::ChunkSizeMismatch {
+                    chunk: index,
+                    expected: chunk.uncompressed_size,
+                    actual: data.len() as u64,
+                });
+            },
+            Ok(_) => {},
+            Err(e) => {
+                issues.push(KFCVerifyIssue::ChunkDecompression {
+                    chunk: index,
+                    message: e.to_string(),
+                });
+            },
+        }
+    }
+
+    Ok(())
+}
+
+fn verify_contents(
+    file: &KFCFile,
+    storage: &dyn KFCStorage,
+    options: &KFCVerifyOptions,
+    issues: &mut Vec<KFCVerifyIssue>,
+) -> Result<(), KFCReadError> {
+    // container infos
+
+    let mut counts = HashMap::<usize, usize>::new();
+
+    for entry in file.contents().values() {
+        *counts.entry(entry.container_index).or_default() += 1;
+    }
+
+    let mut container_readers = Vec::with_capacity(file.containers().len());
+
+    for (index, info) in file.containers().iter().enumerate() {
+        let actual_count = counts.get(&index).copied().unwrap_or(0);
+
+        if actual_count != info.count {
+            issues.push(KFCVerifyIssue::ContainerCountMismatch {
+                container: index,
+                expected: info.count,
+                actual: actual_count,
+            });
+        }
+
+        let stream = KFCStream::Container(index);
+        let container = match storage.open_read(stream) {
+            Ok(container) => container,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                issues.push(KFCVerifyIssue::MissingContainer {
+                    container: index,
+                    expected_size: info.size,
+                });
+                container_readers.push(None);
+                continue;
+            },
+            Err(e) => return Err(e.into()),
+        };
+
+        let size = storage.len(stream)?;
+
+        if size != info.size {
+            issues.push(KFCVerifyIssue::ContainerSizeMismatch {
+                container: index,
+                expected: info.size,
+                actual: size,
+            });
+        }
+
+        container_readers.push(Some((BufReader::new(container), size)));
+    }
+
+    // content entries
+
+    let mut buffer = Vec::new();
+
+    for (hash, entry) in file.contents().iter() {
+        let (container_reader, container_size) = match container_readers.get_mut(entry.container_index) {
+            Some(Some((container_reader, size))) => (container_reader, *size),
+            Some(None) => continue, // already reported as missing
+            None => {
+                issues.push(KFCVerifyIssue::UnknownContainer {
+                    content: *hash,
+                    container: entry.container_index,
+                });
+                continue;
+            },
+        };
+
+        let size = hash.size() as u64;
+
+        if entry.offset.checked_add(size).is_none_or(|end| end > container_size) {
+            issues.push(KFCVerifyIssue::ContentOutOfBounds {
+                content: *hash,
+                container: entry.container_index,
+                offset: entry.offset,
+                size,
+                container_size,
+            });
+            continue;
+        }
+
+        if !options.verify_content_hashes {
+            continue;
+        }
+
+        buffer.resize(size as usize, 0);
+        container_reader.seek(SeekFrom::Start(entry.offset))?;
+        container_reader.read_exact(&mut buffer)?;
+
+        let actual = ContentHash::from_data(&buffer);
+
+        if actual != *hash {
+            issues.push(KFCVerifyIssue::ContentHashMismatch {
+                content: *hash,
+                actual,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use crate::{container::{header::ContentEntry, DiskStorage, MemoryStorage, OverlayStorage}, testing::*};
+
+    use super::*;
+
+    /// Verifies a copy of the archive with the given changes to its index.
+    fn verify_damaged_index(
+        archive: &TestArchive,
+        damage: impl FnOnce(&KFCHeader, &mut Vec<u8>),
+    ) -> Result<KFCVerifyReport, Box<dyn std::error::Error>> {
+        let mut data = std::fs::read(archive.kfc_path())?;
+        let header = KFCHeader::read(&mut Cursor::new(&data))?;
+
+        damage(&header, &mut data);
+
+        let storage = OverlayStorage::new(
+            DiskStorage::new(archive.path(), archive.file_name()),
+            MemoryStorage::new(),
+        );
+
+        storage.create(KFCStream::Index)?.write_all(&data)?;
+
+        Ok(verify_storage(&storage, &KFCVerifyOptions::default())?)
+    }
+
+    fn set_u32(data: &mut [u8], offset: usize, value: u32) {
+        data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    #[test]
+    fn test_verify_damaged_index() -> Result<(), Box<dyn std::error::Error>> {
+        let archive = sample_archive(1);
+
+        let report = verify_damaged_index(&archive, |_, _| {})?;
+        assert!(report.is_ok(), "{:?}", report.issues);
+
+        let report = verify_damaged_index(&archive, |_, data| set_u32(data, 4, u32::MAX))?;
+        assert!(report.issues.iter().any(|issue| matches!(issue, KFCVerifyIssue::HeaderSizeOutOfBounds { .. })), "{:?}", report.issues);
+
+        // the count of the containers location, which follows the version location
+        let report = verify_damaged_index(&archive, |_, data| set_u32(data, 28, u32::MAX))?;
+        assert!(report.issues.iter().any(|issue| matches!(issue, KFCVerifyIssue::LocationOutOfBounds { name: "containers", .. })), "{:?}", report.issues);
+
+        let report = verify_damaged_index(&archive, |header, data| {
+            set_u32(data, header.content_buckets.offset as usize + 4, u32::MAX);
+        })?;
+        assert!(report.issues.iter().any(|issue| matches!(issue, KFCVerifyIssue::BucketOutOfBounds { map: "content", bucket: 0, .. })), "{:?}", report.issues);
+
+        // the first and the last key are in different buckets
+        let report = verify_damaged_index(&archive, |header, data| {
+            let keys = header.content_keys.offset as usize;
+            let last = keys + (header.content_keys.count - 1) * ContentHash::SIZE;
+
+            data.copy_within(last..last + ContentHash::SIZE, keys);
+        })?;
+        assert!(report.issues.iter().any(|issue| matches!(issue, KFCVerifyIssue::MisplacedKey { map: "content", .. })), "{:?}", report.issues);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_overflowing_entries() -> Result<(), Box<dyn std::error::Error>> {
+        let archive = sample_archive(1);
+        let reader = archive.reader()?;
+        let mut file = reader.file().clone();
+
+        // entries which end beyond `u64::MAX` can only be created in memory
+        let mut chunks = file.resource_chunks().to_vec();
+        let last = chunks.len() - 1;
+        chunks[0].offset = u64::MAX;
+        chunks[last].uncompressed_offset = u64::MAX;
+        file.set_resource_chunks(chunks);
+
+        let mut contents = file.contents().as_builder();
+        let hash = *file.contents().keys().first().unwrap();
+        contents.insert(hash, ContentEntry::new(u64::MAX, 0, 0));
+        file.set_contents(contents.build());
+
+        let mut issues = Vec::new();
+        let options = KFCVerifyOptions::default();
+
+        verify_resources(&file, reader.storage().as_ref(), &options, &mut issues)?;
+        verify_contents(&file, reader.storage().as_ref(), &options, &mut issues)?;
+
+        assert!(issues.iter().any(|issue| matches!(issue, KFCVerifyIssue::ChunkOverflow { chunk, .. } if *chunk == last)), "{issues:?}");
+        assert!(issues.iter().any(|issue| matches!(issue, KFCVerifyIssue::ChunkOutOfBounds { chunk: 0, .. })), "{issues:?}");
+        assert!(issues.iter().any(|issue| matches!(issue, KFCVerifyIssue::ContentOutOfBounds { content, .. } if *content == hash)), "{issues:?}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_synthetic_archive() -> Result<(), Box<dyn std::error::Error>> {
+        let archive = sample_archive(4);
+        let report = archive.reader()?.verify(&KFCVerifyOptions::default())?;
+
+        assert!(report.is_ok(), "{:?}", report.issues);
+
+        Ok(())
+    }
+
+}
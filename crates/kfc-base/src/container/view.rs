@@ -0,0 +1,202 @@
+use std::io::Cursor;
+
+use crate::{guid::{ContentHash, ResourceId}, Hash32};
+
+use super::{header::*, KFCFile, KFCReadError, StaticElement, StaticMapView, StaticSlice};
+
+/// A lazily decoded index (`.kfc` file), which borrows the raw bytes of the index.
+///
+/// Only the header is decoded when the view is created. Entries are decoded when they are
+/// looked up, so opening a view does not allocate anything for the entries.
+/// This is meant for memory mapped indices where only a few lookups are needed,
+/// use [`KFCFileView::to_file`] to decode the whole index.
+///
+/// References between the sections are not validated,
+/// lookups with invalid references return `None` instead.
+#[derive(Debug, Clone, Copy)]
+pub struct KFCFileView<'a> {
+    data: &'a [u8],
+    version: &'a str,
+
+    containers: StaticSlice<'a, ContainerInfo>,
+    resource_locations: StaticSlice<'a, ResourceLocation>,
+    resource_chunks: StaticSlice<'a, ResourceChunkInfo>,
+
+    contents: StaticMapView<'a, ContentHash, ContentEntry>,
+    resources: StaticMapView<'a, ResourceId, ResourceEntry>,
+
+    resource_indices: StaticSlice<'a, u32>,
+    resource_bundles: StaticMapView<'a, Hash32, ResourceBundleEntry>,
+}
+
+impl<'a> KFCFileView<'a> {
+
+    pub fn new(data: &'a [u8]) -> Result<Self, KFCReadError> {
+        let file_size = data.len() as u64;
+        let header = KFCHeader::read(&mut Cursor::new(data))?;
+
+        for (section, location, element_size) in header.sections() {
+            location.check_bounds(section, element_size, file_size)?;
+        }
+
+        let version = section_bytes(data, "version", &header.version, 1)?;
+        let version = std::str::from_utf8(version)
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid utf-8"))?;
+
+        Ok(Self {
+            data,
+            version,
+
+            containers: section(data, "containers", &header.containers)?,
+            resource_locations: section(data, "resource_locations", &header.resource_locations)?,
+            resource_chunks: section(data, "resource_chunks", &header.resource_chunks)?,
+
+            contents: StaticMapView::new(
+                section(data, "content_keys", &header.content_keys)?,
+                section(data, "content_values", &header.content_values)?,
+                section(data, "content_buckets", &header.content_buckets)?,
+            )?,
+            resources: StaticMapView::new(
+                section(data, "resource_keys", &header.resource_keys)?,
+                section(data, "resource_values", &header.resource_values)?,
+                section(data, "resource_buckets", &header.resource_buckets)?,
+            )?,
+
+            resource_indices: section(data, "resource_indices", &header.resource_indices)?,
+            resource_bundles: StaticMapView::new(
+                section(data, "resource_bundle_keys", &header.resource_bundle_keys)?,
+                section(data, "resource_bundle_values", &header.resource_bundle_values)?,
+                section(data, "resource_bundle_buckets", &header.resource_bundle_buckets)?,
+            )?,
+        })
+    }
+
+    #[inline]
+    pub fn game_version(&self) -> &'a str {
+        self.version
+    }
+
+    #[inline]
+    pub fn containers(&self) -> StaticSlice<'a, ContainerInfo> {
+        self.containers
+    }
+
+    #[inline]
+    pub fn resource_locations(&self) -> StaticSlice<'a, ResourceLocation> {
+        self.resource_locations
+    }
+
+    #[inline]
+    pub fn resource_chunks(&self) -> StaticSlice<'a, ResourceChunkInfo> {
+        self.resource_chunks
+    }
+
+    #[inline]
+    pub fn contents(&self) -> StaticMapView<'a, ContentHash, ContentEntry> {
+        self.contents
+    }
+
+    #[inline]
+    pub fn resources(&self) -> StaticMapView<'a, ResourceId, ResourceEntry> {
+        self.resources
+    }
+
+    #[inline]
+    pub fn resource_indices(&self) -> StaticSlice<'a, u32> {
+        self.resource_indices
+    }
+
+    #[inline]
+    pub fn resource_bundles(&self) -> StaticMapView<'a, Hash32, ResourceBundleEntry> {
+        self.resource_bundles
+    }
+
+    pub fn resources_by_type(&self, type_hash: Hash32) -> impl Iterator<Item = ResourceId> + 'a {
+        let indices = self.resource_indices;
+        let keys = self.resources.keys();
+
+        self.resource_bundles
+            .get(&type_hash)
+            .map(|info| info.index..info.index.saturating_add(info.count))
+            .unwrap_or_default()
+            .filter_map(move |i| keys.get(indices.get(i)? as usize))
+    }
+
+    /// Decodes the whole index, including validation of all references.
+    pub fn to_file(&self) -> Result<KFCFile, KFCReadError> {
+        KFCFile::from_reader(&mut Cursor::new(self.data), false)
+    }
+
+}
+
+fn section_bytes<'a>(
+    data: &'a [u8],
+    section: &'static str,
+    location: &KFCLocation,
+    element_size: u64,
+) -> Result<&'a [u8], KFCReadError> {
+    location.end(element_size)
+        .and_then(|end| data.get(location.offset as usize..end as usize))
+        .ok_or(KFCReadError::SectionOutOfBounds {
+            section,
+            offset: location.offset,
+            count: location.count,
+            file_size: data.len() as u64,
+        })
+}
+
+pub(super) fn section<'a, T: StaticElement>(
+    data: &'a [u8],
+    section: &'static str,
+    location: &KFCLocation,
+) -> Result<StaticSlice<'a, T>, KFCReadError> {
+    let bytes = section_bytes(data, section, location, T::SIZE as u64)?;
+
+    // the bytes were sliced for exactly `count` elements
+    Ok(StaticSlice::new(bytes, location.count).expect("section has the size of its elements"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{hash::fnv, testing::*};
+
+    use super::*;
+
+    #[test]
+    fn test_file_view() -> Result<(), Box<dyn std::error::Error>> {
+        let archive = sample_archive(1);
+        let data = std::fs::read(archive.kfc_path())?;
+
+        let file = KFCFile::from_reader(&mut Cursor::new(&data), false)?;
+        let view = KFCFile::view(&data)?;
+
+        assert_eq!(view.game_version(), file.game_version());
+        assert_eq!(view.resource_chunks().len(), file.resource_chunks().len());
+        assert_eq!(view.containers().len(), file.containers().len());
+
+        for (id, entry) in file.resources().iter() {
+            let viewed = view.resources().get(id).expect("resource exists");
+
+            assert_eq!((viewed.offset, viewed.size), (entry.offset, entry.size));
+        }
+
+        for (hash, entry) in file.contents().iter() {
+            let viewed = view.contents().get(hash).expect("content exists");
+
+            assert_eq!((viewed.offset, viewed.container_index), (entry.offset, entry.container_index));
+        }
+
+        assert!(!view.resources().contains_key(&sample_resource_id(100, SAMPLE_TYPE_A)));
+        assert_eq!(
+            view.resources_by_type(fnv(SAMPLE_TYPE_A)).collect::<Vec<_>>(),
+            file.resources_by_type(fnv(SAMPLE_TYPE_A)).copied().collect::<Vec<_>>(),
+        );
+        assert_eq!(view.to_file()?.resources().keys(), file.resources().keys());
+
+        // sections must lie within the data
+        assert!(matches!(KFCFile::view(&data[..data.len() / 2]), Err(KFCReadError::SectionOutOfBounds { .. })));
+
+        Ok(())
+    }
+
+}
@@ -1,24 +1,33 @@
-use std::{borrow::Borrow, fs::File, io::{BufWriter, Cursor, Seek, SeekFrom, Write}, path::{Path, PathBuf}};
+use std::{borrow::Borrow, collections::HashMap, io::{BufWriter, Cursor, Read, Seek, SeekFrom, Write}, path::Path, sync::{mpsc::{self, Receiver, Sender}, Arc, Mutex}, thread::JoinHandle};
 
-use crate::{container::header::ResourceChunkInfo, guid::{ContentHash, ResourceId}, io::{WriteExt, WriteSeekExt}, reflection::TypeRegistry};
+use crate::{container::header::ResourceChunkInfo, guid::{ContentHash, ResourceId}, io::{WriteExt, WriteSeekExt}, reflection::TypeRegistry, Hash32};
 
-use super::{header::{ContentEntry, ContainerInfo, ResourceEntry}, KFCFile, KFCReadError, KFCWriteError, StaticMapBuilder};
+use super::{header::{ContentEntry, ContainerInfo, ResourceEntry}, DiskStorage, KFCFile, KFCStorage, KFCStream, KFCWriteError, KFCWriteStream, StaticMapBuilder};
 
-const RESOURCE_ALIGNMENT: u64 = 16;
-const CONTENT_ALIGNMENT: u64 = 4096;
+pub(super) const RESOURCE_ALIGNMENT: u64 = 16;
+pub(super) const CONTENT_ALIGNMENT: u64 = 4096;
 const RESOURCE_CHUNK_ALIGNMENT: u64 = 4096;
 const RESOURCE_CHUNK_SIZE: u64 = 8 * 1024 * 1024; // 8 MiB
+const MIN_RESOURCE_CHUNK_SIZE: u64 = RESOURCE_CHUNK_ALIGNMENT;
+const MAX_RESOURCE_CHUNK_SIZE: u64 = 64 * 1024 * 1024; // 64 MiB
 
 pub struct KFCWriter<F, T> {
     type_registry: T,
 
-    path: PathBuf,
-    file_name: String,
+    storage: Arc<dyn KFCStorage>,
+    /// The streams which are written to staged versions and replaced on finalize.
+    staged_streams: Vec<KFCStream>,
+    /// Restores the resource stream if an incremental write is rolled back.
+    resource_undo: Option<StreamUndo>,
+    committed: bool,
 
-    file: File,
+    file: Box<dyn KFCWriteStream>,
 
     chunk_writer: Cursor<Vec<u8>>,
-    resource_writer: BufWriter<File>,
+    pending_chunks: Vec<Vec<u8>>,
+    /// The threads which compress pending chunks, started once more than one chunk is pending.
+    compression_pool: Option<CompressionPool>,
+    resource_writer: BufWriter<Box<dyn KFCWriteStream>>,
     uncompressed_offset: u64,
     uncompressed_chunk_offset: u64,
     resource_chunks: Vec<ResourceChunkInfo>,
@@ -28,6 +37,8 @@ pub struct KFCWriter<F, T> {
     game_version: String,
     resources: StaticMapBuilder<ResourceId, ResourceEntry>,
     contents: StaticMapBuilder<ContentHash, ContentEntry>,
+    /// The number of removed contents per container.
+    removed_contents: HashMap<usize, usize>,
 
     incremental_data: Option<IncrementalData<F>>,
     options: KFCWriteOptions,
@@ -37,7 +48,16 @@ struct IncrementalData<F> {
     reference_file: F,
 }
 
-#[derive(Debug)]
+/// The original state of a stream which is modified in place.
+struct StreamUndo {
+    stream: KFCStream,
+    len: u64,
+    /// The original data after `offset`, which is overwritten by the writer.
+    offset: u64,
+    data: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
 pub struct KFCWriteOptions {
     /// The extension to use for kfc files.
     pub kfc_extension: String,
@@ -56,6 +76,15 @@ pub struct KFCWriteOptions {
     /// This is used to remove any leftover data from previous writes.
     /// **NOTE:** For incremental writes, this will only affect non-default .dat files.
     pub truncate_containers: bool,
+    /// The zstd compression level used for resource chunks.
+    /// `0` selects the default level of zstd.
+    pub compression_level: i32,
+    /// The uncompressed size of a single resource chunk, between 4 KiB and 64 MiB.
+    /// **NOTE:** The game itself always uses 8 MiB chunks.
+    pub resource_chunk_size: u64,
+    /// How many resource chunks are compressed in parallel.
+    /// Chunks are always written in order, so the output does not depend on this value.
+    pub compression_threads: usize,
 }
 
 impl Default for KFCWriteOptions {
@@ -67,8 +96,30 @@ impl Default for KFCWriteOptions {
             overwrite_containers: false,
             max_container_size: 1024 * 1024 * 1024, // 1 GiB
             truncate_containers: false,
+            compression_level: 0,
+            resource_chunk_size: RESOURCE_CHUNK_SIZE,
+            compression_threads: std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+        }
+    }
+}
+
+impl KFCWriteOptions {
+
+    /// Checks that the options can be used to write an archive.
+    pub fn validate(&self) -> Result<(), KFCWriteError> {
+        if !(MIN_RESOURCE_CHUNK_SIZE..=MAX_RESOURCE_CHUNK_SIZE).contains(&self.resource_chunk_size) {
+            return Err(KFCWriteError::InvalidChunkSize {
+                size: self.resource_chunk_size,
+                min: MIN_RESOURCE_CHUNK_SIZE,
+                max: MAX_RESOURCE_CHUNK_SIZE,
+            });
         }
+
+        Ok(())
     }
+
 }
 
 impl<F, T> KFCWriter<F, T>
@@ -83,7 +134,7 @@ where
         file_name: impl AsRef<str>,
         type_registry: T,
         game_version: impl AsRef<str>,
-    ) -> Result<Self, KFCReadError> {
+    ) -> Result<Self, KFCWriteError> {
         Self::new_with_options(
             path,
             file_name,
@@ -99,31 +150,40 @@ where
         type_registry: T,
         game_version: impl AsRef<str>,
         options: KFCWriteOptions,
-    ) -> Result<Self, KFCReadError> {
-        let kfc_path = path.as_ref().join(format!(
-            "{}.{}",
-            file_name.as_ref(),
-            options.kfc_extension
-        ));
-        let file = File::options().write(true).read(true).open(&kfc_path)?;
-
-        let resources_path = path.as_ref().join(format!(
-            "{}.{}",
-            file_name.as_ref(),
-            options.resource_extension
-        ));
-        let resource_file = File::options().write(true).open(&resources_path)?;
-        let resource_writer = BufWriter::new(resource_file);
+    ) -> Result<Self, KFCWriteError> {
+        let storage = disk_storage(path, file_name, &options);
+
+        Self::with_storage(storage, type_registry, game_version, options)
+    }
+
+    /// Creates a writer which writes the archive into the given storage.
+    ///
+    /// All streams are written to staged versions, which replace the current streams
+    /// once the writer is finalized. See [`KFCWriter::finalize`].
+    pub fn with_storage(
+        storage: impl KFCStorage + 'static,
+        type_registry: T,
+        game_version: impl AsRef<str>,
+        options: KFCWriteOptions,
+    ) -> Result<Self, KFCWriteError> {
+        options.validate()?;
+
+        let file = storage.create_staged(KFCStream::Index)?;
+        let resource_writer = BufWriter::new(storage.create_staged(KFCStream::Resources)?);
 
         Ok(Self {
             type_registry,
 
-            path: path.as_ref().to_path_buf(),
-            file_name: file_name.as_ref().to_string(),
+            storage: Arc::new(storage),
+            staged_streams: vec![KFCStream::Index, KFCStream::Resources],
+            resource_undo: None,
+            committed: false,
 
             file,
 
             chunk_writer: Cursor::new(Vec::new()),
+            pending_chunks: Vec::new(),
+            compression_pool: None,
             resource_writer,
             uncompressed_offset: 0,
             uncompressed_chunk_offset: 0,
@@ -134,6 +194,7 @@ where
             game_version: game_version.as_ref().to_string(),
             resources: StaticMapBuilder::default(),
             contents: StaticMapBuilder::default(),
+            removed_contents: HashMap::new(),
 
             incremental_data: None,
             options,
@@ -146,7 +207,7 @@ where
         file_name: impl AsRef<str>,
         reference_file: F,
         type_registry: T,
-    ) -> Result<Self, KFCReadError> {
+    ) -> Result<Self, KFCWriteError> {
         Self::new_incremental_with_options(
             path,
             file_name,
@@ -162,31 +223,35 @@ where
         reference_file: F,
         type_registry: T,
         options: KFCWriteOptions,
-    ) -> Result<Self, KFCReadError> {
-        let kfc_path = path.as_ref().join(format!(
-            "{}.{}",
-            file_name.as_ref(),
-            options.kfc_extension
-        ));
-        let current_file = KFCFile::from_path(&kfc_path, true)?;
+    ) -> Result<Self, KFCWriteError> {
+        let storage = disk_storage(path, file_name, &options);
+
+        Self::new_incremental_with_storage(storage, reference_file, type_registry, options)
+    }
+
+    /// Creates a writer which appends to the archive in the given storage,
+    /// see [`KFCWriter::new_incremental`].
+    ///
+    /// Resource chunks are appended to the current resource stream in place,
+    /// all other streams are staged like in [`KFCWriter::with_storage`].
+    ///
+    /// **NOTE:** The new chunks replace the data after the chunks of the reference file,
+    /// which may belong to an earlier incremental write. If the process dies before the writer
+    /// is finalized or rolled back, the current index can point to overwritten chunks
+    /// until the archive is restored from a snapshot or written again.
+    pub fn new_incremental_with_storage(
+        storage: impl KFCStorage + 'static,
+        reference_file: F,
+        type_registry: T,
+        options: KFCWriteOptions,
+    ) -> Result<Self, KFCWriteError> {
+        options.validate()?;
 
         let file = reference_file.borrow();
 
         let resources = file.resources().as_builder();
         let contents = file.contents().as_builder();
 
-        drop(current_file);
-
-        let file = File::options().write(true).read(true).open(&kfc_path)?;
-
-        let resources_path = path.as_ref().join(format!(
-            "{}.{}",
-            file_name.as_ref(),
-            options.resource_extension
-        ));
-        let resource_file = File::options().write(true).open(&resources_path)?;
-        let mut resource_writer = BufWriter::new(resource_file);
-
         let previous_resource_size = reference_file.borrow()
             .resource_chunks()
             .iter()
@@ -201,16 +266,44 @@ where
             .max()
             .unwrap_or(0);
 
+        // keep the data after the reference chunks, since the current
+        // index may still point to it if the write is rolled back
+
+        let resource_len = storage.len(KFCStream::Resources)?;
+        let mut resource_reader = storage.open_read(KFCStream::Resources)?;
+        let mut overwritten_data = Vec::new();
+
+        if resource_len > previous_resource_size {
+            resource_reader.seek(SeekFrom::Start(previous_resource_size))?;
+            resource_reader.read_to_end(&mut overwritten_data)?;
+        }
+
+        drop(resource_reader);
+
+        let resource_undo = StreamUndo {
+            stream: KFCStream::Resources,
+            len: resource_len,
+            offset: previous_resource_size,
+            data: overwritten_data,
+        };
+
+        let file = storage.create_staged(KFCStream::Index)?;
+        let mut resource_writer = BufWriter::new(storage.open_write(KFCStream::Resources)?);
+
         resource_writer.seek(SeekFrom::Start(previous_resource_size))?;
 
         Ok(Self {
             type_registry,
 
-            path: path.as_ref().into(),
-            file_name: file_name.as_ref().to_string(),
+            storage: Arc::new(storage),
+            staged_streams: vec![KFCStream::Index],
+            resource_undo: Some(resource_undo),
+            committed: false,
 
             file,
             chunk_writer: Cursor::new(Vec::new()),
+            pending_chunks: Vec::new(),
+            compression_pool: None,
             resource_writer,
             uncompressed_offset: previous_uncompressed_size,
             uncompressed_chunk_offset: previous_uncompressed_size,
@@ -221,6 +314,7 @@ where
             game_version: reference_file.borrow().game_version().to_string(),
             resources,
             contents,
+            removed_contents: HashMap::new(),
 
             incremental_data: Some(IncrementalData {
                 reference_file,
@@ -230,8 +324,8 @@ where
     }
 
     #[inline]
-    pub fn path(&self) -> &Path {
-        &self.path
+    pub fn storage(&self) -> &Arc<dyn KFCStorage> {
+        &self.storage
     }
 
     #[inline]
@@ -264,14 +358,16 @@ where
 
         // write resource data or compress it if needed
 
-        while aligned_size + self.chunk_writer.position() >= RESOURCE_CHUNK_SIZE {
-            let available_size = RESOURCE_CHUNK_SIZE - self.chunk_writer.position();
+        let chunk_size = self.options.resource_chunk_size;
+
+        while aligned_size + self.chunk_writer.position() >= chunk_size {
+            let available_size = chunk_size - self.chunk_writer.position();
             let split_index = (available_size as usize).min(bytes.len());
 
             let (chunk_bytes, remaining_bytes) = bytes.split_at(split_index);
 
             self.chunk_writer.write_all(chunk_bytes)?;
-            self.chunk_writer.align(RESOURCE_CHUNK_SIZE as usize)?;
+            self.chunk_writer.align(chunk_size as usize)?;
 
             // compress and submit chunk
 
@@ -296,38 +392,60 @@ where
         Ok(())
     }
 
+    /// Queues the current chunk for compression.
+    /// Once enough chunks are queued, they are compressed in parallel and written in order.
     fn submit_resource_data(&mut self) -> std::io::Result<()> {
-        let offset = self.resource_writer.stream_position()?;
-        let chunk_data = self.chunk_writer.get_ref();
-
-        zstd::stream::copy_encode(
-            &mut &chunk_data[..],
-            &mut self.resource_writer,
-            0
-        )?;
-
-        let compressed_size = self.resource_writer.stream_position()? - offset;
+        let chunk_data = std::mem::take(self.chunk_writer.get_mut());
 
-        self.resource_writer.align(RESOURCE_CHUNK_ALIGNMENT as usize)?;
-
-        let size = self.resource_writer.stream_position()? - offset;
-        let uncompressed_offset = self.uncompressed_chunk_offset;
-        let uncompressed_size = chunk_data.len() as u64;
+        self.pending_chunks.push(chunk_data);
+        self.chunk_writer.set_position(0);
 
-        self.resource_chunks.push(ResourceChunkInfo {
-            offset,
-            size,
-            compressed_size,
-            uncompressed_offset,
-            uncompressed_size,
-        });
+        if self.pending_chunks.len() >= self.options.compression_threads.max(1) {
+            self.flush_resource_data()?;
+        }
 
-        self.uncompressed_chunk_offset += uncompressed_size;
+        Ok(())
+    }
 
-        // reset chunk writer
+    fn flush_resource_data(&mut self) -> std::io::Result<()> {
+        let pending_chunks = std::mem::take(&mut self.pending_chunks);
+        let level = self.options.compression_level;
+        let uncompressed_sizes = pending_chunks.iter()
+            .map(|chunk_data| chunk_data.len() as u64)
+            .collect::<Vec<_>>();
+
+        let compressed_chunks = if pending_chunks.len() > 1 {
+            let pool = match &mut self.compression_pool {
+                Some(pool) => pool,
+                pool @ None => pool.insert(CompressionPool::new(self.options.compression_threads, level)?),
+            };
+
+            pool.compress(pending_chunks)?
+        } else {
+            pending_chunks.iter()
+                .map(|chunk_data| compress_chunk(chunk_data, level))
+                .collect::<std::io::Result<Vec<_>>>()?
+        };
+
+        for (uncompressed_size, compressed_data) in uncompressed_sizes.into_iter().zip(compressed_chunks) {
+            let offset = self.resource_writer.stream_position()?;
+
+            self.resource_writer.write_all(&compressed_data)?;
+            self.resource_writer.align(RESOURCE_CHUNK_ALIGNMENT as usize)?;
+
+            let size = self.resource_writer.stream_position()? - offset;
+            let uncompressed_offset = self.uncompressed_chunk_offset;
+
+            self.resource_chunks.push(ResourceChunkInfo {
+                offset,
+                size,
+                compressed_size: compressed_data.len() as u64,
+                uncompressed_offset,
+                uncompressed_size,
+            });
 
-        self.chunk_writer.set_position(0);
-        self.chunk_writer.get_mut().clear();
+            self.uncompressed_chunk_offset += uncompressed_size;
+        }
 
         Ok(())
     }
@@ -336,6 +454,17 @@ where
         &mut self,
         guid: &ContentHash,
         data: &[u8],
+    ) -> std::io::Result<()> {
+        self.write_content_with_flags(guid, data, 0)
+    }
+
+    /// Writes a content with the given entry flags,
+    /// which are kept as they are when copying contents from another archive.
+    pub fn write_content_with_flags(
+        &mut self,
+        guid: &ContentHash,
+        data: &[u8],
+        flags: u16,
     ) -> std::io::Result<()> {
         if guid.size() != data.len() as u32 {
             return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Content size mismatch"));
@@ -355,13 +484,50 @@ where
         writer.write_all(data)?;
         writer.align(CONTENT_ALIGNMENT as usize)?;
 
-        self.contents.insert(*guid, ContentEntry::new(offset, 0, index));
+        self.contents.insert(*guid, ContentEntry::new(offset, flags, index));
 
         Ok(())
     }
 
+    /// Removes a resource from the index.
+    /// Returns false if the resource was neither written nor part of the reference file.
+    ///
+    /// **NOTE:** The resource data itself stays in the resource chunks.
+    pub fn remove_resource(&mut self, guid: &ResourceId) -> bool {
+        self.resources.remove(guid).is_some()
+    }
+
+    /// Removes all resources of the given type from the index and returns how many were removed.
+    pub fn remove_resources_by_type(&mut self, type_hash: Hash32) -> usize {
+        let previous_len = self.resources.len();
+
+        self.resources.retain(|guid, _| guid.type_hash() != type_hash);
+
+        previous_len - self.resources.len()
+    }
+
+    /// Removes a content from the index.
+    /// Returns false if the content was neither written nor part of the reference file.
+    ///
+    /// **NOTE:** The content data itself stays in its .dat file.
+    pub fn remove_content(&mut self, guid: &ContentHash) -> bool {
+        let Some(entry) = self.contents.remove(guid) else {
+            return false;
+        };
+
+        *self.removed_contents.entry(entry.container_index).or_default() += 1;
+
+        true
+    }
+
+    /// Writes the index and replaces the streams of the archive.
+    ///
+    /// The staged streams are synced and then moved into place, with the index being last.
+    /// If this fails or the writer is dropped without being finalized, all staged streams
+    /// are discarded and streams which were modified in place are restored.
     pub fn finalize(mut self) -> Result<(), KFCWriteError> {
         self.submit_resource_data()?;
+        self.flush_resource_data()?;
 
         // prepare container infos
 
@@ -382,15 +548,22 @@ where
             });
         }
 
+        for (&index, &count) in &self.removed_contents {
+            if let Some(container) = containers.get_mut(index) {
+                container.count = container.count.saturating_sub(count);
+            }
+        }
+
         // make sure to have a power of two container files
 
         let required_container_count = containers.len().next_power_of_two();
 
         while containers.len() < required_container_count {
-            let path = self.dat_path(containers.len());
+            let stream = KFCStream::Container(containers.len());
 
-            if !path.exists() || self.options.truncate_containers {
-                File::create(path)?;
+            if !self.storage.exists(stream) || self.options.truncate_containers {
+                self.storage.create_staged(stream)?;
+                self.staged_streams.push(stream);
             }
 
             containers.push(ContainerInfo {
@@ -412,26 +585,20 @@ where
         let mut file_writer = BufWriter::new(&mut self.file);
         let mut file = KFCFile::default();
 
-        file.set_game_version(self.game_version);
-        file.set_resources(self.resources.build(), self.type_registry.borrow());
-        file.set_contents(self.contents.build());
+        file.set_game_version(std::mem::take(&mut self.game_version));
+        file.set_resources(std::mem::take(&mut self.resources).build(), self.type_registry.borrow());
+        file.set_contents(std::mem::take(&mut self.contents).build());
         file.set_containers(containers);
         file.set_resource_chunks(chunks);
 
         file.write(&mut file_writer)?;
+        file_writer.flush()?;
 
-        Ok(())
-    }
+        drop(file_writer);
 
-    pub fn dat_path(&self, index: usize) -> PathBuf {
-        // Format: FILE_NAME_{INDEX}.dat where INDEX is 3 digits with leading zeros
-        let name = format!(
-            "{}_{:03}.{}",
-            self.file_name,
-            index,
-            self.options.dat_extension
-        );
-        self.path.join(name)
+        self.commit()?;
+
+        Ok(())
     }
 
     fn get_container_writer(&mut self) -> std::io::Result<&mut ContainerWriter> {
@@ -450,19 +617,24 @@ where
             .map(|data| data.reference_file.borrow().containers().len())
             .unwrap_or(0);
         let next_index = base_index + self.container_writers.len();
-        let path = self.dat_path(next_index);
+        let stream = KFCStream::Container(next_index);
 
-        if !self.options.overwrite_containers && path.exists() {
+        if !self.options.overwrite_containers && self.storage.exists(stream) {
             // make sure we don't accidentally overwrite an existing file
             return Err(std::io::Error::new(
                 std::io::ErrorKind::AlreadyExists,
-                format!("Data file already exists: {}", path.display())
+                match self.storage.path(stream) {
+                    Some(path) => format!("Data file already exists: {}", path.display()),
+                    None => format!("Data file already exists: {next_index}"),
+                }
             ));
         }
 
-        let writer = BufWriter::new(File::create(&path)?);
+        let writer = BufWriter::new(self.storage.create_staged(stream)?);
         let writer = ContainerWriter::new(next_index, writer);
 
+        self.staged_streams.push(stream);
+
         self.container_writers.push(writer);
 
         Ok(self.container_writers.last_mut().unwrap())
@@ -470,16 +642,202 @@ where
 
 }
 
+impl<F, T> KFCWriter<F, T> {
+
+    fn commit(&mut self) -> std::io::Result<()> {
+        self.resource_writer.flush()?;
+
+        // close all streams before moving them
+        self.close_streams();
+
+        if let Some(undo) = &self.resource_undo {
+            self.storage.sync(undo.stream)?;
+        }
+
+        // the index must be last, so it never points to containers which were not moved yet,
+        // the resource chunks were already written in place (see `new_incremental_with_storage`)
+        self.staged_streams.sort_by_key(|&stream| stream == KFCStream::Index);
+
+        for &stream in &self.staged_streams {
+            self.storage.commit_staged(stream)?;
+        }
+
+        self.committed = true;
+
+        Ok(())
+    }
+
+    fn rollback(&mut self) -> std::io::Result<()> {
+        self.close_streams();
+
+        let mut result = Ok(());
+
+        for &stream in &self.staged_streams {
+            result = result.and(self.storage.discard_staged(stream));
+        }
+
+        if let Some(undo) = &self.resource_undo {
+            result = result.and_then(|_| {
+                let mut writer = self.storage.open_write(undo.stream)?;
+
+                writer.seek(SeekFrom::Start(undo.offset))?;
+                writer.write_all(&undo.data)?;
+                writer.flush()?;
+
+                drop(writer);
+
+                self.storage.set_len(undo.stream, undo.len)?;
+                self.storage.sync(undo.stream)
+            });
+        }
+
+        result
+    }
+
+    /// Closes all open streams without writing any buffered data.
+    fn close_streams(&mut self) {
+        let empty_stream = || Box::new(Cursor::new(Vec::new())) as Box<dyn KFCWriteStream>;
+
+        let resource_writer = std::mem::replace(&mut self.resource_writer, BufWriter::new(empty_stream()));
+        let _ = resource_writer.into_parts();
+
+        for container_writer in self.container_writers.drain(..) {
+            let _ = container_writer.writer.into_parts();
+        }
+
+        self.file = empty_stream();
+    }
+
+}
+
+impl<F, T> Drop for KFCWriter<F, T> {
+
+    fn drop(&mut self) {
+        if !self.committed {
+            // errors can't be reported here, the streams are restored on a best effort basis
+            let _ = self.rollback();
+        }
+    }
+
+}
+
+fn disk_storage(
+    path: impl AsRef<Path>,
+    file_name: impl AsRef<str>,
+    options: &KFCWriteOptions,
+) -> DiskStorage {
+    DiskStorage::new(path, file_name).with_extensions(
+        &options.kfc_extension,
+        &options.resource_extension,
+        &options.dat_extension,
+    )
+}
+
+fn compress_chunk(chunk_data: &[u8], level: i32) -> std::io::Result<Vec<u8>> {
+    let mut compressed_data = Vec::new();
+
+    zstd::stream::copy_encode(chunk_data, &mut compressed_data, level)?;
+
+    Ok(compressed_data)
+}
+
+/// A fixed set of threads which compress resource chunks,
+/// so the threads are not started again for every batch of chunks.
+struct CompressionPool {
+    jobs: Option<Sender<(usize, Vec<u8>)>>,
+    results: Receiver<(usize, std::io::Result<Vec<u8>>)>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl CompressionPool {
+
+    fn new(threads: usize, level: i32) -> std::io::Result<Self> {
+        let (jobs, job_receiver) = mpsc::channel::<(usize, Vec<u8>)>();
+        let (result_sender, results) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+        let workers = (0..threads.max(1))
+            .map(|i| {
+                let jobs = job_receiver.clone();
+                let results = result_sender.clone();
+
+                std::thread::Builder::new()
+                    .name(format!("kfc-compression-{i}"))
+                    .spawn(move || loop {
+                        let job = match jobs.lock() {
+                            Ok(jobs) => jobs.recv(),
+                            Err(_) => break,
+                        };
+
+                        let Ok((index, chunk_data)) = job else {
+                            break;
+                        };
+
+                        // a panic is reported as an error of its chunk, so the writer does not wait forever
+                        let result = std::panic::catch_unwind(|| compress_chunk(&chunk_data, level))
+                            .unwrap_or_else(|_| Err(std::io::Error::other("compression thread panicked")));
+
+                        if results.send((index, result)).is_err() {
+                            break;
+                        }
+                    })
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        Ok(Self {
+            jobs: Some(jobs),
+            results,
+            workers,
+        })
+    }
+
+    /// Compresses the chunks in parallel and returns them in the same order.
+    fn compress(&mut self, chunks: Vec<Vec<u8>>) -> std::io::Result<Vec<Vec<u8>>> {
+        let stopped = || std::io::Error::other("compression threads stopped");
+        let jobs = self.jobs.as_ref().ok_or_else(stopped)?;
+        let count = chunks.len();
+
+        for job in chunks.into_iter().enumerate() {
+            jobs.send(job).map_err(|_| stopped())?;
+        }
+
+        let mut compressed_chunks = (0..count).map(|_| None).collect::<Vec<_>>();
+
+        for _ in 0..count {
+            let (index, result) = self.results.recv().map_err(|_| stopped())?;
+            compressed_chunks[index] = Some(result);
+        }
+
+        compressed_chunks.into_iter()
+            .map(|result| result.ok_or_else(stopped)?)
+            .collect()
+    }
+
+}
+
+impl Drop for CompressionPool {
+
+    fn drop(&mut self) {
+        // closing the channel stops the workers once they are idle
+        self.jobs = None;
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+
+}
+
 struct ContainerWriter {
     index: usize,
     count: usize,
-    writer: BufWriter<File>,
+    writer: BufWriter<Box<dyn KFCWriteStream>>,
 }
 
 impl ContainerWriter {
 
     #[inline]
-    fn new(index: usize, writer: BufWriter<File>) -> Self {
+    fn new(index: usize, writer: BufWriter<Box<dyn KFCWriteStream>>) -> Self {
         Self {
             index,
             count: 0,
@@ -488,7 +846,7 @@ impl ContainerWriter {
     }
 
     #[inline]
-    fn aquire(&mut self) -> std::io::Result<&mut BufWriter<File>> {
+    fn aquire(&mut self) -> std::io::Result<&mut BufWriter<Box<dyn KFCWriteStream>>> {
         self.count += 1;
         Ok(&mut self.writer)
     }
@@ -504,3 +862,222 @@ impl ContainerWriter {
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{container::{KFCVerifyOptions, MemoryStorage}, hash::fnv, testing::*};
+
+    use super::*;
+
+    #[test]
+    fn test_deterministic_compression() -> Result<(), Box<dyn std::error::Error>> {
+        let single = sample_archive(1);
+        let parallel = sample_archive(8);
+
+        let a = std::fs::read(single.path().join("test.kfc_resources"))?;
+        let b = std::fs::read(parallel.path().join("test.kfc_resources"))?;
+
+        assert!(a == b, "resources differ between single and parallel compression");
+
+        let a = format!("{:?}", single.reader()?.file().resource_chunks());
+        let b = format!("{:?}", parallel.reader()?.file().resource_chunks());
+
+        assert_eq!(a, b, "resource chunks differ between single and parallel compression");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_chunk_size() {
+        for resource_chunk_size in [0, 1, MAX_RESOURCE_CHUNK_SIZE + 1, u64::MAX] {
+            let result = KFCWriter::<KFCFile, _>::with_storage(
+                MemoryStorage::new(),
+                sample_type_registry(),
+                "test",
+                KFCWriteOptions {
+                    resource_chunk_size,
+                    ..Default::default()
+                },
+            );
+
+            assert!(matches!(
+                result,
+                Err(KFCWriteError::InvalidChunkSize { size, .. }) if size == resource_chunk_size
+            ));
+        }
+    }
+
+    #[test]
+    fn test_compression_pool() -> std::io::Result<()> {
+        let chunks = (0..3u8)
+            .map(|i| sample_resource_data(i, 10_000))
+            .collect::<Vec<_>>();
+        let expected = chunks.iter()
+            .map(|chunk_data| compress_chunk(chunk_data, 0))
+            .collect::<std::io::Result<Vec<_>>>()?;
+
+        // the same threads compress every batch, even if it is larger than the pool
+        let mut pool = CompressionPool::new(2, 0)?;
+
+        for _ in 0..3 {
+            assert_eq!(pool.compress(chunks.clone())?, expected);
+        }
+
+        assert_eq!(pool.workers.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_entries() -> Result<(), Box<dyn std::error::Error>> {
+        let archive = sample_archive(4);
+        let reference = archive.reader()?.file().clone();
+        let removed_content = *reference.contents().keys().first().expect("archive has contents");
+
+        let mut writer = KFCWriter::new_incremental(
+            archive.path(),
+            archive.file_name(),
+            &reference,
+            archive.type_registry(),
+        )?;
+
+        assert!(writer.remove_resource(&sample_resource_id(0, SAMPLE_TYPE_A)));
+        assert!(!writer.remove_resource(&sample_resource_id(100, SAMPLE_TYPE_A)));
+        assert_eq!(writer.remove_resources_by_type(fnv(SAMPLE_TYPE_B)), 16);
+        assert!(writer.remove_content(&removed_content));
+        assert!(!writer.remove_content(&removed_content));
+
+        writer.finalize()?;
+
+        let kfc_reader = archive.reader()?;
+        let file = kfc_reader.file();
+
+        assert_eq!(file.resources().len(), 15);
+        assert_eq!(file.resources_by_type(fnv(SAMPLE_TYPE_A)).count(), 15);
+        assert_eq!(file.resources_by_type(fnv(SAMPLE_TYPE_B)).count(), 0);
+        assert_eq!(file.resource_types().collect::<Vec<_>>(), [fnv(SAMPLE_TYPE_A)]);
+        assert_eq!(file.contents().len(), 7);
+        assert_eq!(file.containers().iter().map(|c| c.count).sum::<usize>(), 7);
+        assert!(!file.contents().contains_key(&removed_content));
+
+        let mut cursor = kfc_reader.new_cursor()?;
+
+        assert!(cursor.read_resource(&sample_resource_id(0, SAMPLE_TYPE_A))?.is_none());
+        assert_eq!(cursor.read_resource(&sample_resource_id(2, SAMPLE_TYPE_A))?.as_deref(), Some(&sample_resource_data(2, 2 * 997)[..]));
+        assert!(kfc_reader.verify(&KFCVerifyOptions::default())?.is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rollback_incremental_write() -> Result<(), Box<dyn std::error::Error>> {
+        let archive = sample_archive(4);
+        let reference = archive.reader()?.file().clone();
+
+        // a first patch, which a rolled back write must keep intact
+
+        let mut writer = KFCWriter::new_incremental_with_options(
+            archive.path(),
+            archive.file_name(),
+            &reference,
+            archive.type_registry(),
+            KFCWriteOptions {
+                resource_chunk_size: 4096,
+                ..Default::default()
+            },
+        )?;
+
+        writer.write_resource(&sample_resource_id(2, SAMPLE_TYPE_A), &sample_resource_data(42, 10_000))?;
+        writer.write_content(&ContentHash::from_data(&sample_resource_data(42, 7000)), &sample_resource_data(42, 7000))?;
+        writer.finalize()?;
+
+        let before = read_dir_files(archive.path())?;
+
+        // a second patch, which is dropped halfway
+
+        let mut writer = KFCWriter::new_incremental_with_options(
+            archive.path(),
+            archive.file_name(),
+            &reference,
+            archive.type_registry(),
+            KFCWriteOptions {
+                resource_chunk_size: 4096,
+                overwrite_containers: true,
+                ..Default::default()
+            },
+        )?;
+
+        for i in 0..8 {
+            writer.write_resource(&sample_resource_id(i, SAMPLE_TYPE_A), &sample_resource_data(100 + i, 20_000))?;
+        }
+
+        writer.write_content(&ContentHash::from_data(&sample_resource_data(43, 9000)), &sample_resource_data(43, 9000))?;
+
+        drop(writer);
+
+        assert!(before == read_dir_files(archive.path())?, "rolled back write modified the archive");
+
+        let kfc_reader = archive.reader()?;
+
+        assert_eq!(kfc_reader.new_cursor()?.read_resource(&sample_resource_id(2, SAMPLE_TYPE_A))?, Some(sample_resource_data(42, 10_000)));
+        assert!(kfc_reader.verify(&KFCVerifyOptions::default())?.is_ok());
+
+        drop(kfc_reader);
+
+        // repeating the first patch replaces its chunks instead of appending them
+
+        let storage = DiskStorage::new(archive.path(), archive.file_name());
+        let resource_len = storage.len(KFCStream::Resources)?;
+
+        let mut writer = KFCWriter::new_incremental_with_storage(
+            storage.clone(),
+            &reference,
+            archive.type_registry(),
+            KFCWriteOptions {
+                resource_chunk_size: 4096,
+                overwrite_containers: true,
+                ..Default::default()
+            },
+        )?;
+
+        writer.write_resource(&sample_resource_id(2, SAMPLE_TYPE_A), &sample_resource_data(42, 10_000))?;
+        writer.finalize()?;
+
+        assert_eq!(storage.len(KFCStream::Resources)?, resource_len);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rollback_new_archive() -> Result<(), Box<dyn std::error::Error>> {
+        let storage = MemoryStorage::new();
+        let type_registry = sample_type_registry();
+
+        let mut writer = KFCWriter::<KFCFile, _>::with_storage(
+            storage.clone(),
+            &type_registry,
+            "test",
+            KFCWriteOptions::default(),
+        )?;
+
+        writer.write_resource(&sample_resource_id(0, SAMPLE_TYPE_A), &sample_resource_data(0, 100))?;
+        writer.write_content(&ContentHash::from_data(&sample_resource_data(1, 100)), &sample_resource_data(1, 100))?;
+
+        assert!(!storage.exists(KFCStream::Index));
+
+        drop(writer);
+
+        assert!(!storage.exists(KFCStream::Index));
+        assert!(!storage.exists(KFCStream::Resources));
+        assert!(!storage.exists(KFCStream::Container(0)));
+
+        // finalizing leaves no staged files behind
+
+        let archive = sample_archive(4);
+
+        assert!(read_dir_files(archive.path())?.keys().all(|name| !name.ends_with(".tmp")));
+
+        Ok(())
+    }
+
+}
@@ -6,7 +6,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::guid::Guid;
 use crate::hash::compute_content_guid;
-use crate::{container::StaticHash, Hash32};
+use crate::{container::{KFCReadError, StaticElement, StaticHash}, Hash32};
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default, Serialize, Deserialize)]
 pub struct ContentHash {
@@ -149,6 +149,15 @@ impl StaticHash for ContentHash {
 
 }
 
+impl StaticElement for ContentHash {
+    const SIZE: usize = 16;
+
+    #[inline]
+    fn decode(bytes: &[u8]) -> Result<Self, KFCReadError> {
+        Ok(Self::read(&mut &bytes[..])?)
+    }
+}
+
 impl FromStr for ContentHash {
     type Err = String;
 
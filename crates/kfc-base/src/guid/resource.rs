@@ -5,7 +5,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::guid::Guid;
 use crate::hash::fnv_bytes_with_seed;
-use crate::{container::StaticHash, io::{ReadExt, WriteExt}, Hash32};
+use crate::{container::{KFCReadError, StaticElement, StaticHash}, io::{ReadExt, WriteExt}, Hash32};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
 pub struct ResourceId {
@@ -164,6 +164,15 @@ impl StaticHash for ResourceId {
 
 }
 
+impl StaticElement for ResourceId {
+    const SIZE: usize = 32;
+
+    #[inline]
+    fn decode(bytes: &[u8]) -> Result<Self, KFCReadError> {
+        Ok(Self::read(&mut &bytes[..])?)
+    }
+}
+
 impl Display for ResourceId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         Display::fmt(&self.guid(), f)
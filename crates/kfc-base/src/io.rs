@@ -83,19 +83,28 @@ pub trait ReadExt: Read {
     #[inline]
     fn read_exact_n(&mut self, len: usize, buf: &mut Vec<u8>) -> Result<()> {
         let mut chunk = self.take(len as u64);
-        assert_eq!(chunk.read_to_end(buf)?, len);
+
+        if chunk.read_to_end(buf)? != len {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "failed to fill whole buffer"));
+        }
+
         Ok(())
     }
 
     #[inline]
     fn padding(&mut self, n: usize) -> Result<()> {
-        let mut buf = vec![0; n];
-        self.read_exact(&mut buf)?;
+        let mut buf = [0; 64];
+        let mut remaining = n;
+
+        while remaining > 0 {
+            let len = remaining.min(buf.len());
+            self.read_exact(&mut buf[..len])?;
 
-        for byte in buf {
-            if byte != 0 {
+            if buf[..len].iter().any(|&byte| byte != 0) {
                 return Err(Error::new(ErrorKind::InvalidData, "padding is not 0"));
             }
+
+            remaining -= len;
         }
 
         Ok(())
@@ -1,4 +1,5 @@
-use container::StaticHash;
+use container::{KFCReadError, StaticElement, StaticHash};
+use io::ReadExt;
 
 pub mod container;
 pub mod guid;
@@ -6,6 +7,9 @@ pub mod reflection;
 pub mod hash;
 pub mod io;
 
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
 /// Represent an fnv1a32 hash
 pub type Hash32 = u32;
 
@@ -17,3 +21,13 @@ impl StaticHash for Hash32 {
         *self
     }
 }
+
+impl StaticElement for Hash32 {
+    const SIZE: usize = 4;
+
+    fn decode(bytes: &[u8]) -> Result<Self, KFCReadError> {
+        let mut reader = bytes;
+
+        Ok(reader.read_u32()?)
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+
+use indexmap::IndexMap;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::reflection::{Attribute, PrimitiveType, TypeFlags, TypeIndex, TypeMetadata, TypeRegistry};
+
+/// The maximum number of issues shown in the message of [`TypeRegistryError`].
+const MAX_DISPLAYED_ISSUES: usize = 8;
+
+#[derive(Debug, Error)]
+#[error("Invalid type registry: {}", format_issues(.issues))]
+pub struct TypeRegistryError {
+    pub issues: Vec<TypeRegistryIssue>,
+}
+
+/// A reason why a set of types can not form a type registry.
+#[derive(Debug, Clone, PartialEq, Eq, Error, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TypeRegistryIssue {
+    #[error("Type {type_name} at position {position} has index {index}")]
+    IndexMismatch {
+        type_name: String,
+        position: usize,
+        index: TypeIndex,
+    },
+    #[error("Types {first} and {second} have the same qualified hash {hash:#010X}")]
+    DuplicateQualifiedHash {
+        hash: u32,
+        first: String,
+        second: String,
+    },
+    #[error("Types {first} and {second} have the same impact hash {hash:#010X}")]
+    DuplicateImpactHash {
+        hash: u32,
+        first: String,
+        second: String,
+    },
+    #[error("Type {type_name} has the missing inner type {index}")]
+    MissingInnerType {
+        type_name: String,
+        index: TypeIndex,
+    },
+    #[error("Field {field} of type {type_name} has the missing type {index}")]
+    MissingFieldType {
+        type_name: String,
+        field: String,
+        index: TypeIndex,
+    },
+    #[error("Attribute {attribute} of type {type_name} has the missing type {index}")]
+    MissingAttributeType {
+        type_name: String,
+        /// The field the attribute belongs to, or `None` for attributes of the type.
+        field: Option<String>,
+        attribute: String,
+        index: TypeIndex,
+    },
+    #[error("Typedef {type_name} has no inner type")]
+    TypedefWithoutInnerType {
+        type_name: String,
+    },
+    #[error("Inner types of {type_name} form a cycle")]
+    InnerTypeCycle {
+        type_name: String,
+    },
+}
+
+/// Collects types and validates them before they form a [`TypeRegistry`].
+///
+/// A registry relies on the types referencing each other only by valid indices,
+/// on unique hashes and on acyclic inner types. [`TypeRegistryBuilder::build`] checks all
+/// of them and reports every issue instead of failing at the first one, so types from
+/// untrusted sources like a cache on disk can be loaded without risking a panic.
+#[derive(Debug, Clone, Default)]
+pub struct TypeRegistryBuilder {
+    version: String,
+    types: Vec<TypeMetadata>,
+}
+
+impl TypeRegistryBuilder {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_version(mut self, version: impl Into<String>) -> Self {
+        self.version = version.into();
+        self
+    }
+
+    /// Adds a type, whose index must be its position in the order of insertion.
+    pub fn add_type(&mut self, r#type: TypeMetadata) -> &mut Self {
+        self.types.push(r#type);
+        self
+    }
+
+    pub fn add_types(&mut self, types: impl IntoIterator<Item = TypeMetadata>) -> &mut Self {
+        self.types.extend(types);
+        self
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.types.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+    }
+
+    /// Returns all issues which prevent the types from forming a registry.
+    pub fn validate(&self) -> Vec<TypeRegistryIssue> {
+        let mut issues = Vec::new();
+
+        self.validate_indices(&mut issues);
+        self.validate_hashes(&mut issues);
+        self.validate_references(&mut issues);
+        self.validate_inner_types(&mut issues);
+
+        issues
+    }
+
+    pub fn build(self) -> Result<TypeRegistry, TypeRegistryError> {
+        let issues = self.validate();
+
+        if !issues.is_empty() {
+            return Err(TypeRegistryError { issues });
+        }
+
+        let mut registry = TypeRegistry {
+            version: self.version,
+            ..Default::default()
+        };
+
+        registry.types_by_qualified_hash.reserve(self.types.len());
+
+        for r#type in &self.types {
+            if !r#type.flags.contains(TypeFlags::HAS_DS) {
+                registry.types_by_impact_hash.insert(r#type.impact_hash, r#type.index);
+            }
+
+            registry.types_by_qualified_hash.insert(r#type.qualified_hash, r#type.index);
+        }
+
+        registry.types = self.types;
+
+        Ok(registry)
+    }
+
+    fn validate_indices(&self, issues: &mut Vec<TypeRegistryIssue>) {
+        for (position, r#type) in self.types.iter().enumerate() {
+            if r#type.index.as_usize() != position {
+                issues.push(TypeRegistryIssue::IndexMismatch {
+                    type_name: r#type.qualified_name.clone(),
+                    position,
+                    index: r#type.index,
+                });
+            }
+        }
+    }
+
+    fn validate_hashes(&self, issues: &mut Vec<TypeRegistryIssue>) {
+        let mut qualified_hashes = HashMap::<u32, &TypeMetadata>::with_capacity(self.types.len());
+        let mut impact_hashes = HashMap::<u32, &TypeMetadata>::with_capacity(self.types.len());
+
+        for r#type in &self.types {
+            if let Some(previous) = qualified_hashes.insert(r#type.qualified_hash, r#type) {
+                issues.push(TypeRegistryIssue::DuplicateQualifiedHash {
+                    hash: r#type.qualified_hash,
+                    first: previous.qualified_name.clone(),
+                    second: r#type.qualified_name.clone(),
+                });
+            }
+
+            // types with ds data are not reachable by their impact hash
+            if r#type.flags.contains(TypeFlags::HAS_DS) {
+                continue;
+            }
+
+            if let Some(previous) = impact_hashes.insert(r#type.impact_hash, r#type) {
+                issues.push(TypeRegistryIssue::DuplicateImpactHash {
+                    hash: r#type.impact_hash,
+                    first: previous.qualified_name.clone(),
+                    second: r#type.qualified_name.clone(),
+                });
+            }
+        }
+    }
+
+    fn validate_references(&self, issues: &mut Vec<TypeRegistryIssue>) {
+        let is_valid = |index: TypeIndex| index.as_usize() < self.types.len();
+
+        let attribute_issues = |r#type: &TypeMetadata, field: Option<&str>, attributes: &IndexMap<String, Attribute>| {
+            attributes.values()
+                .filter_map(|attribute| attribute.r#type.map(|index| (attribute, index)))
+                .filter(|(_, index)| !is_valid(*index))
+                .map(|(attribute, index)| TypeRegistryIssue::MissingAttributeType {
+                    type_name: r#type.qualified_name.clone(),
+                    field: field.map(str::to_string),
+                    attribute: attribute.name.clone(),
+                    index,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        for r#type in &self.types {
+            match r#type.inner_type {
+                Some(index) if !is_valid(index) => {
+                    issues.push(TypeRegistryIssue::MissingInnerType {
+                        type_name: r#type.qualified_name.clone(),
+                        index,
+                    });
+                },
+                None if r#type.primitive_type == PrimitiveType::Typedef => {
+                    issues.push(TypeRegistryIssue::TypedefWithoutInnerType {
+                        type_name: r#type.qualified_name.clone(),
+                    });
+                },
+                _ => {},
+            }
+
+            for field in r#type.struct_fields.values() {
+                if !is_valid(field.r#type) {
+                    issues.push(TypeRegistryIssue::MissingFieldType {
+                        type_name: r#type.qualified_name.clone(),
+                        field: field.name.clone(),
+                        index: field.r#type,
+                    });
+                }
+
+                issues.extend(attribute_issues(r#type, Some(&field.name), &field.attributes));
+            }
+
+            issues.extend(attribute_issues(r#type, None, &r#type.attributes));
+        }
+    }
+
+    /// Finds cycles in the inner types, which would make typedefs and
+    /// inheritance chains endless.
+    fn validate_inner_types(&self, issues: &mut Vec<TypeRegistryIssue>) {
+        #[derive(Clone, Copy, PartialEq, Eq)]
+        enum State {
+            Unvisited,
+            Visiting,
+            Visited,
+        }
+
+        let mut states = vec![State::Unvisited; self.types.len()];
+        let mut path = Vec::new();
+
+        for start in 0..self.types.len() {
+            let mut current = Some(start);
+
+            // each type has at most one inner type, so the inner types form chains
+            while let Some(index) = current {
+                match states[index] {
+                    State::Unvisited => {},
+                    State::Visiting => {
+                        issues.push(TypeRegistryIssue::InnerTypeCycle {
+                            type_name: self.types[index].qualified_name.clone(),
+                        });
+                        break;
+                    },
+                    State::Visited => break,
+                }
+
+                states[index] = State::Visiting;
+                path.push(index);
+
+                current = self.types[index].inner_type
+                    .map(|inner| inner.as_usize())
+                    .filter(|&inner| inner < self.types.len());
+            }
+
+            for index in path.drain(..) {
+                states[index] = State::Visited;
+            }
+        }
+    }
+
+}
+
+fn format_issues(issues: &[TypeRegistryIssue]) -> String {
+    let mut message = issues.iter()
+        .take(MAX_DISPLAYED_ISSUES)
+        .map(|issue| issue.to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    if issues.len() > MAX_DISPLAYED_ISSUES {
+        message.push_str(&format!(" and {} more", issues.len() - MAX_DISPLAYED_ISSUES));
+    }
+
+    message
+}
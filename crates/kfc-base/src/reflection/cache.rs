@@ -0,0 +1,446 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use crc::{Crc, CRC_64_ECMA_182};
+use indexmap::IndexMap;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::io::{ReadExt, WriteExt};
+use crate::reflection::{Attribute, EnumFieldMetadata, PrimitiveType, StructFieldMetadata, TypeFlags, TypeIndex, TypeMetadata, TypeRegistry, TypeRegistryBuilder, TypeRegistryError};
+
+static CRC: Crc<u64> = Crc::<u64>::new(&CRC_64_ECMA_182);
+
+const CACHE_MAGIC: [u8; 4] = *b"KTRC";
+/// The version of the cache layout, which must be increased whenever the layout changes.
+const CACHE_VERSION: u32 = 1;
+
+/// Marks an absent string or type index.
+const NONE: u32 = u32::MAX;
+
+#[derive(Debug, Error)]
+pub enum TypeCacheError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Not a type registry cache")]
+    InvalidMagic,
+    #[error("Unsupported cache version {0}, expected {CACHE_VERSION}")]
+    UnsupportedVersion(u32),
+    #[error("Malformed cache: {0}")]
+    Malformed(&'static str),
+    #[error("{0}")]
+    InvalidTypes(#[from] TypeRegistryError),
+}
+
+/// Identifies the executable the types of a cache were extracted from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ExecutableFingerprint {
+    pub size: u64,
+    /// The modification time in nanoseconds since the unix epoch.
+    pub modified: u64,
+    /// The CRC-64 of the whole executable.
+    pub checksum: u64,
+}
+
+impl ExecutableFingerprint {
+
+    pub fn from_path(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let (size, modified) = file_info(path.as_ref())?;
+
+        Ok(Self {
+            size,
+            modified,
+            checksum: file_checksum(path.as_ref())?,
+        })
+    }
+
+    /// Returns true if the executable at the path is the one of this fingerprint.
+    ///
+    /// The checksum is only computed if the size matches but the modification time does not,
+    /// so the usual case of an unchanged executable does not read it.
+    pub fn matches(&self, path: impl AsRef<Path>) -> std::io::Result<bool> {
+        let (size, modified) = file_info(path.as_ref())?;
+
+        if size != self.size {
+            return Ok(false);
+        }
+
+        if modified == self.modified {
+            return Ok(true);
+        }
+
+        Ok(file_checksum(path.as_ref())? == self.checksum)
+    }
+
+}
+
+impl TypeRegistry {
+
+    /// Reads a registry from the binary cache format written by [`TypeRegistry::write_cache`].
+    ///
+    /// Returns the registry together with the fingerprint of the executable it was extracted from.
+    /// The types are validated like any other registry, so a damaged cache results in an error.
+    pub fn read_cache(data: &[u8]) -> Result<(Self, ExecutableFingerprint), TypeCacheError> {
+        let mut reader = CacheReader {
+            data,
+            strings: Vec::new(),
+        };
+
+        if reader.data.len() < CACHE_MAGIC.len() || reader.data[..CACHE_MAGIC.len()] != CACHE_MAGIC {
+            return Err(TypeCacheError::InvalidMagic);
+        }
+
+        reader.data = &reader.data[CACHE_MAGIC.len()..];
+
+        let version = reader.data.read_u32()?;
+
+        if version != CACHE_VERSION {
+            return Err(TypeCacheError::UnsupportedVersion(version));
+        }
+
+        let executable = ExecutableFingerprint {
+            size: reader.data.read_u64()?,
+            modified: reader.data.read_u64()?,
+            checksum: reader.data.read_u64()?,
+        };
+
+        let string_count = reader.read_count(4)?;
+        reader.strings.reserve_exact(string_count);
+
+        for _ in 0..string_count {
+            let len = reader.read_count(1)?;
+            let string = reader.data.read_string(len)?;
+
+            reader.strings.push(string);
+        }
+
+        let version = reader.read_string()?;
+        let type_count = reader.read_count(64)?;
+        let mut builder = TypeRegistryBuilder::new()
+            .with_version(version);
+
+        for index in 0..type_count {
+            builder.add_type(reader.read_type(index)?);
+        }
+
+        if !reader.data.is_empty() {
+            return Err(TypeCacheError::Malformed("trailing data"));
+        }
+
+        Ok((builder.build()?, executable))
+    }
+
+    /// Writes the registry in a compact binary format, which loads much faster than JSON.
+    ///
+    /// All strings are stored once in a string table and referenced by their index.
+    pub fn write_cache(
+        &self,
+        writer: &mut impl Write,
+        executable: &ExecutableFingerprint,
+    ) -> std::io::Result<()> {
+        let mut cache = CacheWriter::default();
+
+        cache.write_string(&self.version)?;
+        cache.types.write_u32(self.types.len() as u32)?;
+
+        for r#type in &self.types {
+            cache.write_type(r#type)?;
+        }
+
+        writer.write_all(&CACHE_MAGIC)?;
+        writer.write_u32(CACHE_VERSION)?;
+        writer.write_u64(executable.size)?;
+        writer.write_u64(executable.modified)?;
+        writer.write_u64(executable.checksum)?;
+
+        writer.write_u32(cache.strings.len() as u32)?;
+
+        for string in &cache.strings {
+            writer.write_u32(string.len() as u32)?;
+            writer.write_all(string.as_bytes())?;
+        }
+
+        writer.write_all(&cache.types)?;
+        writer.flush()
+    }
+
+}
+
+#[derive(Default)]
+struct CacheWriter<'a> {
+    strings: Vec<&'a str>,
+    string_indices: HashMap<&'a str, u32>,
+    types: Vec<u8>,
+}
+
+impl<'a> CacheWriter<'a> {
+
+    fn write_type(&mut self, r#type: &'a TypeMetadata) -> std::io::Result<()> {
+        self.write_string(&r#type.name)?;
+        self.write_string(&r#type.impact_name)?;
+        self.write_string(&r#type.qualified_name)?;
+        self.write_namespace(&r#type.namespace)?;
+        self.write_type_index(r#type.inner_type)?;
+
+        self.types.write_u32(r#type.size)?;
+        self.types.write_u16(r#type.alignment)?;
+        self.types.write_u16(r#type.element_alignment)?;
+        self.types.write_u32(r#type.field_count)?;
+        self.types.write_u8(r#type.primitive_type.to_u8())?;
+        self.types.write_u8(r#type.flags.bits())?;
+        self.types.write_u32(r#type.name_hash)?;
+        self.types.write_u32(r#type.impact_hash)?;
+        self.types.write_u32(r#type.qualified_hash)?;
+        self.types.write_u32(r#type.internal_hash)?;
+
+        self.types.write_u32(r#type.struct_fields.len() as u32)?;
+
+        for field in r#type.struct_fields.values() {
+            self.write_string(&field.name)?;
+            self.types.write_u32(field.r#type.as_usize() as u32)?;
+            self.types.write_u64(field.data_offset)?;
+            self.write_attributes(&field.attributes)?;
+        }
+
+        self.types.write_u32(r#type.enum_fields.len() as u32)?;
+
+        for field in r#type.enum_fields.values() {
+            self.write_string(&field.name)?;
+            self.types.write_u64(field.value)?;
+        }
+
+        match &r#type.default_value {
+            Some(default_value) => {
+                self.types.write_u32(default_value.len() as u32)?;
+                self.types.write_all(default_value)?;
+            },
+            None => self.types.write_u32(NONE)?,
+        }
+
+        self.write_attributes(&r#type.attributes)
+    }
+
+    fn write_attributes(&mut self, attributes: &'a IndexMap<String, Attribute>) -> std::io::Result<()> {
+        self.types.write_u32(attributes.len() as u32)?;
+
+        for attribute in attributes.values() {
+            self.write_string(&attribute.name)?;
+            self.write_namespace(&attribute.namespace)?;
+            self.write_type_index(attribute.r#type)?;
+            self.write_string(&attribute.value)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_namespace(&mut self, namespace: &'a [String]) -> std::io::Result<()> {
+        self.types.write_u32(namespace.len() as u32)?;
+
+        for name in namespace {
+            self.write_string(name)?;
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    fn write_type_index(&mut self, index: Option<TypeIndex>) -> std::io::Result<()> {
+        self.types.write_u32(index.map_or(NONE, |index| index.as_usize() as u32))
+    }
+
+    fn write_string(&mut self, string: &'a str) -> std::io::Result<()> {
+        let next_index = self.strings.len() as u32;
+        let index = *self.string_indices.entry(string).or_insert(next_index);
+
+        if index == next_index {
+            self.strings.push(string);
+        }
+
+        self.types.write_u32(index)
+    }
+
+}
+
+struct CacheReader<'a> {
+    data: &'a [u8],
+    strings: Vec<String>,
+}
+
+impl CacheReader<'_> {
+
+    fn read_type(&mut self, index: usize) -> Result<TypeMetadata, TypeCacheError> {
+        let name = self.read_string()?;
+        let impact_name = self.read_string()?;
+        let qualified_name = self.read_string()?;
+        let namespace = self.read_namespace()?;
+        let inner_type = self.read_type_index()?;
+
+        let size = self.data.read_u32()?;
+        let alignment = self.data.read_u16()?;
+        let element_alignment = self.data.read_u16()?;
+        let field_count = self.data.read_u32()?;
+        let primitive_type = match self.data.read_u8()? {
+            // from_u8 panics on unknown values
+            value if value <= PrimitiveType::Guid.to_u8() => PrimitiveType::from_u8(value),
+            _ => return Err(TypeCacheError::Malformed("invalid primitive type")),
+        };
+        let flags = TypeFlags::from_bits_retain(self.data.read_u8()?);
+        let name_hash = self.data.read_u32()?;
+        let impact_hash = self.data.read_u32()?;
+        let qualified_hash = self.data.read_u32()?;
+        let internal_hash = self.data.read_u32()?;
+
+        let struct_field_count = self.read_count(20)?;
+        let mut struct_fields = IndexMap::with_capacity(struct_field_count);
+
+        for _ in 0..struct_field_count {
+            let field = StructFieldMetadata {
+                name: self.read_string()?,
+                r#type: TypeIndex::new(self.data.read_u32()? as usize),
+                data_offset: self.data.read_u64()?,
+                attributes: self.read_attributes()?,
+            };
+
+            struct_fields.insert(field.name.clone(), field);
+        }
+
+        let enum_field_count = self.read_count(12)?;
+        let mut enum_fields = IndexMap::with_capacity(enum_field_count);
+
+        for _ in 0..enum_field_count {
+            let field = EnumFieldMetadata {
+                name: self.read_string()?,
+                value: self.data.read_u64()?,
+            };
+
+            enum_fields.insert(field.name.clone(), field);
+        }
+
+        let default_value = match self.data.read_u32()? {
+            NONE => None,
+            len => {
+                let len = len as usize;
+
+                if len > self.data.len() {
+                    return Err(TypeCacheError::Malformed("default value out of bounds"));
+                }
+
+                let (default_value, data) = self.data.split_at(len);
+                self.data = data;
+
+                Some(default_value.to_vec())
+            },
+        };
+
+        Ok(TypeMetadata {
+            index: TypeIndex::new(index),
+            name,
+            impact_name,
+            qualified_name,
+            namespace,
+            inner_type,
+            size,
+            alignment,
+            element_alignment,
+            field_count,
+            primitive_type,
+            flags,
+            name_hash,
+            impact_hash,
+            qualified_hash,
+            internal_hash,
+            struct_fields,
+            enum_fields,
+            default_value,
+            attributes: self.read_attributes()?,
+        })
+    }
+
+    fn read_attributes(&mut self) -> Result<IndexMap<String, Attribute>, TypeCacheError> {
+        let count = self.read_count(16)?;
+        let mut attributes = IndexMap::with_capacity(count);
+
+        for _ in 0..count {
+            let attribute = Attribute {
+                name: self.read_string()?,
+                namespace: self.read_namespace()?,
+                r#type: self.read_type_index()?,
+                value: self.read_string()?,
+            };
+
+            attributes.insert(attribute.name.clone(), attribute);
+        }
+
+        Ok(attributes)
+    }
+
+    fn read_namespace(&mut self) -> Result<Vec<String>, TypeCacheError> {
+        let count = self.read_count(4)?;
+
+        (0..count)
+            .map(|_| self.read_string())
+            .collect()
+    }
+
+    #[inline]
+    fn read_type_index(&mut self) -> Result<Option<TypeIndex>, TypeCacheError> {
+        Ok(match self.data.read_u32()? {
+            NONE => None,
+            index => Some(TypeIndex::new(index as usize)),
+        })
+    }
+
+    #[inline]
+    fn read_string(&mut self) -> Result<String, TypeCacheError> {
+        let index = self.data.read_u32()? as usize;
+
+        self.strings.get(index)
+            .cloned()
+            .ok_or(TypeCacheError::Malformed("string index out of bounds"))
+    }
+
+    /// Reads the number of following elements, which must fit into the remaining data
+    /// with the given minimum size per element, so a damaged count does not allocate too much.
+    fn read_count(&mut self, min_element_size: usize) -> Result<usize, TypeCacheError> {
+        let count = self.data.read_u32()? as usize;
+
+        if count.saturating_mul(min_element_size) > self.data.len() {
+            return Err(TypeCacheError::Malformed("element count out of bounds"));
+        }
+
+        Ok(count)
+    }
+
+}
+
+fn file_info(path: &Path) -> std::io::Result<(u64, u64)> {
+    let metadata = std::fs::metadata(path)?;
+    let modified = metadata.modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_nanos() as u64);
+
+    Ok((metadata.len(), modified))
+}
+
+fn file_checksum(path: &Path) -> std::io::Result<u64> {
+    let mut file = File::open(path)?;
+    let mut digest = CRC.digest();
+    let mut buffer = vec![0; 64 * 1024];
+
+    loop {
+        let len = match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        digest.update(&buffer[..len]);
+    }
+
+    Ok(digest.finalize())
+}
@@ -0,0 +1,331 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+
+use serde::Serialize;
+
+use crate::{container::KFCFile, reflection::{LookupKey, PrimitiveType, TypeIndex, TypeMetadata, TypeRegistry}};
+
+/// The differences between two type registries, usually of two game versions.
+///
+/// Types are matched by their qualified name.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TypeRegistryDiff {
+    pub old_version: String,
+    pub new_version: String,
+
+    pub added_types: Vec<String>,
+    pub removed_types: Vec<String>,
+    /// The changes of types which exist in both registries, by their qualified name.
+    pub changed_types: BTreeMap<String, TypeDiff>,
+    /// Types of both registries which did not change themselves, but contain a changed type,
+    /// for example as a field, array element or parent type.
+    pub affected_types: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TypeDiff {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub primitive_type: Option<ValueChange<PrimitiveType>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<ValueChange<u32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alignment: Option<ValueChange<u16>>,
+    /// The qualified name of the inner type, which is the parent type of structs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inner_type: Option<ValueChange<Option<String>>>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub added_fields: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub removed_fields: Vec<String>,
+    /// Fields which were removed and added with another name at the same offset and with the same type.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub renamed_fields: Vec<FieldRename>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub moved_fields: Vec<FieldChange<u64>>,
+    /// Fields whose type changed, by the qualified name of the type.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub retyped_fields: Vec<FieldChange<String>>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub added_enum_values: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub removed_enum_values: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub changed_enum_values: Vec<FieldChange<u64>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ValueChange<T> {
+    pub old: T,
+    pub new: T,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldChange<T> {
+    pub name: String,
+    pub old: T,
+    pub new: T,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldRename {
+    pub old_name: String,
+    pub new_name: String,
+    pub data_offset: u64,
+}
+
+impl TypeRegistryDiff {
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.added_types.is_empty() &&
+            self.removed_types.is_empty() &&
+            self.changed_types.is_empty()
+    }
+
+    /// Returns true if the type or any type it contains changed.
+    pub fn is_affected(&self, qualified_name: &str) -> bool {
+        self.changed_types.contains_key(qualified_name) ||
+            self.affected_types.binary_search_by(|name| name.as_str().cmp(qualified_name)).is_ok()
+    }
+
+    /// Returns the qualified names of all resource types of the archive which are affected by the changes.
+    ///
+    /// The type registry must be the new registry of the diff.
+    pub fn affected_resource_types(
+        &self,
+        file: &KFCFile,
+        type_registry: &TypeRegistry,
+    ) -> Vec<String> {
+        let mut types = file.resource_types()
+            .filter_map(|hash| type_registry.get_by_hash(LookupKey::Qualified(hash)))
+            .map(|t| &t.qualified_name)
+            .filter(|name| self.is_affected(name))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        types.sort();
+        types
+    }
+
+}
+
+impl TypeDiff {
+
+    pub fn is_empty(&self) -> bool {
+        self.primitive_type.is_none() &&
+            self.size.is_none() &&
+            self.alignment.is_none() &&
+            self.inner_type.is_none() &&
+            self.added_fields.is_empty() &&
+            self.removed_fields.is_empty() &&
+            self.renamed_fields.is_empty() &&
+            self.moved_fields.is_empty() &&
+            self.retyped_fields.is_empty() &&
+            self.added_enum_values.is_empty() &&
+            self.removed_enum_values.is_empty() &&
+            self.changed_enum_values.is_empty()
+    }
+
+}
+
+/// Compares two type registries.
+pub fn diff_type_registries(
+    old: &TypeRegistry,
+    new: &TypeRegistry,
+) -> TypeRegistryDiff {
+    let old_types = old.iter()
+        .map(|t| (t.qualified_name.as_str(), t))
+        .collect::<HashMap<_, _>>();
+    let new_types = new.iter()
+        .map(|t| (t.qualified_name.as_str(), t))
+        .collect::<HashMap<_, _>>();
+
+    let mut added_types = new_types.keys()
+        .filter(|name| !old_types.contains_key(*name))
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>();
+    let mut removed_types = old_types.keys()
+        .filter(|name| !new_types.contains_key(*name))
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>();
+
+    added_types.sort();
+    removed_types.sort();
+
+    let changed_types = new_types.iter()
+        .filter_map(|(name, new_type)| {
+            let old_type = old_types.get(name)?;
+            let diff = diff_types(old, old_type, new, new_type);
+
+            (!diff.is_empty()).then(|| (name.to_string(), diff))
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    let affected_types = find_affected_types(new, &changed_types)
+        .into_iter()
+        .filter(|name| old_types.contains_key(name.as_str()))
+        .collect();
+
+    TypeRegistryDiff {
+        old_version: old.version.clone(),
+        new_version: new.version.clone(),
+
+        added_types,
+        removed_types,
+        changed_types,
+        affected_types,
+    }
+}
+
+fn diff_types(
+    old_registry: &TypeRegistry,
+    old: &TypeMetadata,
+    new_registry: &TypeRegistry,
+    new: &TypeMetadata,
+) -> TypeDiff {
+    let old_type_name = |index: TypeIndex| type_name(old_registry, index);
+    let new_type_name = |index: TypeIndex| type_name(new_registry, index);
+
+    let mut diff = TypeDiff {
+        primitive_type: value_change(old.primitive_type, new.primitive_type),
+        size: value_change(old.size, new.size),
+        alignment: value_change(old.alignment, new.alignment),
+        inner_type: value_change(
+            old.inner_type.map(old_type_name),
+            new.inner_type.map(new_type_name),
+        ),
+        ..Default::default()
+    };
+
+    // struct fields
+
+    let mut removed_fields = Vec::new();
+
+    for (name, old_field) in &old.struct_fields {
+        let Some(new_field) = new.struct_fields.get(name) else {
+            removed_fields.push(old_field);
+            continue;
+        };
+
+        if old_field.data_offset != new_field.data_offset {
+            diff.moved_fields.push(FieldChange {
+                name: name.clone(),
+                old: old_field.data_offset,
+                new: new_field.data_offset,
+            });
+        }
+
+        let old_field_type = old_type_name(old_field.r#type);
+        let new_field_type = new_type_name(new_field.r#type);
+
+        if old_field_type != new_field_type {
+            diff.retyped_fields.push(FieldChange {
+                name: name.clone(),
+                old: old_field_type,
+                new: new_field_type,
+            });
+        }
+    }
+
+    let mut added_fields = new.struct_fields.values()
+        .filter(|field| !old.struct_fields.contains_key(&field.name))
+        .collect::<Vec<_>>();
+
+    // a field which disappeared while another one appeared at the same place was most likely renamed
+    removed_fields.retain(|old_field| {
+        let renamed = added_fields.iter().position(|new_field| {
+            new_field.data_offset == old_field.data_offset &&
+                new_type_name(new_field.r#type) == old_type_name(old_field.r#type)
+        });
+
+        let Some(index) = renamed else {
+            return true;
+        };
+
+        let new_field = added_fields.remove(index);
+
+        diff.renamed_fields.push(FieldRename {
+            old_name: old_field.name.clone(),
+            new_name: new_field.name.clone(),
+            data_offset: new_field.data_offset,
+        });
+
+        false
+    });
+
+    diff.removed_fields = removed_fields.into_iter().map(|field| field.name.clone()).collect();
+    diff.added_fields = added_fields.into_iter().map(|field| field.name.clone()).collect();
+
+    // enum values
+
+    for (name, old_value) in &old.enum_fields {
+        match new.enum_fields.get(name) {
+            Some(new_value) if new_value.value != old_value.value => {
+                diff.changed_enum_values.push(FieldChange {
+                    name: name.clone(),
+                    old: old_value.value,
+                    new: new_value.value,
+                });
+            },
+            Some(_) => {},
+            None => diff.removed_enum_values.push(name.clone()),
+        }
+    }
+
+    diff.added_enum_values = new.enum_fields.keys()
+        .filter(|name| !old.enum_fields.contains_key(*name))
+        .cloned()
+        .collect();
+
+    diff
+}
+
+/// Returns all unchanged types of the registry which contain one of the changed types.
+fn find_affected_types(
+    registry: &TypeRegistry,
+    changed_types: &BTreeMap<String, TypeDiff>,
+) -> Vec<String> {
+    // maps each type to the types which contain it
+    let mut dependents = HashMap::<TypeIndex, Vec<TypeIndex>>::new();
+
+    for t in registry.iter() {
+        let contained = t.inner_type.into_iter()
+            .chain(t.struct_fields.values().map(|field| field.r#type));
+
+        for index in contained {
+            dependents.entry(index).or_default().push(t.index);
+        }
+    }
+
+    let mut queue = registry.iter()
+        .filter(|t| changed_types.contains_key(&t.qualified_name))
+        .map(|t| t.index)
+        .collect::<VecDeque<_>>();
+    let mut affected = BTreeSet::new();
+
+    while let Some(index) = queue.pop_front() {
+        for &dependent in dependents.get(&index).into_iter().flatten() {
+            let Some(t) = registry.get(dependent) else {
+                continue;
+            };
+
+            if !changed_types.contains_key(&t.qualified_name) && affected.insert(t.qualified_name.clone()) {
+                queue.push_back(dependent);
+            }
+        }
+    }
+
+    affected.into_iter().collect()
+}
+
+#[inline]
+fn value_change<T: PartialEq>(old: T, new: T) -> Option<ValueChange<T>> {
+    (old != new).then_some(ValueChange { old, new })
+}
+
+fn type_name(registry: &TypeRegistry, index: TypeIndex) -> String {
+    registry.get(index)
+        .map(|t| t.qualified_name.clone())
+        .unwrap_or_else(|| format!("#{index}"))
+}
@@ -1,5 +1,7 @@
 use thiserror::Error;
 
+use crate::reflection::TypeRegistryError;
+
 #[derive(Debug, Error)]
 pub enum PEParseError {
     #[error("IO error: {0}")]
@@ -28,4 +30,6 @@ pub enum ReflectionParseError {
     MissingRDataSection,
     #[error("Malformed pattern")]
     MalformedPattern,
+    #[error("{0}")]
+    InvalidTypes(#[from] TypeRegistryError),
 }
@@ -1,8 +1,14 @@
 mod registry;
+mod builder;
 mod extract;
 mod serde;
 mod type_handle;
+mod diff;
+mod cache;
 
 pub use registry::*;
+pub use builder::*;
 pub use extract::*;
 pub use type_handle::*;
+pub use diff::*;
+pub use cache::*;
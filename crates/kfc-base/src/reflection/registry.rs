@@ -4,7 +4,7 @@ use std::fmt::{Debug, Display};
 use std::path::Path;
 
 use crate::hash::fnv;
-use crate::reflection::{PrimitiveType, ReflectionParseError, TypeFlags, TypeMetadata};
+use crate::reflection::{PrimitiveType, ReflectionParseError, TypeMetadata, TypeRegistryBuilder};
 
 #[derive(Debug, Default)]
 pub struct TypeRegistry {
@@ -53,12 +53,12 @@ impl TypeRegistry {
     pub fn load_from_executable(
         path: impl AsRef<Path>,
     ) -> Result<Self, ReflectionParseError> {
-        let mut registry = Self::default();
         let types = super::extract_reflection_data(path)?;
+        let mut builder = TypeRegistryBuilder::new();
 
-        registry.extend(types);
+        builder.add_types(types);
 
-        Ok(registry)
+        Ok(builder.build()?)
     }
 
     #[inline]
@@ -188,7 +188,7 @@ impl TypeRegistry {
     ) -> &'a TypeMetadata {
         match &r#type.primitive_type {
             PrimitiveType::Typedef => {
-                // TODO: ensure safe unwrap by validating it in [extend]
+                // typedefs always have an inner type, which is validated by the builder
                 self.get_inner_type(r#type)
                     .expect("missing inner type for typedef")
             },
@@ -196,48 +196,4 @@ impl TypeRegistry {
         }
     }
 
-    pub(super) fn extend(
-        &mut self,
-        types: Vec<TypeMetadata>
-    ) -> usize {
-        if self.types.is_empty() {
-            self.types.reserve_exact(types.len());
-            self.types_by_qualified_hash.reserve(types.len());
-        }
-
-        let len = types.len();
-
-        // TODO: handle duplicates properly
-        // TODO: maybe add validation at some point
-
-        for value in types {
-            if !value.flags.contains(TypeFlags::HAS_DS) {
-                if let Some(previous) = self.types_by_impact_hash.get(&value.impact_hash) {
-                    panic!(
-                        "Duplicate impact hash: {:#010X}, previous: {}, current: {}",
-                        value.impact_hash,
-                        self.get(*previous).unwrap().qualified_name,
-                        value.qualified_name
-                    );
-                }
-
-                self.types_by_impact_hash.insert(value.impact_hash, value.index);
-            }
-
-            if let Some(previous) = self.types_by_qualified_hash.get(&value.qualified_hash) {
-                panic!(
-                    "Duplicate qualified hash: {:#010X}, previous: {}, current: {}",
-                    value.qualified_hash,
-                    self.get(*previous).unwrap().qualified_name,
-                    value.qualified_name
-                );
-            }
-
-            self.types_by_qualified_hash.insert(value.qualified_hash, value.index);
-            self.types.push(value);
-        }
-
-        len
-    }
-
 }
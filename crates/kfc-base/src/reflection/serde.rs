@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-use crate::reflection::{TypeMetadata, TypeRegistry};
+use crate::reflection::{TypeMetadata, TypeRegistry, TypeRegistryBuilder};
 
 #[derive(Deserialize)]
 struct TypeRegistrySerdeOwned {
@@ -20,14 +20,11 @@ impl<'de> Deserialize<'de> for TypeRegistry {
         D: serde::Deserializer<'de>,
     {
         let data = TypeRegistrySerdeOwned::deserialize(deserializer)?;
-        let mut registry = Self {
-            version: data.version,
-            ..Default::default()
-        };
-
-        registry.extend(data.types);
+        let mut builder = TypeRegistryBuilder::new()
+            .with_version(data.version);
 
-        Ok(registry)
+        builder.add_types(data.types);
+        builder.build().map_err(serde::de::Error::custom)
     }
 }
 
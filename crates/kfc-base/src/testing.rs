@@ -0,0 +1,328 @@
+//! Helpers for building small synthetic archives in tests.
+//!
+//! Only available with the `testing` feature and in the tests of this crate.
+
+use std::path::{Path, PathBuf};
+
+use indexmap::IndexMap;
+use tempfile::TempDir;
+use thiserror::Error;
+
+use crate::{
+    container::{DiskStorage, KFCFile, KFCReadError, KFCReader, KFCReaderOptions, KFCStorage, KFCWriteError, KFCWriteOptions, KFCWriter},
+    guid::{ContentHash, Guid, ResourceId},
+    hash::fnv,
+    reflection::{EnumFieldMetadata, PrimitiveType, StructFieldMetadata, TypeFlags, TypeIndex, TypeMetadata, TypeRegistry, TypeRegistryBuilder},
+};
+
+#[derive(Debug, Error)]
+pub enum TestArchiveError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Read error: {0}")]
+    Read(#[from] KFCReadError),
+    #[error("Write error: {0}")]
+    Write(#[from] KFCWriteError),
+}
+
+/// Builds a small but valid archive through [`KFCWriter`].
+///
+/// All resource types must be known to the given type registry,
+/// see [`test_type`] and [`test_type_registry`] to create one by hand.
+pub struct TestArchiveBuilder {
+    type_registry: TypeRegistry,
+    file_name: String,
+    game_version: String,
+    options: KFCWriteOptions,
+
+    resources: Vec<(ResourceId, Vec<u8>)>,
+    contents: Vec<Vec<u8>>,
+}
+
+impl TestArchiveBuilder {
+
+    pub fn new(type_registry: TypeRegistry) -> Self {
+        Self {
+            type_registry,
+            file_name: "test".to_string(),
+            game_version: "test".to_string(),
+            options: KFCWriteOptions::default(),
+
+            resources: Vec::new(),
+            contents: Vec::new(),
+        }
+    }
+
+    pub fn with_file_name(mut self, file_name: impl Into<String>) -> Self {
+        self.file_name = file_name.into();
+        self
+    }
+
+    pub fn with_game_version(mut self, game_version: impl Into<String>) -> Self {
+        self.game_version = game_version.into();
+        self
+    }
+
+    pub fn with_options(mut self, options: KFCWriteOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn add_resource(&mut self, id: ResourceId, data: impl Into<Vec<u8>>) -> &mut Self {
+        self.resources.push((id, data.into()));
+        self
+    }
+
+    /// Adds a content and returns its hash.
+    pub fn add_content(&mut self, data: impl Into<Vec<u8>>) -> ContentHash {
+        let data = data.into();
+        let hash = ContentHash::from_data(&data);
+
+        self.contents.push(data);
+
+        hash
+    }
+
+    /// Writes the archive into a new temporary directory,
+    /// which is deleted once the returned [`TestArchive`] is dropped.
+    pub fn build(self) -> Result<TestArchive, TestArchiveError> {
+        let dir = tempfile::tempdir()?;
+
+        self.build_in(dir.path())?;
+
+        Ok(TestArchive {
+            dir,
+            file_name: self.file_name,
+            type_registry: self.type_registry,
+            options: self.options,
+        })
+    }
+
+    /// Writes the archive into the given directory.
+    pub fn build_in(&self, dir: &Path) -> Result<(), TestArchiveError> {
+        let storage = DiskStorage::new(dir, &self.file_name).with_extensions(
+            &self.options.kfc_extension,
+            &self.options.resource_extension,
+            &self.options.dat_extension,
+        );
+
+        self.build_in_storage(storage)
+    }
+
+    /// Writes the archive into the given storage.
+    /// Use a clone of a [`MemoryStorage`](crate::container::MemoryStorage) to keep the archive in memory.
+    pub fn build_in_storage(&self, storage: impl KFCStorage + 'static) -> Result<(), TestArchiveError> {
+        let mut writer = KFCWriter::<KFCFile, _>::with_storage(
+            storage,
+            &self.type_registry,
+            &self.game_version,
+            self.options.clone(),
+        )?;
+
+        for (id, data) in &self.resources {
+            writer.write_resource(id, data)?;
+        }
+
+        for data in &self.contents {
+            writer.write_content(&ContentHash::from_data(data), data)?;
+        }
+
+        writer.finalize()?;
+
+        Ok(())
+    }
+
+}
+
+/// An archive written to a temporary directory by [`TestArchiveBuilder`].
+pub struct TestArchive {
+    dir: TempDir,
+    file_name: String,
+    type_registry: TypeRegistry,
+    options: KFCWriteOptions,
+}
+
+impl TestArchive {
+
+    #[inline]
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    #[inline]
+    pub fn file_name(&self) -> &str {
+        &self.file_name
+    }
+
+    #[inline]
+    pub fn kfc_path(&self) -> PathBuf {
+        self.path().join(format!("{}.{}", self.file_name, self.options.kfc_extension))
+    }
+
+    #[inline]
+    pub fn type_registry(&self) -> &TypeRegistry {
+        &self.type_registry
+    }
+
+    #[inline]
+    pub fn reader(&self) -> Result<KFCReader, KFCReadError> {
+        KFCReader::new_with_options(
+            self.path(),
+            &self.file_name,
+            KFCReaderOptions {
+                kfc_extension: &self.options.kfc_extension,
+                dat_extension: &self.options.dat_extension,
+                resource_extension: &self.options.resource_extension,
+            },
+        )
+    }
+
+}
+
+/// Creates the metadata of a type without any fields.
+///
+/// All hashes are derived from the qualified name, so the type can be looked up
+/// with [`LookupKey::Qualified`](crate::reflection::LookupKey::Qualified).
+pub fn test_type(
+    index: usize,
+    qualified_name: &str,
+    primitive_type: PrimitiveType,
+    size: u32,
+) -> TypeMetadata {
+    let name = qualified_name.rsplit("::").next().unwrap_or(qualified_name);
+    let alignment = size.clamp(1, 8).next_power_of_two() as u16;
+
+    TypeMetadata {
+        index: TypeIndex::new(index),
+        name: name.to_string(),
+        impact_name: name.to_string(),
+        qualified_name: qualified_name.to_string(),
+        namespace: Vec::new(),
+        inner_type: None,
+        size,
+        alignment,
+        element_alignment: alignment,
+        field_count: 0,
+        primitive_type,
+        flags: TypeFlags::NONE,
+        name_hash: fnv(name),
+        impact_hash: fnv(name),
+        qualified_hash: fnv(qualified_name),
+        internal_hash: !fnv(qualified_name),
+        struct_fields: IndexMap::new(),
+        enum_fields: IndexMap::new(),
+        default_value: None,
+        attributes: IndexMap::new(),
+    }
+}
+
+/// Adds struct fields given as `(name, type index, data offset)` to a type.
+pub fn with_fields(mut t: TypeMetadata, fields: &[(&str, usize, u64)]) -> TypeMetadata {
+    for &(name, r#type, data_offset) in fields {
+        t.struct_fields.insert(name.to_string(), StructFieldMetadata {
+            name: name.to_string(),
+            r#type: TypeIndex::new(r#type),
+            data_offset,
+            attributes: Default::default(),
+        });
+    }
+
+    t.field_count = t.struct_fields.len() as u32;
+    t
+}
+
+/// Sets the inner type of a type.
+pub fn with_inner(mut t: TypeMetadata, inner_type: usize) -> TypeMetadata {
+    t.inner_type = Some(TypeIndex::new(inner_type));
+    t
+}
+
+/// Adds enum values given as `(name, value)` to a type.
+pub fn with_values(mut t: TypeMetadata, values: &[(&str, u64)]) -> TypeMetadata {
+    for &(name, value) in values {
+        t.enum_fields.insert(name.to_string(), EnumFieldMetadata {
+            name: name.to_string(),
+            value,
+        });
+    }
+
+    t
+}
+
+/// Creates a type registry from the given types.
+///
+/// # Panics
+/// Panics if the types are not valid, for example if two types share the same hash.
+pub fn test_type_registry(types: impl IntoIterator<Item = TypeMetadata>) -> TypeRegistry {
+    let mut builder = TypeRegistryBuilder::new()
+        .with_version("test");
+
+    builder.add_types(types);
+    builder.build().expect("invalid test types")
+}
+
+/// The resource types of [`sample_archive`].
+pub const SAMPLE_TYPE_A: &str = "keen::TestResourceA";
+pub const SAMPLE_TYPE_B: &str = "keen::TestResourceB";
+
+/// Creates a type registry with the resource types of [`sample_archive`].
+pub fn sample_type_registry() -> TypeRegistry {
+    test_type_registry([
+        test_type(0, SAMPLE_TYPE_A, PrimitiveType::Struct, 16),
+        test_type(1, SAMPLE_TYPE_B, PrimitiveType::Struct, 16),
+    ])
+}
+
+/// Creates the id of a resource, whose guid consists of the index only.
+pub fn sample_resource_id(index: u8, type_name: &str) -> ResourceId {
+    ResourceId::new(Guid::new([index; 16]), fnv(type_name), 0)
+}
+
+/// Creates data which differs for every index, so swapped resources are detected.
+pub fn sample_resource_data(index: u8, size: usize) -> Vec<u8> {
+    (0..size).map(|i| (i as u8) ^ index).collect()
+}
+
+/// Returns the type of the resource with the given index in [`sample_archive`].
+#[inline]
+pub fn sample_resource_type(index: u8) -> &'static str {
+    if index.is_multiple_of(2) { SAMPLE_TYPE_A } else { SAMPLE_TYPE_B }
+}
+
+/// Builds an archive with small chunks, so most resources span multiple chunks.
+///
+/// The archive contains 32 resources with `index * 997` bytes of [`sample_resource_data`],
+/// alternating between both sample types, and 8 contents with `5000 + index` bytes.
+pub fn sample_archive(compression_threads: usize) -> TestArchive {
+    let mut builder = TestArchiveBuilder::new(sample_type_registry())
+        .with_options(KFCWriteOptions {
+            resource_chunk_size: 4096,
+            compression_threads,
+            ..Default::default()
+        });
+
+    for i in 0..32 {
+        builder.add_resource(sample_resource_id(i, sample_resource_type(i)), sample_resource_data(i, i as usize * 997));
+    }
+
+    for i in 0..8 {
+        builder.add_content(sample_resource_data(i, 5000 + i as usize));
+    }
+
+    builder.build().expect("failed to build archive")
+}
+
+/// Reads all files of a directory, which is used to check that a directory was left untouched.
+pub fn read_dir_files(path: &Path) -> std::io::Result<std::collections::BTreeMap<String, Vec<u8>>> {
+    let mut files = std::collections::BTreeMap::new();
+
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+
+        if entry.file_type()?.is_file() {
+            files.insert(entry.file_name().to_string_lossy().into_owned(), std::fs::read(entry.path())?);
+        }
+    }
+
+    Ok(files)
+}
@@ -0,0 +1,483 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use kfc::reflection::{PrimitiveType, TypeIndex, TypeMetadata, TypeRegistry};
+
+const PRELUDE: &str = r#"// This file was automatically generated.
+
+use kfc::resource::typed::{LayoutCheck, TypedValue};
+use serde::{Deserialize, Serialize};
+
+"#;
+
+/// Static arrays up to this length are emitted as Rust arrays, longer ones as `Vec`,
+/// since serde only implements arrays up to this length.
+const MAX_ARRAY_LENGTH: u32 = 32;
+
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if",
+    "impl", "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override",
+    "priv", "pub", "ref", "return", "static", "struct", "trait", "true", "try", "type",
+    "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+/// Keywords which can not be used as raw identifiers.
+const RESERVED: &[&str] = &["crate", "self", "Self", "super"];
+
+/// Generates Rust types for the given types and all types they use.
+///
+/// Structs become structs which implement [`TypedValue`](crate::typed::TypedValue),
+/// with the fields of the parent type flattened into a `base` field. Enums become enums
+/// which are represented by the names of their values, bitmasks become a `Vec` of their
+/// bit enum and variants become enums over the base type and all types inheriting from it.
+/// Typedefs are replaced by the type they refer to.
+///
+/// Fields of types which can not be converted to values are left out,
+/// enum values without a name can not be represented.
+pub fn generate_rust(
+    type_registry: &TypeRegistry,
+    types: &[&TypeMetadata],
+) -> String {
+    let mut generator = RustGenerator::new(type_registry);
+
+    for r#type in types {
+        generator.collect(r#type);
+    }
+
+    generator.assign_names();
+
+    let mut output = String::new();
+
+    output.push_str(PRELUDE);
+
+    for index in &generator.types {
+        let r#type = type_registry.get(TypeIndex::new(*index))
+            .expect("collected types exist");
+
+        match r#type.primitive_type {
+            PrimitiveType::Struct => generator.append_struct(&mut output, r#type),
+            PrimitiveType::Enum => generator.append_enum(&mut output, r#type),
+            PrimitiveType::BlobVariant => generator.append_variant(&mut output, r#type),
+            _ => unreachable!("only structs, enums and variants are collected"),
+        }
+    }
+
+    output
+}
+
+struct RustGenerator<'a> {
+    type_registry: &'a TypeRegistry,
+    /// The indices of all types which are emitted, in the order of the registry.
+    types: BTreeSet<usize>,
+    names: HashMap<usize, String>,
+}
+
+impl<'a> RustGenerator<'a> {
+
+    fn new(type_registry: &'a TypeRegistry) -> Self {
+        Self {
+            type_registry,
+            types: BTreeSet::new(),
+            names: HashMap::new(),
+        }
+    }
+
+    fn collect(&mut self, r#type: &TypeMetadata) {
+        let type_registry = self.type_registry;
+
+        match r#type.primitive_type {
+            PrimitiveType::Struct => {
+                if !self.types.insert(r#type.index.as_usize()) {
+                    return;
+                }
+
+                if let Some(parent_type) = type_registry.get_inner_type(r#type) {
+                    self.collect(parent_type);
+                }
+
+                for field in r#type.struct_fields.values() {
+                    if let Some(field_type) = type_registry.get(field.r#type) {
+                        self.collect(field_type);
+                    }
+                }
+            },
+            PrimitiveType::Enum => {
+                self.types.insert(r#type.index.as_usize());
+            },
+            PrimitiveType::BlobVariant => {
+                if !self.types.insert(r#type.index.as_usize()) {
+                    return;
+                }
+
+                for variant_type in self.variant_types(r#type) {
+                    self.collect(variant_type);
+                }
+            },
+            PrimitiveType::Bitmask8 |
+            PrimitiveType::Bitmask16 |
+            PrimitiveType::Bitmask32 |
+            PrimitiveType::Bitmask64 |
+            PrimitiveType::Typedef |
+            PrimitiveType::StaticArray |
+            PrimitiveType::BlobArray |
+            PrimitiveType::BlobOptional => {
+                if let Some(inner_type) = type_registry.get_inner_type(r#type) {
+                    self.collect(inner_type);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    /// Returns the base type of the variant and all types inheriting from it.
+    fn variant_types(&self, r#type: &TypeMetadata) -> Vec<&'a TypeMetadata> {
+        let type_registry = self.type_registry;
+        let Some(base_type) = type_registry.get_inner_type(r#type) else {
+            return Vec::new();
+        };
+
+        type_registry.iter()
+            .filter(|t| t.primitive_type == PrimitiveType::Struct)
+            .filter(|t| type_registry.is_sub_type(base_type, t))
+            .collect()
+    }
+
+    /// Names each type after its short name, falling back to the qualified name
+    /// if multiple types have the same short name.
+    fn assign_names(&mut self) {
+        let mut used = HashSet::new();
+
+        for &index in &self.types {
+            let r#type = self.type_registry.get(TypeIndex::new(index))
+                .expect("collected types exist");
+
+            let (short_name, qualified_name) = match r#type.primitive_type {
+                PrimitiveType::BlobVariant => match self.type_registry.get_inner_type(r#type) {
+                    Some(base_type) => (
+                        format!("{}Variant", type_ident(&base_type.name)),
+                        format!("{}Variant", type_ident(&base_type.qualified_name)),
+                    ),
+                    None => (type_ident(&r#type.name), type_ident(&r#type.qualified_name)),
+                },
+                _ => (type_ident(&r#type.name), type_ident(&r#type.qualified_name)),
+            };
+
+            let name = [short_name, qualified_name.clone()].into_iter()
+                .chain((2..).map(|i| format!("{qualified_name}{i}")))
+                .find(|name| !used.contains(name))
+                .expect("infinite candidates");
+
+            used.insert(name.clone());
+            self.names.insert(index, name);
+        }
+    }
+
+    fn name(&self, r#type: &TypeMetadata) -> &str {
+        self.names.get(&r#type.index.as_usize())
+            .map(String::as_str)
+            .unwrap_or("()")
+    }
+
+    /// Returns the Rust type of a value, or `None` if the type can not be converted.
+    fn rust_type(&self, r#type: &TypeMetadata) -> Option<String> {
+        let type_registry = self.type_registry;
+        let inner_type = || match type_registry.get_inner_type(r#type) {
+            Some(inner_type) => self.rust_type(inner_type),
+            None => Some("()".to_string()),
+        };
+
+        Some(match r#type.primitive_type {
+            PrimitiveType::None => "()".to_string(),
+            PrimitiveType::Bool => "bool".to_string(),
+            PrimitiveType::UInt8 => "u8".to_string(),
+            PrimitiveType::SInt8 => "i8".to_string(),
+            PrimitiveType::UInt16 => "u16".to_string(),
+            PrimitiveType::SInt16 => "i16".to_string(),
+            PrimitiveType::UInt32 => "u32".to_string(),
+            PrimitiveType::SInt32 => "i32".to_string(),
+            PrimitiveType::UInt64 => "u64".to_string(),
+            PrimitiveType::SInt64 => "i64".to_string(),
+            PrimitiveType::Float32 => "f32".to_string(),
+            PrimitiveType::Float64 => "f64".to_string(),
+            PrimitiveType::Enum |
+            PrimitiveType::Struct |
+            PrimitiveType::BlobVariant => self.name(r#type).to_string(),
+            PrimitiveType::Bitmask8 |
+            PrimitiveType::Bitmask16 |
+            PrimitiveType::Bitmask32 |
+            PrimitiveType::Bitmask64 => format!("Vec<{}>", inner_type()?),
+            PrimitiveType::Typedef => inner_type()?,
+            PrimitiveType::StaticArray if r#type.field_count <= MAX_ARRAY_LENGTH => {
+                format!("[{}; {}]", inner_type()?, r#type.field_count)
+            },
+            PrimitiveType::StaticArray |
+            PrimitiveType::BlobArray => format!("Vec<{}>", inner_type()?),
+            PrimitiveType::BlobString => "String".to_string(),
+            // boxed, since structs may contain themselves through optionals
+            PrimitiveType::BlobOptional => match type_registry.get_inner_type(r#type) {
+                Some(t) if matches!(t.primitive_type, PrimitiveType::Struct | PrimitiveType::BlobVariant) => {
+                    format!("Option<Box<{}>>", self.rust_type(t)?)
+                },
+                _ => format!("Option<{}>", inner_type()?),
+            },
+            // references and guids are converted to strings, or null if they are not set
+            PrimitiveType::ObjectReference |
+            PrimitiveType::Guid => "Option<String>".to_string(),
+            PrimitiveType::DsArray |
+            PrimitiveType::DsString |
+            PrimitiveType::DsOptional |
+            PrimitiveType::DsVariant => return None,
+        })
+    }
+
+    fn append_struct(&self, output: &mut String, r#type: &TypeMetadata) {
+        let name = self.name(r#type);
+        let mut used = HashSet::new();
+
+        append_doc(output, r#type);
+        output.push_str("#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]\n");
+        output.push_str(&format!("pub struct {name} {{\n"));
+
+        if let Some(parent_type) = self.type_registry.get_inner_type(r#type) {
+            let field_name = unique_ident("base".to_string(), &mut used);
+
+            output.push_str("    #[serde(flatten)]\n");
+            output.push_str(&format!("    pub {field_name}: {},\n", self.name(parent_type)));
+        }
+
+        for field in r#type.struct_fields.values() {
+            let field_type = self.type_registry.get(field.r#type)
+                .and_then(|t| self.rust_type(t));
+
+            let Some(field_type) = field_type else {
+                output.push_str(&format!("    // `{}` can not be converted\n", field.name));
+                continue;
+            };
+
+            let field_name = unique_ident(field_ident(&field.name), &mut used);
+
+            if field_name.trim_start_matches("r#") != field.name {
+                output.push_str(&format!("    #[serde(rename = {:?})]\n", field.name));
+            }
+
+            output.push_str(&format!("    pub {field_name}: {field_type},\n"));
+        }
+
+        output.push_str("}\n\n");
+
+        output.push_str(&format!("impl TypedValue for {name} {{\n"));
+        output.push_str(&format!("    const QUALIFIED_NAME: &'static str = {:?};\n", r#type.qualified_name));
+        output.push_str(&format!("    const QUALIFIED_HASH: u32 = 0x{:08x};\n", r#type.qualified_hash));
+        output.push_str(&format!("    const SIZE: u32 = {};\n", r#type.size));
+        output.push_str(&format!("    const ALIGNMENT: u16 = {};\n", r#type.alignment));
+        output.push_str("    const FIELDS: &'static [(&'static str, u32, u64)] = &[\n");
+
+        for field in r#type.struct_fields.values() {
+            let field_hash = self.type_registry.get(field.r#type)
+                .map(|t| t.qualified_hash)
+                .unwrap_or_default();
+
+            output.push_str(&format!("        ({:?}, 0x{:08x}, {}),\n", field.name, field_hash, field.data_offset));
+        }
+
+        output.push_str("    ];\n");
+
+        let mut nested = BTreeSet::new();
+
+        if let Some(parent_type) = self.type_registry.get_inner_type(r#type) {
+            self.nested_structs(parent_type, &mut nested);
+        }
+
+        for field in r#type.struct_fields.values() {
+            if let Some(field_type) = self.type_registry.get(field.r#type) {
+                self.nested_structs(field_type, &mut nested);
+            }
+        }
+
+        if nested.is_empty() {
+            output.push_str("    const NESTED: &'static [LayoutCheck] = &[];\n");
+        } else {
+            output.push_str("    const NESTED: &'static [LayoutCheck] = &[\n");
+
+            for index in nested {
+                let nested_type = self.type_registry.get(TypeIndex::new(index))
+                    .expect("collected types exist");
+
+                output.push_str(&format!("        {}::check_nested_layout,\n", self.name(nested_type)));
+            }
+
+            output.push_str("    ];\n");
+        }
+        output.push_str("}\n\n");
+    }
+
+    /// Collects the struct types a value of the given type contains directly,
+    /// looking through the same types as [`RustGenerator::collect`].
+    fn nested_structs(&self, r#type: &TypeMetadata, nested: &mut BTreeSet<usize>) {
+        match r#type.primitive_type {
+            PrimitiveType::Struct => {
+                nested.insert(r#type.index.as_usize());
+            },
+            PrimitiveType::BlobVariant => {
+                for variant_type in self.variant_types(r#type) {
+                    nested.insert(variant_type.index.as_usize());
+                }
+            },
+            PrimitiveType::Typedef |
+            PrimitiveType::StaticArray |
+            PrimitiveType::BlobArray |
+            PrimitiveType::BlobOptional => {
+                if let Some(inner_type) = self.type_registry.get_inner_type(r#type) {
+                    self.nested_structs(inner_type, nested);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn append_enum(&self, output: &mut String, r#type: &TypeMetadata) {
+        let name = self.name(r#type);
+        let mut used = HashSet::new();
+        let variants = r#type.enum_fields.values()
+            .map(|field| (unique_ident(type_ident(&field.name), &mut used), field))
+            .collect::<Vec<_>>();
+
+        append_doc(output, r#type);
+        output.push_str("#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]\n");
+        output.push_str(&format!("pub enum {name} {{\n"));
+
+        for (variant, field) in &variants {
+            if *variant != field.name {
+                output.push_str(&format!("    #[serde(rename = {:?})]\n", field.name));
+            }
+
+            output.push_str(&format!("    {variant},\n"));
+        }
+
+        output.push_str("}\n\n");
+
+        // values are not used as discriminants, since multiple names may have the same value
+        output.push_str(&format!("impl {name} {{\n"));
+        output.push_str("    pub const fn value(self) -> u64 {\n");
+        output.push_str("        match self {\n");
+
+        for (variant, field) in &variants {
+            output.push_str(&format!("            Self::{variant} => {},\n", field.value));
+        }
+
+        output.push_str("        }\n");
+        output.push_str("    }\n");
+        output.push_str("}\n\n");
+    }
+
+    fn append_variant(&self, output: &mut String, r#type: &TypeMetadata) {
+        let name = self.name(r#type);
+        let mut used = HashSet::new();
+
+        append_doc(output, r#type);
+        output.push_str("#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]\n");
+        output.push_str("#[serde(tag = \"$type\", content = \"$value\")]\n");
+        output.push_str(&format!("pub enum {name} {{\n"));
+
+        for variant_type in self.variant_types(r#type) {
+            let variant = unique_ident(type_ident(&variant_type.name), &mut used);
+
+            output.push_str(&format!("    #[serde(rename = {:?})]\n", variant_type.qualified_name));
+            // boxed, since the variant types may contain the variant again
+            output.push_str(&format!("    {variant}(Box<{}>),\n", self.name(variant_type)));
+        }
+
+        output.push_str("}\n\n");
+    }
+
+}
+
+fn append_doc(output: &mut String, r#type: &TypeMetadata) {
+    output.push_str(&format!("/// `{}`\n", r#type.qualified_name));
+}
+
+/// Converts a name to an identifier in upper camel case.
+fn type_ident(name: &str) -> String {
+    let mut ident = String::new();
+
+    for word in name.split(|c: char| !c.is_ascii_alphanumeric()).filter(|word| !word.is_empty()) {
+        let mut chars = word.chars();
+        let first = chars.next().expect("words are not empty");
+        let rest = chars.as_str();
+
+        ident.push(first.to_ascii_uppercase());
+
+        // words in upper case like `SOME_VALUE` become `SomeValue`
+        if rest.chars().all(|c| !c.is_ascii_lowercase()) {
+            ident.push_str(&rest.to_ascii_lowercase());
+        } else {
+            ident.push_str(rest);
+        }
+    }
+
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, 'T');
+    }
+
+    escape_ident(ident)
+}
+
+/// Converts a field name to an identifier in snake case.
+fn field_ident(name: &str) -> String {
+    let chars = name.chars().collect::<Vec<_>>();
+    let mut ident = String::new();
+
+    for (i, &c) in chars.iter().enumerate() {
+        if !c.is_ascii_alphanumeric() {
+            if !ident.is_empty() && !ident.ends_with('_') {
+                ident.push('_');
+            }
+
+            continue;
+        }
+
+        if c.is_ascii_uppercase() && i > 0 && !ident.is_empty() && !ident.ends_with('_') {
+            let previous = chars[i - 1];
+            let next = chars.get(i + 1).copied().unwrap_or('_');
+
+            // split `fooBar` and the end of acronyms like `HTTPServer`
+            if previous.is_ascii_lowercase() ||
+                previous.is_ascii_digit() ||
+                (previous.is_ascii_uppercase() && next.is_ascii_lowercase())
+            {
+                ident.push('_');
+            }
+        }
+
+        ident.push(c.to_ascii_lowercase());
+    }
+
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_ascii_digit()) {
+        ident.insert(0, '_');
+    }
+
+    escape_ident(ident)
+}
+
+fn escape_ident(ident: String) -> String {
+    if RESERVED.contains(&ident.as_str()) {
+        format!("{ident}_")
+    } else if KEYWORDS.contains(&ident.as_str()) {
+        format!("r#{ident}")
+    } else {
+        ident
+    }
+}
+
+fn unique_ident(ident: String, used: &mut HashSet<String>) -> String {
+    let ident = (1..)
+        .map(|i| match i {
+            1 => ident.clone(),
+            i => format!("{ident}{i}"),
+        })
+        .find(|candidate| !used.contains(candidate))
+        .expect("infinite candidates");
+
+    used.insert(ident.clone());
+    ident
+}
@@ -1,2 +1,5 @@
+pub mod codegen;
 pub mod mapped;
+pub mod schema;
+pub mod typed;
 pub mod value;
@@ -0,0 +1,289 @@
+use std::fmt::Write;
+
+use kfc::reflection::{PrimitiveType, TypeMetadata, TypeRegistry};
+use serde_json::{json, Map, Value};
+
+const SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// Generates a JSON Schema for values of the given type in the shape produced by
+/// [`ConversionOptions::HUMAN_READABLE`](crate::value::ConversionOptions::HUMAN_READABLE).
+///
+/// Enums, bitmasks, structs and variants are placed in `$defs` by their qualified name.
+/// Structs contain the fields of their parent types, since inherited fields are flattened
+/// into the same object.
+pub fn generate_schema(
+    type_registry: &TypeRegistry,
+    r#type: &TypeMetadata,
+) -> Value {
+    let mut generator = SchemaGenerator::new(type_registry);
+    let schema = generator.type_schema(r#type);
+
+    generator.finish(r#type, schema)
+}
+
+/// Generates a JSON Schema for unpacked resource files of the given type.
+///
+/// In addition to the fields of the type, resource files contain
+/// the `$type`, `$guid` and `$part` fields which identify the resource.
+pub fn generate_resource_schema(
+    type_registry: &TypeRegistry,
+    r#type: &TypeMetadata,
+) -> Value {
+    let mut generator = SchemaGenerator::new(type_registry);
+    let mut schema = generator.struct_schema(r#type);
+
+    if let Some(properties) = schema["properties"].as_object_mut() {
+        properties.insert("$type".into(), json!({ "const": r#type.qualified_name }));
+        properties.insert("$guid".into(), json!({ "type": "string" }));
+        properties.insert("$part".into(), json!({ "type": "integer", "minimum": 0 }));
+    }
+
+    if let Some(required) = schema["required"].as_array_mut() {
+        required.push("$type".into());
+        required.push("$guid".into());
+    }
+
+    generator.finish(r#type, schema)
+}
+
+struct SchemaGenerator<'a> {
+    type_registry: &'a TypeRegistry,
+    definitions: Map<String, Value>,
+}
+
+impl<'a> SchemaGenerator<'a> {
+
+    fn new(type_registry: &'a TypeRegistry) -> Self {
+        Self {
+            type_registry,
+            definitions: Map::new(),
+        }
+    }
+
+    fn finish(self, r#type: &TypeMetadata, schema: Value) -> Value {
+        let mut root = Map::new();
+
+        root.insert("$schema".into(), SCHEMA_DIALECT.into());
+        root.insert("title".into(), r#type.qualified_name.clone().into());
+
+        match schema {
+            Value::Object(schema) => root.extend(schema),
+            // only `true` or `false`, which can not be merged
+            schema => {
+                root.insert("allOf".into(), json!([schema]));
+            }
+        }
+
+        if !self.definitions.is_empty() {
+            root.insert("$defs".into(), Value::Object(self.definitions));
+        }
+
+        Value::Object(root)
+    }
+
+    fn type_schema(&mut self, r#type: &TypeMetadata) -> Value {
+        match r#type.primitive_type {
+            PrimitiveType::None => json!({ "type": "null" }),
+            PrimitiveType::Bool => json!({ "type": "boolean" }),
+            PrimitiveType::UInt8 => integer_schema(u8::MIN, u8::MAX),
+            PrimitiveType::SInt8 => integer_schema(i8::MIN, i8::MAX),
+            PrimitiveType::UInt16 => integer_schema(u16::MIN, u16::MAX),
+            PrimitiveType::SInt16 => integer_schema(i16::MIN, i16::MAX),
+            PrimitiveType::UInt32 => integer_schema(u32::MIN, u32::MAX),
+            PrimitiveType::SInt32 => integer_schema(i32::MIN, i32::MAX),
+            PrimitiveType::UInt64 => integer_schema(u64::MIN, u64::MAX),
+            PrimitiveType::SInt64 => integer_schema(i64::MIN, i64::MAX),
+            PrimitiveType::Float32 | PrimitiveType::Float64 => json!({ "type": "number" }),
+            PrimitiveType::Enum |
+            PrimitiveType::Bitmask8 |
+            PrimitiveType::Bitmask16 |
+            PrimitiveType::Bitmask32 |
+            PrimitiveType::Bitmask64 |
+            PrimitiveType::Struct |
+            PrimitiveType::BlobVariant => self.definition(r#type),
+            PrimitiveType::Typedef => self.inner_type_schema(r#type),
+            PrimitiveType::StaticArray => json!({
+                "type": "array",
+                "items": self.inner_type_schema(r#type),
+                "minItems": r#type.field_count,
+                "maxItems": r#type.field_count,
+            }),
+            PrimitiveType::BlobArray => json!({
+                "type": "array",
+                "items": self.inner_type_schema(r#type),
+            }),
+            PrimitiveType::BlobString => json!({ "type": "string" }),
+            PrimitiveType::BlobOptional => match self.type_registry.get_inner_type(r#type) {
+                Some(inner_type) => json!({
+                    "anyOf": [
+                        { "type": "null" },
+                        self.type_schema(inner_type),
+                    ],
+                }),
+                None => json!({ "type": "null" }),
+            },
+            PrimitiveType::ObjectReference => {
+                let mut schema = json!({ "type": ["string", "null"] });
+
+                if let Some(inner_type) = self.type_registry.get_inner_type(r#type) {
+                    schema["description"] = format!("Reference to {}", inner_type.qualified_name).into();
+                }
+
+                schema
+            },
+            PrimitiveType::Guid => json!({ "type": ["string", "null"] }),
+            // values of these types can not be converted yet, so anything is accepted
+            PrimitiveType::DsArray |
+            PrimitiveType::DsString |
+            PrimitiveType::DsOptional |
+            PrimitiveType::DsVariant => json!({}),
+        }
+    }
+
+    fn inner_type_schema(&mut self, r#type: &TypeMetadata) -> Value {
+        match self.type_registry.get_inner_type(r#type) {
+            Some(inner_type) => self.type_schema(inner_type),
+            None => json!({}),
+        }
+    }
+
+    /// Adds the type to `$defs` if it is not there yet and returns a reference to it.
+    fn definition(&mut self, r#type: &TypeMetadata) -> Value {
+        let name = &r#type.qualified_name;
+
+        if !self.definitions.contains_key(name) {
+            // reserve the name first, so recursive types terminate
+            self.definitions.insert(name.clone(), Value::Null);
+
+            let mut schema = match r#type.primitive_type {
+                PrimitiveType::Enum => self.enum_schema(r#type),
+                PrimitiveType::Struct => self.struct_schema(r#type),
+                PrimitiveType::BlobVariant => self.variant_schema(r#type),
+                _ => self.bitmask_schema(r#type),
+            };
+
+            schema["title"] = name.clone().into();
+
+            self.definitions.insert(name.clone(), schema);
+        }
+
+        json!({ "$ref": definition_ref(name) })
+    }
+
+    /// Enum values are represented by their name, unnamed values by their number.
+    fn enum_schema(&self, r#type: &TypeMetadata) -> Value {
+        json!({
+            "anyOf": [
+                { "enum": r#type.enum_fields.keys().collect::<Vec<_>>() },
+                { "type": "integer" },
+            ],
+        })
+    }
+
+    /// Bitmasks are represented by the names of the set bits, unnamed bits by their number.
+    fn bitmask_schema(&self, r#type: &TypeMetadata) -> Value {
+        let names = self.type_registry.get_inner_type(r#type)
+            .map(|bit_type| bit_type.enum_fields.keys().collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        json!({
+            "type": "array",
+            "uniqueItems": true,
+            "items": {
+                "anyOf": [
+                    { "enum": names },
+                    { "type": "integer", "minimum": 0 },
+                ],
+            },
+        })
+    }
+
+    fn struct_schema(&mut self, r#type: &TypeMetadata) -> Value {
+        let mut hierarchy = vec![r#type];
+
+        while let Some(parent_type) = self.type_registry.get_inner_type(hierarchy[hierarchy.len() - 1]) {
+            hierarchy.push(parent_type);
+        }
+
+        let mut properties = Map::new();
+        let mut required = Vec::new();
+
+        // parent fields come first, like in the converted values
+        for current in hierarchy.into_iter().rev() {
+            for field in current.struct_fields.values() {
+                let schema = match self.type_registry.get(field.r#type) {
+                    Some(field_type) => self.type_schema(field_type),
+                    None => json!({}),
+                };
+
+                properties.insert(field.name.clone(), schema);
+                required.push(field.name.clone());
+            }
+        }
+
+        json!({
+            "type": "object",
+            "properties": properties,
+            "required": required,
+            "additionalProperties": false,
+        })
+    }
+
+    /// Variants are represented by an object with the qualified name of the actual type
+    /// in `$type` and its value in `$value`. The actual type is the base type or any type
+    /// which inherits from it.
+    fn variant_schema(&mut self, r#type: &TypeMetadata) -> Value {
+        let Some(base_type) = self.type_registry.get_inner_type(r#type) else {
+            return json!({});
+        };
+
+        let type_registry = self.type_registry;
+        let variants = type_registry.iter()
+            .filter(|t| t.primitive_type == PrimitiveType::Struct)
+            .filter(|t| type_registry.is_sub_type(base_type, t))
+            .map(|t| json!({
+                "type": "object",
+                "properties": {
+                    "$type": { "const": t.qualified_name },
+                    "$value": self.definition(t),
+                },
+                "required": ["$type", "$value"],
+                "additionalProperties": false,
+            }))
+            .collect::<Vec<_>>();
+
+        json!({ "oneOf": variants })
+    }
+
+}
+
+fn integer_schema(minimum: impl Into<Value>, maximum: impl Into<Value>) -> Value {
+    json!({
+        "type": "integer",
+        "minimum": minimum.into(),
+        "maximum": maximum.into(),
+    })
+}
+
+/// Returns the URI reference of a definition,
+/// escaped as a JSON pointer and percent-encoded for the URI fragment.
+fn definition_ref(name: &str) -> String {
+    let mut reference = String::from("#/$defs/");
+
+    for c in name.chars() {
+        match c {
+            '~' => reference.push_str("~0"),
+            '/' => reference.push_str("~1"),
+            c if c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | ':') => reference.push(c),
+            c => {
+                let mut buffer = [0; 4];
+
+                for byte in c.encode_utf8(&mut buffer).bytes() {
+                    write!(reference, "%{byte:02X}").unwrap();
+                }
+            }
+        }
+    }
+
+    reference
+}
@@ -0,0 +1,182 @@
+use std::collections::HashSet;
+
+use kfc::reflection::{LookupKey, TypeMetadata, TypeRegistry};
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+use crate::{mapped::MappingError, value::{ConversionOptions, Value, WriteError}};
+
+#[derive(Debug, Error)]
+pub enum TypedValueError {
+    #[error("type not found: {0}")]
+    TypeNotFound(&'static str),
+    #[error("layout of {name} does not match the type registry: {reason}")]
+    LayoutMismatch {
+        name: &'static str,
+        reason: String,
+    },
+
+    #[error("mapping error: {0}")]
+    Mapping(#[from] MappingError),
+    #[error("write error: {0}")]
+    Write(#[from] WriteError),
+    #[error("conversion error: {0}")]
+    Conversion(#[from] serde_json::Error),
+}
+
+/// Checks the layout of a type and the types it contains,
+/// see [`TypedValue::check_nested_layout`].
+pub type LayoutCheck = fn(&TypeRegistry, &mut HashSet<&'static str>) -> Result<(), TypedValueError>;
+
+/// A struct type of the type registry with a Rust representation,
+/// usually emitted by [`generate_rust`](crate::codegen::generate_rust).
+///
+/// The Rust type has the shape produced by [`ConversionOptions::HUMAN_READABLE`]
+/// and is converted through [`Value`], while the constants describe the blob layout
+/// the type was generated for. [`TypedValue::check_layout`] compares them with a
+/// type registry, so changes of a new game version are detected before any data is read.
+pub trait TypedValue: Serialize + DeserializeOwned {
+    const QUALIFIED_NAME: &'static str;
+    const QUALIFIED_HASH: u32;
+    const SIZE: u32;
+    const ALIGNMENT: u16;
+    /// The names, qualified type hashes and offsets of the fields declared by this type,
+    /// without inherited fields.
+    const FIELDS: &'static [(&'static str, u32, u64)];
+    /// The layout checks of the struct types this type contains, including its parent type.
+    const NESTED: &'static [LayoutCheck];
+
+    /// Returns the type in the registry if it and the types it contains
+    /// still have the layout of their Rust types.
+    fn check_layout(type_registry: &TypeRegistry) -> Result<&TypeMetadata, TypedValueError> {
+        let r#type = check_own_layout::<Self>(type_registry)?;
+        let mut checked = HashSet::from([Self::QUALIFIED_NAME]);
+
+        for check in Self::NESTED {
+            check(type_registry, &mut checked)?;
+        }
+
+        Ok(r#type)
+    }
+
+    /// Checks the layout of this type and the types it contains,
+    /// unless it was checked already, since types may contain themselves.
+    fn check_nested_layout(
+        type_registry: &TypeRegistry,
+        checked: &mut HashSet<&'static str>,
+    ) -> Result<(), TypedValueError> {
+        if !checked.insert(Self::QUALIFIED_NAME) {
+            return Ok(());
+        }
+
+        check_own_layout::<Self>(type_registry)?;
+
+        for check in Self::NESTED {
+            check(type_registry, checked)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads the value from blob data after checking the layout.
+    fn from_bytes(
+        type_registry: &TypeRegistry,
+        data: &[u8],
+    ) -> Result<Self, TypedValueError> {
+        let r#type = Self::check_layout(type_registry)?;
+        let value = Value::from_bytes_with_options(
+            type_registry,
+            r#type,
+            data,
+            ConversionOptions::HUMAN_READABLE,
+        )?;
+
+        Self::from_value(&value)
+    }
+
+    /// Writes the value as blob data after checking the layout.
+    fn to_bytes(
+        &self,
+        type_registry: &TypeRegistry,
+    ) -> Result<Vec<u8>, TypedValueError> {
+        let r#type = Self::check_layout(type_registry)?;
+
+        Ok(self.to_value()?.to_bytes(type_registry, r#type)?)
+    }
+
+    /// Converts a value in the shape of [`ConversionOptions::HUMAN_READABLE`].
+    fn from_value(value: &Value) -> Result<Self, TypedValueError> {
+        Ok(serde_json::from_value(serde_json::to_value(value)?)?)
+    }
+
+    fn to_value(&self) -> Result<Value, TypedValueError> {
+        Ok(serde_json::from_value(serde_json::to_value(self)?)?)
+    }
+}
+
+/// Compares the hash, size, alignment and fields of a type with the registry,
+/// without the types it contains.
+fn check_own_layout<T: TypedValue>(
+    type_registry: &TypeRegistry,
+) -> Result<&TypeMetadata, TypedValueError> {
+    let r#type = type_registry.get_by_name(LookupKey::Qualified(T::QUALIFIED_NAME))
+        .ok_or(TypedValueError::TypeNotFound(T::QUALIFIED_NAME))?;
+
+    let mismatch = |reason: String| TypedValueError::LayoutMismatch {
+        name: T::QUALIFIED_NAME,
+        reason,
+    };
+
+    if r#type.qualified_hash != T::QUALIFIED_HASH {
+        return Err(mismatch(format!(
+            "qualified hash is {:08x}, expected {:08x}",
+            r#type.qualified_hash,
+            T::QUALIFIED_HASH
+        )));
+    }
+
+    if r#type.size != T::SIZE || r#type.alignment != T::ALIGNMENT {
+        return Err(mismatch(format!(
+            "size {} with alignment {}, expected size {} with alignment {}",
+            r#type.size,
+            r#type.alignment,
+            T::SIZE,
+            T::ALIGNMENT
+        )));
+    }
+
+    if r#type.struct_fields.len() != T::FIELDS.len() {
+        return Err(mismatch(format!(
+            "{} fields, expected {}",
+            r#type.struct_fields.len(),
+            T::FIELDS.len()
+        )));
+    }
+
+    for &(name, type_hash, offset) in T::FIELDS {
+        let Some(field) = r#type.struct_fields.get(name) else {
+            return Err(mismatch(format!("field {name} is missing")));
+        };
+
+        if field.data_offset != offset {
+            return Err(mismatch(format!(
+                "field {} is at offset {}, expected {}",
+                name,
+                field.data_offset,
+                offset
+            )));
+        }
+
+        let field_hash = type_registry.get(field.r#type)
+            .map(|t| t.qualified_hash)
+            .unwrap_or_default();
+
+        if field_hash != type_hash {
+            return Err(mismatch(format!(
+                "field {name} has the type {field_hash:08x}, expected {type_hash:08x}"
+            )));
+        }
+    }
+
+    Ok(r#type)
+}
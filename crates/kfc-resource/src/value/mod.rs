@@ -344,3 +344,57 @@ impl_into!(Vec<Value>, Array);
 impl_into!(Variant, Variant);
 impl_into!(Box<Variant>, Variant);
 impl_into!(Guid, Guid);
+
+#[cfg(test)]
+mod tests {
+    use kfc::{
+        reflection::{LookupKey, PrimitiveType, TypeRegistry},
+        testing::{test_type, test_type_registry, with_fields, with_inner, with_values},
+    };
+
+    use super::*;
+
+    fn settings_type_registry() -> TypeRegistry {
+        test_type_registry([
+            test_type(0, "uint32", PrimitiveType::UInt32, 4),
+            test_type(1, "float32", PrimitiveType::Float32, 4),
+            with_inner(with_values(test_type(2, "keen::Mode", PrimitiveType::Enum, 4), &[
+                ("Off", 0),
+                ("FULL_SCREEN", 1),
+            ]), 0),
+            with_fields(test_type(3, "keen::Settings", PrimitiveType::Struct, 12), &[
+                ("mode", 2, 0),
+                ("scaleFactor", 1, 4),
+                ("id", 0, 8),
+            ]),
+        ])
+    }
+
+    #[test]
+    fn test_value_round_trip() {
+        let type_registry = settings_type_registry();
+        let r#type = type_registry.get_by_name(LookupKey::Qualified("keen::Settings")).unwrap();
+
+        let mut data = Vec::new();
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&1.5f32.to_le_bytes());
+        data.extend_from_slice(&7u32.to_le_bytes());
+
+        for options in [ConversionOptions::COMPACT, ConversionOptions::HUMAN_READABLE] {
+            let value = Value::from_bytes_with_options(&type_registry, r#type, &data, options.clone()).unwrap();
+            let Value::Struct(fields) = &value else {
+                panic!("expected a struct, got {value:?}");
+            };
+
+            let mode = match options.enum_repr {
+                EnumRepr::Value => Value::UInt(1),
+                EnumRepr::Name => Value::String("FULL_SCREEN".to_string()),
+            };
+
+            assert_eq!(fields["mode"], mode);
+            assert_eq!(fields["scaleFactor"], Value::Float(1.5));
+            assert_eq!(fields["id"], Value::UInt(7));
+            assert_eq!(value.to_bytes(&type_registry, r#type).unwrap(), data);
+        }
+    }
+}
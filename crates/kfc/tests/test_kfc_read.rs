@@ -1,6 +1,6 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{collections::HashMap, io::{Cursor, Read, Seek, SeekFrom}, path::PathBuf};
 
-use kfc::{container::KFCReader, guid::ContentHash};
+use kfc::{container::{diff_files, KFCManifest, KFCReader, KFCVerifyOptions}, guid::ContentHash};
 use kfc_base::{container::KFCFile, reflection::{LookupKey, TypeRegistry}};
 
 fn get_game_dir() -> PathBuf {
@@ -131,3 +131,121 @@ fn test_validate_kfc() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+#[ignore = "requires GAME_DIR environment variable"]
+fn test_mapped_cursor() -> Result<(), Box<dyn std::error::Error>> {
+    let mut buf = Vec::new();
+
+    let dir = get_game_dir();
+    let kfc_reader = KFCReader::new(&dir, "enshrouded")?;
+    let mut reader = kfc_reader.new_cursor()?;
+    let mut mapped_reader = kfc_reader.new_mapped_cursor()?;
+
+    for guid in kfc_reader.file().resources().keys() {
+        buf.clear();
+
+        assert!(reader.read_resource_into(guid, &mut buf)?, "Resource {guid} not found in KFC file");
+
+        let mapped = mapped_reader.read_resource(guid)?;
+        assert!(mapped.is_some(), "Resource {guid} not found in mapped KFC file");
+
+        assert_eq!(buf, mapped.unwrap().as_ref(), "Mapped data for resource {guid} does not match");
+    }
+
+    for guid in kfc_reader.file().contents().keys() {
+        buf.clear();
+
+        assert!(reader.read_content_into(guid, &mut buf)?, "Content {guid} not found in KFC file");
+
+        let mapped = mapped_reader.read_content(guid)?;
+        assert!(mapped.is_some(), "Content {guid} not found in mapped KFC file");
+
+        assert_eq!(buf, mapped.unwrap(), "Mapped data for content {guid} does not match");
+    }
+
+    Ok(())
+}
+
+#[test]
+#[ignore = "requires GAME_DIR environment variable"]
+fn test_open_resource() -> Result<(), Box<dyn std::error::Error>> {
+    let mut buf = Vec::new();
+    let mut streamed = Vec::new();
+
+    let dir = get_game_dir();
+    let kfc_reader = KFCReader::new(&dir, "enshrouded")?;
+    let mut reader = kfc_reader.new_cursor()?;
+
+    for guid in kfc_reader.file().resources().keys() {
+        buf.clear();
+        streamed.clear();
+
+        assert!(reader.read_resource_into(guid, &mut buf)?, "Resource {guid} not found in KFC file");
+
+        let mut resource = reader.open_resource(guid)?
+            .expect("resource exists");
+
+        assert_eq!(resource.size(), buf.len() as u64, "Resource {guid} has an unexpected size");
+
+        resource.read_to_end(&mut streamed)?;
+        assert_eq!(buf, streamed, "Streamed data for resource {guid} does not match");
+
+        // seek into the middle and read the tail again
+        let middle = buf.len() as u64 / 2;
+
+        streamed.clear();
+        resource.seek(SeekFrom::Start(middle))?;
+        resource.read_to_end(&mut streamed)?;
+
+        assert_eq!(&buf[middle as usize..], streamed, "Streamed tail for resource {guid} does not match");
+    }
+
+    Ok(())
+}
+
+#[test]
+#[ignore = "requires GAME_DIR environment variable"]
+fn test_verify_kfc() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = get_game_dir();
+    let kfc_reader = KFCReader::new(&dir, "enshrouded")?;
+    let report = kfc_reader.verify(&KFCVerifyOptions::default())?;
+
+    for issue in &report.issues {
+        println!("{:?}: {}", issue.severity(), issue);
+    }
+
+    assert!(report.is_ok(), "Found {} errors in the KFC file", report.errors().count());
+
+    Ok(())
+}
+
+#[test]
+#[ignore = "requires GAME_DIR environment variable"]
+fn test_diff_identical() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = get_game_dir();
+    let type_registry = TypeRegistry::load_from_executable(dir.join("enshrouded.exe"))?;
+    let kfc_file = KFCFile::from_path(dir.join("enshrouded.kfc"), false)?;
+
+    let diff = diff_files(&kfc_file, &kfc_file, &type_registry);
+
+    assert!(diff.is_empty(), "Diff of identical files is not empty: {diff:?}");
+
+    Ok(())
+}
+
+#[test]
+#[ignore = "requires GAME_DIR environment variable"]
+fn test_manifest_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+    let data = std::fs::read(get_game_dir().join("enshrouded.kfc"))?;
+
+    let manifest = KFCManifest::read(&mut Cursor::new(&data))?;
+    let manifest = serde_json::from_str::<KFCManifest>(&serde_json::to_string(&manifest)?)?;
+
+    let mut rebuilt = Cursor::new(Vec::new());
+    manifest.write_index(&mut rebuilt)?;
+
+    assert!(rebuilt.into_inner() == data, "rebuilt index differs from the original");
+
+    Ok(())
+}
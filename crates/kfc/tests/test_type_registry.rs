@@ -1,6 +1,10 @@
 use std::path::PathBuf;
 
-use kfc_base::reflection::TypeRegistry;
+use kfc::resource::{codegen::generate_rust, schema::{generate_resource_schema, generate_schema}, typed::{LayoutCheck, TypedValue, TypedValueError}};
+
+use serde::{Deserialize, Serialize};
+use kfc::hash::fnv;
+use kfc_base::{reflection::{diff_type_registries, Attribute, FieldChange, FieldRename, ExecutableFingerprint, LookupKey, PrimitiveType, TypeCacheError, TypeIndex, TypeMetadata, TypeRegistry, TypeRegistryBuilder, TypeRegistryIssue, ValueChange}, testing::{test_type, test_type_registry, with_fields, with_inner, with_values}};
 
 fn get_game_dir() -> PathBuf {
     std::env::var("GAME_DIR")
@@ -20,3 +24,572 @@ fn test_validate_type_registry() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[test]
+fn test_diff_type_registries() {
+    let old = test_type_registry([
+        test_type(0, "uint32", PrimitiveType::UInt32, 4),
+        test_type(1, "float32", PrimitiveType::Float32, 4),
+        with_fields(test_type(2, "keen::Stats", PrimitiveType::Struct, 12), &[
+            ("health", 0, 0),
+            ("speed", 1, 4),
+            ("armor", 0, 8),
+        ]),
+        with_fields(test_type(3, "keen::Player", PrimitiveType::Struct, 12), &[
+            ("stats", 2, 0),
+        ]),
+        with_fields(test_type(4, "keen::Team", PrimitiveType::Struct, 12), &[
+            ("leader", 3, 0),
+        ]),
+        with_fields(test_type(5, "keen::League", PrimitiveType::Struct, 12), &[
+            ("team", 4, 0),
+        ]),
+        with_values(test_type(6, "keen::Faction", PrimitiveType::Enum, 4), &[
+            ("Neutral", 0),
+            ("Hostile", 1),
+        ]),
+        test_type(7, "keen::Removed", PrimitiveType::Struct, 4),
+        with_fields(test_type(8, "keen::Unchanged", PrimitiveType::Struct, 4), &[
+            ("value", 0, 0),
+        ]),
+    ]);
+
+    // the types are in another order, so their indices differ
+    let new = test_type_registry([
+        test_type(0, "float32", PrimitiveType::Float32, 4),
+        test_type(1, "uint32", PrimitiveType::UInt32, 4),
+        with_fields(test_type(2, "keen::Stats", PrimitiveType::Struct, 16), &[
+            ("health", 1, 0),
+            ("stamina", 1, 4),
+            ("defense", 1, 8),
+            ("speed", 0, 12),
+        ]),
+        with_fields(test_type(3, "keen::Player", PrimitiveType::Struct, 16), &[
+            ("stats", 2, 0),
+        ]),
+        with_fields(test_type(4, "keen::Team", PrimitiveType::Struct, 12), &[
+            ("leader", 3, 0),
+        ]),
+        with_fields(test_type(5, "keen::League", PrimitiveType::Struct, 12), &[
+            ("team", 4, 0),
+        ]),
+        with_fields(test_type(6, "keen::Wrapper", PrimitiveType::Struct, 16), &[
+            ("player", 3, 0),
+        ]),
+        with_values(test_type(7, "keen::Faction", PrimitiveType::Enum, 4), &[
+            ("Neutral", 0),
+            ("Friendly", 1),
+            ("Hostile", 2),
+        ]),
+        test_type(8, "keen::Added", PrimitiveType::Struct, 4),
+        with_fields(test_type(9, "keen::Unchanged", PrimitiveType::Struct, 4), &[
+            ("value", 1, 0),
+        ]),
+    ]);
+
+    let diff = diff_type_registries(&old, &new);
+
+    assert_eq!(diff.added_types, ["keen::Added", "keen::Wrapper"]);
+    assert_eq!(diff.removed_types, ["keen::Removed"]);
+    assert_eq!(
+        diff.changed_types.keys().map(String::as_str).collect::<Vec<_>>(),
+        ["keen::Faction", "keen::Player", "keen::Stats"]
+    );
+
+    let stats = &diff.changed_types["keen::Stats"];
+    assert_eq!(stats.size, Some(ValueChange { old: 12, new: 16 }));
+    assert_eq!(stats.alignment, None);
+    assert_eq!(stats.added_fields, ["stamina"]);
+    assert!(stats.removed_fields.is_empty());
+    assert_eq!(stats.renamed_fields, [FieldRename {
+        old_name: "armor".to_string(),
+        new_name: "defense".to_string(),
+        data_offset: 8,
+    }]);
+    assert_eq!(stats.moved_fields, [FieldChange { name: "speed".to_string(), old: 4, new: 12 }]);
+    assert!(stats.retyped_fields.is_empty());
+
+    let faction = &diff.changed_types["keen::Faction"];
+    assert_eq!(faction.added_enum_values, ["Friendly"]);
+    assert!(faction.removed_enum_values.is_empty());
+    assert_eq!(faction.changed_enum_values, [FieldChange { name: "Hostile".to_string(), old: 1, new: 2 }]);
+
+    // types which only contain changed types are affected, new types are not
+    assert_eq!(diff.affected_types, ["keen::League", "keen::Team"]);
+    assert!(diff.is_affected("keen::Player"));
+    assert!(diff.is_affected("keen::League"));
+    assert!(!diff.is_affected("keen::Unchanged"));
+
+    assert!(diff_type_registries(&new, &new).is_empty());
+}
+
+#[test]
+fn test_generate_schema() {
+    let mut items = with_inner(test_type(9, "keen::StaticArray<uint32,2>", PrimitiveType::StaticArray, 8), 0);
+    items.field_count = 2;
+
+    let type_registry = test_type_registry([
+        test_type(0, "uint32", PrimitiveType::UInt32, 4),
+        test_type(1, "float32", PrimitiveType::Float32, 4),
+        with_inner(with_values(test_type(2, "keen::Flag", PrimitiveType::Enum, 4), &[
+            ("Visible", 0),
+            ("Solid", 1),
+        ]), 0),
+        with_inner(test_type(3, "keen::Flags", PrimitiveType::Bitmask32, 4), 2),
+        with_fields(test_type(4, "keen::Shape", PrimitiveType::Struct, 4), &[
+            ("flags", 3, 0),
+        ]),
+        with_inner(with_fields(test_type(5, "keen::Circle", PrimitiveType::Struct, 8), &[
+            ("radius", 1, 4),
+        ]), 4),
+        with_inner(test_type(6, "keen::BlobVariant<keen::Shape>", PrimitiveType::BlobVariant, 12), 4),
+        with_inner(test_type(7, "keen::BlobOptional<float32>", PrimitiveType::BlobOptional, 4), 1),
+        with_inner(test_type(8, "keen::BlobArray<keen::BlobVariant<keen::Shape>>", PrimitiveType::BlobArray, 8), 6),
+        items,
+        with_fields(test_type(10, "keen::Scene", PrimitiveType::Struct, 24), &[
+            ("shapes", 8, 0),
+            ("scale", 7, 8),
+            ("items", 9, 12),
+        ]),
+    ]);
+
+    let scene = type_registry.get_by_name(LookupKey::Qualified("keen::Scene")).unwrap();
+    let schema = generate_resource_schema(&type_registry, scene);
+
+    assert_eq!(schema["title"], "keen::Scene");
+    assert_eq!(schema["additionalProperties"], false);
+    assert_eq!(schema["properties"]["$type"]["const"], "keen::Scene");
+    assert_eq!(schema["required"], serde_json::json!(["shapes", "scale", "items", "$type", "$guid"]));
+
+    let properties = &schema["properties"];
+    assert_eq!(properties["scale"]["anyOf"][0]["type"], "null");
+    assert_eq!(properties["scale"]["anyOf"][1]["type"], "number");
+    assert_eq!(properties["items"]["minItems"], 2);
+    assert_eq!(properties["items"]["maxItems"], 2);
+    assert_eq!(properties["items"]["items"]["maximum"], u32::MAX);
+    assert_eq!(properties["shapes"]["items"]["$ref"], "#/$defs/keen::BlobVariant%3Ckeen::Shape%3E");
+
+    // variants can hold the base type or any type inheriting from it
+    let defs = &schema["$defs"];
+    let variants = defs["keen::BlobVariant<keen::Shape>"]["oneOf"].as_array().unwrap();
+    let variant_types = variants.iter()
+        .map(|v| v["properties"]["$type"]["const"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(variant_types, ["keen::Shape", "keen::Circle"]);
+    assert_eq!(variants[1]["properties"]["$value"]["$ref"], "#/$defs/keen::Circle");
+
+    // inherited fields are part of the derived struct
+    assert_eq!(defs["keen::Circle"]["required"], serde_json::json!(["flags", "radius"]));
+    assert_eq!(defs["keen::Circle"]["properties"]["flags"]["$ref"], "#/$defs/keen::Flags");
+
+    assert_eq!(defs["keen::Flags"]["type"], "array");
+    assert_eq!(defs["keen::Flags"]["items"]["anyOf"][0]["enum"], serde_json::json!(["Visible", "Solid"]));
+    // the bits are part of the bitmask, so the enum is not referenced
+    assert!(defs.get("keen::Flag").is_none());
+
+    let flag = type_registry.get_by_name(LookupKey::Qualified("keen::Flag")).unwrap();
+    let schema = generate_schema(&type_registry, flag);
+
+    assert_eq!(schema["$ref"], "#/$defs/keen::Flag");
+    assert_eq!(schema["$defs"]["keen::Flag"]["anyOf"][0]["enum"], serde_json::json!(["Visible", "Solid"]));
+}
+
+fn settings_type_registry(settings_size: u32) -> TypeRegistry {
+    test_type_registry(settings_types(settings_size))
+}
+
+fn settings_types(settings_size: u32) -> Vec<TypeMetadata> {
+    vec![
+        test_type(0, "uint32", PrimitiveType::UInt32, 4),
+        test_type(1, "float32", PrimitiveType::Float32, 4),
+        with_inner(with_values(test_type(2, "keen::Mode", PrimitiveType::Enum, 4), &[
+            ("Off", 0),
+            ("FULL_SCREEN", 1),
+        ]), 0),
+        with_fields(test_type(3, "keen::Base", PrimitiveType::Struct, 4), &[
+            ("id", 0, 0),
+        ]),
+        with_inner(with_fields(test_type(4, "keen::Settings", PrimitiveType::Struct, settings_size), &[
+            ("mode", 2, 4),
+            ("scaleFactor", 1, 8),
+            ("type", 0, 12),
+        ]), 3),
+        test_type(5, "keen::Unused", PrimitiveType::Struct, 4),
+    ]
+}
+
+// the types as emitted by `generate_rust` for `settings_type_registry`
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Base {
+    pub id: u32,
+}
+
+impl TypedValue for Base {
+    const QUALIFIED_NAME: &'static str = "keen::Base";
+    const QUALIFIED_HASH: u32 = 0x3dc4196b;
+    const SIZE: u32 = 4;
+    const ALIGNMENT: u16 = 4;
+    const FIELDS: &'static [(&'static str, u32, u64)] = &[
+        ("id", 0x32940bec, 0),
+    ];
+    const NESTED: &'static [LayoutCheck] = &[];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Mode {
+    Off,
+    #[serde(rename = "FULL_SCREEN")]
+    FullScreen,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Settings {
+    #[serde(flatten)]
+    pub base: Base,
+    pub mode: Mode,
+    #[serde(rename = "scaleFactor")]
+    pub scale_factor: f32,
+    pub r#type: u32,
+}
+
+impl TypedValue for Settings {
+    const QUALIFIED_NAME: &'static str = "keen::Settings";
+    const QUALIFIED_HASH: u32 = 0xb1ba77d3;
+    const SIZE: u32 = 16;
+    const ALIGNMENT: u16 = 8;
+    const FIELDS: &'static [(&'static str, u32, u64)] = &[
+        ("mode", 0x9540162d, 4),
+        ("scaleFactor", 0xe89f7410, 8),
+        ("type", 0x32940bec, 12),
+    ];
+    const NESTED: &'static [LayoutCheck] = &[
+        Base::check_nested_layout,
+    ];
+}
+
+#[test]
+fn test_generate_rust() {
+    let type_registry = settings_type_registry(16);
+    let settings = type_registry.get_by_name(LookupKey::Qualified("keen::Settings")).unwrap();
+    let code = generate_rust(&type_registry, &[settings]);
+
+    let expected = [
+        "pub struct Base {\n    pub id: u32,\n}",
+        "pub enum Mode {\n    Off,\n    #[serde(rename = \"FULL_SCREEN\")]\n    FullScreen,\n}",
+        "            Self::FullScreen => 1,\n",
+        "#[serde(flatten)]\n    pub base: Base,\n    pub mode: Mode,\n    #[serde(rename = \"scaleFactor\")]\n    pub scale_factor: f32,\n    pub r#type: u32,\n}",
+        &format!("const QUALIFIED_HASH: u32 = 0x{:08x};", Base::QUALIFIED_HASH),
+        &format!("const QUALIFIED_HASH: u32 = 0x{:08x};", Settings::QUALIFIED_HASH),
+        "    const SIZE: u32 = 16;\n    const ALIGNMENT: u16 = 8;\n",
+        "        (\"mode\", 0x9540162d, 4),\n        (\"scaleFactor\", 0xe89f7410, 8),\n        (\"type\", 0x32940bec, 12),\n",
+        "    const NESTED: &'static [LayoutCheck] = &[\n        Base::check_nested_layout,\n    ];\n",
+        "    const NESTED: &'static [LayoutCheck] = &[];\n",
+    ];
+
+    for snippet in expected {
+        assert!(code.contains(snippet), "missing {snippet:?} in:\n{code}");
+    }
+
+    assert!(!code.contains("Unused"));
+}
+
+#[test]
+fn test_typed_value() -> Result<(), Box<dyn std::error::Error>> {
+    let type_registry = settings_type_registry(16);
+
+    let settings = Settings {
+        base: Base { id: 7 },
+        mode: Mode::FullScreen,
+        scale_factor: 1.5,
+        r#type: 3,
+    };
+
+    let data = settings.to_bytes(&type_registry)?;
+
+    assert_eq!(data.len(), 16);
+    assert_eq!(&data[0..4], &7u32.to_le_bytes());
+    assert_eq!(&data[4..8], &1u32.to_le_bytes());
+    assert_eq!(&data[8..12], &1.5f32.to_le_bytes());
+    assert_eq!(Settings::from_bytes(&type_registry, &data)?, settings);
+
+    // a new game version changed the size of the type
+    let type_registry = settings_type_registry(20);
+
+    assert!(matches!(
+        Settings::from_bytes(&type_registry, &data),
+        Err(TypedValueError::LayoutMismatch { name: "keen::Settings", .. })
+    ));
+
+    // the type of a field changed, but not its offset
+    let mut types = settings_types(16);
+    types[4] = with_inner(with_fields(test_type(4, "keen::Settings", PrimitiveType::Struct, 16), &[
+        ("mode", 2, 4),
+        ("scaleFactor", 1, 8),
+        ("type", 1, 12),
+    ]), 3);
+
+    assert!(matches!(
+        Settings::from_bytes(&test_type_registry(types), &data),
+        Err(TypedValueError::LayoutMismatch { name: "keen::Settings", .. })
+    ));
+
+    // the parent type changed, while the type itself is the same
+    let mut types = settings_types(16);
+    types[3] = with_fields(test_type(3, "keen::Base", PrimitiveType::Struct, 4), &[
+        ("id", 1, 0),
+    ]);
+
+    assert!(matches!(
+        Settings::from_bytes(&test_type_registry(types), &data),
+        Err(TypedValueError::LayoutMismatch { name: "keen::Base", .. })
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_type_registry_builder() {
+    let mut builder = TypeRegistryBuilder::new()
+        .with_version("1");
+
+    builder.add_types([
+        test_type(0, "uint32", PrimitiveType::UInt32, 4),
+        with_fields(test_type(1, "keen::Item", PrimitiveType::Struct, 4), &[("id", 0, 0)]),
+        with_inner(test_type(2, "keen::ItemId", PrimitiveType::Typedef, 4), 0),
+    ]);
+
+    assert!(builder.validate().is_empty());
+
+    let registry = builder.build().unwrap();
+    assert_eq!(registry.version, "1");
+    assert_eq!(registry.len(), 3);
+    assert_eq!(registry.unwrap_typedef(registry.get(TypeIndex::new(2)).unwrap()).qualified_name, "uint32");
+}
+
+#[test]
+fn test_type_registry_builder_issues() {
+    let mut duplicate = test_type(1, "keen::Item", PrimitiveType::Struct, 4);
+    duplicate.qualified_name = "keen::Duplicate".to_string();
+
+    let mut attribute_type = with_fields(test_type(3, "keen::Attributed", PrimitiveType::Struct, 4), &[("id", 0, 0)]);
+    attribute_type.struct_fields["id"].attributes.insert("range".to_string(), Attribute {
+        name: "range".to_string(),
+        namespace: Vec::new(),
+        r#type: Some(TypeIndex::new(50)),
+        value: String::new(),
+    });
+
+    let mut builder = TypeRegistryBuilder::new();
+
+    builder.add_types([
+        test_type(0, "uint32", PrimitiveType::UInt32, 4),
+        test_type(1, "keen::Item", PrimitiveType::Struct, 4),
+        duplicate,
+        attribute_type,
+        with_fields(test_type(4, "keen::Dangling", PrimitiveType::Struct, 4), &[("value", 99, 0)]),
+        with_inner(test_type(5, "keen::Orphan", PrimitiveType::BlobArray, 8), 42),
+        test_type(6, "keen::EmptyTypedef", PrimitiveType::Typedef, 4),
+        with_inner(test_type(7, "keen::A", PrimitiveType::Typedef, 4), 8),
+        with_inner(test_type(8, "keen::B", PrimitiveType::Typedef, 4), 7),
+        test_type(10, "keen::Misplaced", PrimitiveType::Struct, 4),
+    ]);
+
+    let issues = builder.validate();
+    let expected = [
+        TypeRegistryIssue::IndexMismatch {
+            type_name: "keen::Duplicate".to_string(),
+            position: 2,
+            index: TypeIndex::new(1),
+        },
+        TypeRegistryIssue::IndexMismatch {
+            type_name: "keen::Misplaced".to_string(),
+            position: 9,
+            index: TypeIndex::new(10),
+        },
+        TypeRegistryIssue::DuplicateQualifiedHash {
+            hash: fnv("keen::Item"),
+            first: "keen::Item".to_string(),
+            second: "keen::Duplicate".to_string(),
+        },
+        TypeRegistryIssue::DuplicateImpactHash {
+            hash: fnv("Item"),
+            first: "keen::Item".to_string(),
+            second: "keen::Duplicate".to_string(),
+        },
+        TypeRegistryIssue::MissingAttributeType {
+            type_name: "keen::Attributed".to_string(),
+            field: Some("id".to_string()),
+            attribute: "range".to_string(),
+            index: TypeIndex::new(50),
+        },
+        TypeRegistryIssue::MissingFieldType {
+            type_name: "keen::Dangling".to_string(),
+            field: "value".to_string(),
+            index: TypeIndex::new(99),
+        },
+        TypeRegistryIssue::MissingInnerType {
+            type_name: "keen::Orphan".to_string(),
+            index: TypeIndex::new(42),
+        },
+        TypeRegistryIssue::TypedefWithoutInnerType {
+            type_name: "keen::EmptyTypedef".to_string(),
+        },
+        TypeRegistryIssue::InnerTypeCycle {
+            type_name: "keen::A".to_string(),
+        },
+    ];
+
+    assert_eq!(issues, expected);
+
+    let error = builder.build().unwrap_err();
+    assert_eq!(error.issues.len(), expected.len());
+    assert!(error.to_string().contains("keen::Dangling"));
+}
+
+#[test]
+fn test_deserialize_invalid_type_registry() {
+    let registry = test_type_registry([
+        test_type(0, "uint32", PrimitiveType::UInt32, 4),
+        with_fields(test_type(1, "keen::Item", PrimitiveType::Struct, 4), &[("id", 0, 0)]),
+    ]);
+
+    let mut json = serde_json::to_value(&registry).unwrap();
+    assert!(serde_json::from_value::<TypeRegistry>(json.clone()).is_ok());
+
+    // a tampered cache must fail to load instead of panicking
+    json["types"][1]["structFields"]["id"]["type"] = 7.into();
+
+    let error = serde_json::from_value::<TypeRegistry>(json).unwrap_err();
+    assert!(error.to_string().contains("missing type 7"), "{error}");
+}
+
+fn cache_type_registry() -> TypeRegistry {
+    let mut attributed = with_fields(test_type(2, "keen::Item", PrimitiveType::Struct, 8), &[("id", 1, 0), ("count", 0, 4)]);
+    attributed.namespace = vec!["keen".to_string()];
+    attributed.default_value = Some(vec![1, 2, 3, 4, 5, 6, 7, 8]);
+    attributed.struct_fields["count"].attributes.insert("range".to_string(), Attribute {
+        name: "range".to_string(),
+        namespace: vec!["keen".to_string()],
+        r#type: Some(TypeIndex::new(0)),
+        value: "0..100".to_string(),
+    });
+
+    let mut registry = test_type_registry([
+        test_type(0, "uint32", PrimitiveType::UInt32, 4),
+        with_inner(test_type(1, "keen::ItemId", PrimitiveType::Typedef, 4), 0),
+        attributed,
+        with_values(test_type(3, "keen::Rarity", PrimitiveType::Enum, 4), &[("Common", 0), ("Rare", 2)]),
+    ]);
+
+    registry.version = "1234".to_string();
+    registry
+}
+
+const TEST_EXECUTABLE: ExecutableFingerprint = ExecutableFingerprint {
+    size: 1024,
+    modified: 1_700_000_000_000_000_000,
+    checksum: 0x0123_4567_89ab_cdef,
+};
+
+#[test]
+fn test_type_registry_cache() {
+    let registry = cache_type_registry();
+    let mut data = Vec::new();
+
+    registry.write_cache(&mut data, &TEST_EXECUTABLE).unwrap();
+
+    let (cached, executable) = TypeRegistry::read_cache(&data).unwrap();
+
+    assert_eq!(executable, TEST_EXECUTABLE);
+    assert_eq!(cached.version, "1234");
+    assert_eq!(serde_json::to_value(&cached).unwrap(), serde_json::to_value(&registry).unwrap());
+    assert_eq!(cached.get_by_name(LookupKey::Qualified("keen::Item")).unwrap().index, TypeIndex::new(2));
+
+    // strings are stored once
+    let json = serde_json::to_vec(&registry).unwrap();
+    assert!(data.len() < json.len(), "{} >= {}", data.len(), json.len());
+}
+
+#[test]
+fn test_read_invalid_type_registry_cache() {
+    let registry = cache_type_registry();
+    let mut data = Vec::new();
+
+    registry.write_cache(&mut data, &TEST_EXECUTABLE).unwrap();
+
+    assert!(matches!(
+        TypeRegistry::read_cache(b"{\"version\":\"1234\"}"),
+        Err(TypeCacheError::InvalidMagic)
+    ));
+
+    let mut other_version = data.clone();
+    other_version[4] += 1;
+
+    assert!(matches!(
+        TypeRegistry::read_cache(&other_version),
+        Err(TypeCacheError::UnsupportedVersion(2))
+    ));
+
+    // every truncation must be detected without panicking
+    for len in 0..data.len() {
+        assert!(TypeRegistry::read_cache(&data[..len]).is_err(), "truncated to {len} bytes");
+    }
+
+    let mut trailing = data.clone();
+    trailing.push(0);
+
+    assert!(matches!(
+        TypeRegistry::read_cache(&trailing),
+        Err(TypeCacheError::Malformed(_))
+    ));
+
+    // a dangling inner type is rejected by the validation
+    let mut tampered = test_type_registry([
+        test_type(0, "uint32", PrimitiveType::UInt32, 4),
+        with_inner(test_type(1, "keen::ItemId", PrimitiveType::Typedef, 4), 0),
+    ]);
+    let mut data = Vec::new();
+
+    tampered.version = "1234".to_string();
+    tampered.write_cache(&mut data, &TEST_EXECUTABLE).unwrap();
+
+    // the inner type of the last type is followed by 46 bytes of fixed size fields
+    let inner_type = data.len() - 46 - 4;
+    assert_eq!(data[inner_type..inner_type + 4], 0u32.to_le_bytes());
+    data[inner_type..inner_type + 4].copy_from_slice(&7u32.to_le_bytes());
+
+    assert!(matches!(
+        TypeRegistry::read_cache(&data),
+        Err(TypeCacheError::InvalidTypes(_))
+    ));
+}
+
+#[test]
+fn test_executable_fingerprint() {
+    let path = std::env::temp_dir().join(format!("kfc-fingerprint-{}.exe", std::process::id()));
+
+    std::fs::write(&path, b"executable").unwrap();
+
+    let fingerprint = ExecutableFingerprint::from_path(&path).unwrap();
+    assert_eq!(fingerprint.size, 10);
+    assert!(fingerprint.matches(&path).unwrap());
+
+    // a different modification time alone does not invalidate the fingerprint
+    let touched = ExecutableFingerprint {
+        modified: fingerprint.modified + 1,
+        ..fingerprint
+    };
+    assert!(touched.matches(&path).unwrap());
+
+    std::fs::write(&path, b"Executable").unwrap();
+
+    // same size, but the contents changed
+    assert_ne!(ExecutableFingerprint::from_path(&path).unwrap().checksum, fingerprint.checksum);
+    assert!(!touched.matches(&path).unwrap());
+
+    std::fs::write(&path, b"new executable").unwrap();
+    assert!(!fingerprint.matches(&path).unwrap());
+
+    std::fs::remove_file(&path).unwrap();
+}
@@ -29,6 +29,7 @@ pub struct AppConfig {
 
     feature_flags: AppFeatures,
     export_dir: PathBuf,
+    chunk_cache_max_size: u64,
 }
 
 bitflags! {
@@ -75,6 +76,9 @@ impl AppState {
             .unwrap_or_else(|| env.game_dir().join("export"));
 
         let skip_cache = options.skip_cache;
+        let chunk_cache = options.chunk_cache;
+        let chunk_cache_max_size = options.chunk_cache_max_size
+            .unwrap_or(crate::DEFAULT_CHUNK_CACHE_MAX_SIZE);
         let is_server = options.is_server
             .unwrap_or_else(|| file_name.to_lowercase().contains("server"));
 
@@ -95,6 +99,7 @@ impl AppState {
             is_server,
 
             feature_flags,
+            chunk_cache_max_size,
         };
 
         // load and attach to resources
@@ -111,13 +116,11 @@ impl AppState {
         );
         let type_registry = Rc::new(type_registry);
 
-        let kfc_path = game_dir
-            .join(file_name)
-            .with_extension("kfc");
-        let bak_path = crate::load::create_backup(&kfc_path)?;
+        let snapshot = crate::load::create_backup(game_dir, file_name)?;
 
-        let ref_file = crate::load::load_kfc_file(&bak_path)?;
-        let reader = crate::load::create_reader(game_dir, file_name)?;
+        let chunk_cache_dir = chunk_cache.then(|| cache_dir.join("chunks").join(file_name));
+        let reader = crate::load::create_reader(game_dir, file_name, &snapshot, chunk_cache_dir.as_deref())?;
+        let ref_file = Rc::new(reader.file().clone());
         let writer = crate::load::create_writer(game_dir, file_name, &type_registry, &ref_file)?;
 
         Ok(Self {
@@ -182,6 +185,13 @@ impl AppState {
         self.reader.borrow_mut()
     }
 
+    /// Prunes the chunk cache of the reader to its maximum size, if it is enabled.
+    pub fn prune_chunk_cache(&self) {
+        if let Some(chunk_cache) = self.reader.borrow().disk_cache() {
+            crate::load::prune_chunk_cache(chunk_cache, self.config.chunk_cache_max_size);
+        }
+    }
+
     #[inline]
     pub fn take_writer(
         &self,
@@ -13,6 +13,9 @@ mod alias;
 mod load;
 mod lua;
 
+/// The default maximum size of the chunk cache.
+pub const DEFAULT_CHUNK_CACHE_MAX_SIZE: u64 = 4 * 1024 * 1024 * 1024; // 4 GiB
+
 #[derive(Debug, Clone, Default)]
 pub struct RunOptions {
     /// If true, it will ignore the cache and re-apply all mods.
@@ -25,6 +28,12 @@ pub struct RunOptions {
     pub export: bool,
     /// If None, it will use the default export directory (`<game_dir>/export`).
     pub export_dir: Option<std::path::PathBuf>,
+    /// If true, decompressed resource chunks are kept in the cache directory,
+    /// which speeds up repeated runs at the cost of disk space.
+    pub chunk_cache: bool,
+    /// The size in bytes the chunk cache is pruned to after each run.
+    /// If None, it will use [`DEFAULT_CHUNK_CACHE_MAX_SIZE`].
+    pub chunk_cache_max_size: Option<u64>,
 }
 
 #[derive(Debug, Clone)]
@@ -121,6 +130,8 @@ pub fn run(
         writer.finalize()?;
     }
 
+    app_state.prune_chunk_cache();
+
     // create a new cache file
 
     if app_state.has_feature(AppFeatures::PATCH) {
@@ -169,8 +180,5 @@ pub fn restore(
     game_dir: impl AsRef<Path>,
     file_name: &str,
 ) -> bool {
-    let kfc_path = game_dir.as_ref()
-        .join(format!("{file_name}.kfc"));
-
-    load::restore_backup(&kfc_path).is_ok()
+    load::restore_backup(game_dir.as_ref(), file_name).is_ok()
 }
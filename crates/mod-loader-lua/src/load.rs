@@ -1,48 +1,79 @@
-use std::{fs::File, io::BufReader, rc::Rc};
+use std::{fs::File, io::BufWriter, rc::Rc};
 
-use kfc::{container::{KFCCursor, KFCFile, KFCReader, KFCReaderOptions, KFCWriteOptions, KFCWriter}, reflection::TypeRegistry};
+use kfc::{container::{DiskChunkCache, KFCCursor, KFCFile, KFCReader, KFCSnapshot, KFCSnapshotError, KFCSnapshotManager, ORIGINAL_SNAPSHOT_NAME, KFCWriteOptions, KFCWriter, OverlayStorage}, reflection::{diff_type_registries, ExecutableFingerprint, TypeRegistry}};
 
-use crate::{alias::{Path, PathBuf}, log::{debug, error, info, warn}};
+use crate::{alias::Path, log::{debug, error, info, warn}};
 
-pub fn load_kfc_file(
-    kfc_path: impl AsRef<Path>
-) -> Result<Rc<KFCFile>, ()> {
-    let kfc_path = kfc_path.as_ref();
+pub fn create_reader(
+    dir: &Path,
+    file_name: &str,
+    snapshot: &KFCSnapshot,
+    chunk_cache_dir: Option<&Path>,
+) -> Result<KFCCursor<KFCReader>, ()> {
+    let manager = KFCSnapshotManager::for_game_dir(dir, file_name);
+
+    // read the archive as it was when the snapshot was taken
+    let storage = OverlayStorage::new(
+        manager.archive().clone(),
+        manager.storage(snapshot),
+    );
 
-    match KFCFile::from_path(kfc_path, false) {
-        Ok(file) => Ok(Rc::new(file)),
+    let cursor = match KFCReader::from_storage(storage).and_then(|reader| reader.into_cursor()) {
+        Ok(cursor) => cursor,
         Err(e) => {
             error!(
+                path = ?dir,
+                file_name = %file_name,
+                snapshot = %snapshot.name,
                 error = %e,
-                path = ?kfc_path,
-                "Failed to open KFC file",
+                "Failed to create KFC reader",
             );
-            Err(())
+            return Err(());
         }
+    };
+
+    let Some(chunk_cache_dir) = chunk_cache_dir else {
+        return Ok(cursor);
+    };
+
+    // the chunks of other snapshots and game versions are at different offsets
+    let chunk_cache = DiskChunkCache::new(
+        chunk_cache_dir,
+        cursor.file().game_version(),
+        snapshot.index.checksum,
+    );
+
+    if let Err(e) = chunk_cache.remove_others() {
+        warn!(
+            path = ?chunk_cache_dir,
+            error = %e,
+            "Failed to remove outdated chunks from the cache",
+        );
     }
+
+    Ok(cursor.with_disk_cache(chunk_cache))
 }
 
-pub fn create_reader(
-    dir: &Path,
-    file_name: &str,
-) -> Result<KFCCursor<KFCReader>, ()> {
-    match KFCReader::new_with_options(
-        dir,
-        file_name,
-        KFCReaderOptions {
-            kfc_extension: "kfc.bak",
-            ..Default::default()
+/// Removes the least recently used chunks until the cache fits into `max_size` bytes.
+pub fn prune_chunk_cache(
+    chunk_cache: &DiskChunkCache,
+    max_size: u64,
+) {
+    match chunk_cache.prune(max_size) {
+        Ok(0) => {}
+        Ok(removed) => {
+            debug!(
+                path = ?chunk_cache.directory(),
+                removed = removed,
+                "Pruned chunk cache",
+            );
         }
-    ).and_then(|reader| reader.into_cursor()) {
-        Ok(reader) => Ok(reader),
         Err(e) => {
-            error!(
-                path = ?dir,
-                file_name = %file_name,
+            warn!(
+                path = ?chunk_cache.directory(),
                 error = %e,
-                "Failed to create KFC reader",
+                "Failed to prune chunk cache",
             );
-            Err(())
         }
     }
 }
@@ -78,126 +109,87 @@ pub fn create_writer(
     Ok(writer)
 }
 
+/// Returns the snapshot of the unmodified archive, creating it if necessary.
 pub fn create_backup(
-    kfc_path: impl AsRef<Path>
-) -> Result<PathBuf, ()> {
-    let path = kfc_path.as_ref();
-    let mut bak_path = path.to_string();
-    bak_path.push_str(".bak");
-    let bak_path = Path::new(&bak_path);
-
-    if bak_path.exists() && !validate_backup(path, bak_path) {
-        warn!(
-            path = ?path,
-            backup_path = ?bak_path,
-            "Backup file is not compatible with the current KFC file, creating a new backup.",
-        );
+    game_dir: &Path,
+    file_name: &str,
+) -> Result<KFCSnapshot, ()> {
+    let manager = KFCSnapshotManager::for_game_dir(game_dir, file_name);
 
-        if !bak_path.is_file() {
-            error!(
-                path = ?bak_path,
-                "Backup path is not a file, please remove it manually.",
+    match manager.ensure_original() {
+        Ok(snapshot) => {
+            debug!(
+                path = ?manager.directory(),
+                game_version = %snapshot.game_version,
+                "Snapshot is ready",
             );
 
-            return Err(());
+            Ok(snapshot)
         }
-
-        if let Err(e) = std::fs::remove_file(bak_path) {
-            warn!(
-                path = ?bak_path,
-                error = %e,
-                "Failed to remove old backup file",
+        Err(KFCSnapshotError::OriginalMismatch { issues, .. }) => {
+            error!(
+                path = ?manager.directory(),
+                issues = ?issues,
+                "Snapshot does not match the current archive, please verify the game files.",
             );
-        }
-    }
 
-    if !bak_path.exists() {
-        if let Err(e) = std::fs::copy(path, bak_path) {
+            Err(())
+        }
+        Err(e) => {
             error!(
-                path = ?bak_path,
+                path = ?manager.directory(),
                 error = %e,
-                "Failed to create backup file",
+                "Failed to create snapshot",
             );
 
-            return Err(());
-        } else {
-            info!(
-                path = ?bak_path,
-                "Backup file created successfully",
-            );
+            Err(())
         }
     }
-
-    Ok(bak_path.to_path_buf())
 }
 
+/// Restores the archive from the snapshot of the unmodified archive.
 pub fn restore_backup(
-    kfc_path: impl AsRef<Path>,
+    game_dir: &Path,
+    file_name: &str,
 ) -> Result<(), ()> {
-    let path = kfc_path.as_ref();
-    let mut bak_path = path.to_string();
-    bak_path.push_str(".bak");
-    let bak_path = Path::new(&bak_path);
-
-    if !bak_path.exists() {
-        warn!(
-            path = ?bak_path,
-            "Backup file does not exist, cannot restore.",
-        );
-        return Err(());
-    }
-
-    if !bak_path.is_file() {
-        error!(
-            path = ?bak_path,
-            "Backup path is not a file, please remove it manually.",
-        );
-        return Err(());
-    }
-
-    if !validate_backup(path, bak_path) {
-        warn!(
-            path = ?path,
-            backup_path = ?bak_path,
-            "Backup file is not compatible with the current KFC file, cannot restore.",
-        );
-        return Err(());
-    }
+    let manager = KFCSnapshotManager::for_game_dir(game_dir, file_name);
+    let snapshot = match manager.game_version()
+        .and_then(|game_version| manager.get(&game_version, ORIGINAL_SNAPSHOT_NAME))
+    {
+        Ok(Some(snapshot)) => snapshot,
+        Ok(None) => {
+            warn!(
+                path = ?manager.directory(),
+                "Snapshot does not exist for the current game version, cannot restore.",
+            );
+            return Err(());
+        }
+        Err(e) => {
+            error!(
+                path = ?manager.directory(),
+                error = %e,
+                "Failed to read snapshot",
+            );
+            return Err(());
+        }
+    };
 
-    if let Err(e) = std::fs::copy(bak_path, path) {
+    if let Err(e) = manager.restore(&snapshot) {
         error!(
-            path = ?path,
-            backup_path = ?bak_path,
+            path = ?manager.directory(),
             error = %e,
-            "Failed to restore backup file",
+            "Failed to restore snapshot",
         );
         return Err(());
-    } else {
-        info!(
-            path = ?path,
-            backup_path = ?bak_path,
-            "Backup file restored successfully",
-        );
     }
 
-    Ok(())
-}
-
-pub fn validate_backup(
-    kfc_path: &Path,
-    kfc_path_bak: &Path
-) -> bool {
-    let version_bak = match KFCFile::get_version_tag(kfc_path_bak) {
-        Ok(file) => file,
-        Err(_) => return false,
-    };
-
-    let version = match KFCFile::get_version_tag(kfc_path) {
-        Ok(file) => file,
-        Err(_) => return false,
-    };
+    info!(
+        path = ?manager.directory(),
+        game_version = %snapshot.game_version,
+        "Snapshot restored successfully",
+    );
 
-    version == version_bak
+    Ok(())
 }
 
 pub fn load_type_registry(
@@ -214,27 +206,23 @@ pub fn load_type_registry(
         return Err(());
     }
 
-    let types_path = cache_dir.join("types.json");
+    let types_path = cache_dir.join("types.bin");
     let exe_path = game_dir.join(file_name).with_extension("exe");
     let kfc_path = game_dir.join(file_name).with_extension("kfc");
 
-    let type_registry = match File::open(&types_path) {
-        Ok(file) => {
-            let reader = BufReader::new(file);
-
-            match serde_json::from_reader::<_, TypeRegistry>(reader) {
-                Ok(registry) => Some(registry),
-                Err(e) => {
-                    debug!(
-                        error = %e,
-                        path = ?types_path,
-                        "Failed to read type registry from file, attempting to extract types...",
-                    );
+    let type_registry = match std::fs::read(&types_path) {
+        Ok(data) => match TypeRegistry::read_cache(&data) {
+            Ok(cache) => Some(cache),
+            Err(e) => {
+                debug!(
+                    error = %e,
+                    path = ?types_path,
+                    "Failed to read type registry from file, attempting to extract types...",
+                );
 
-                    None
-                },
-            }
-        }
+                None
+            },
+        },
         Err(e) => {
             debug!(
                 error = %e,
@@ -260,25 +248,41 @@ pub fn load_type_registry(
         }
     };
 
-    let type_registry = match type_registry {
-        Some(type_registry) => {
+    // the outdated registry is kept to report the changes of the new game version
+    let (type_registry, outdated_registry) = match type_registry {
+        Some((type_registry, executable)) => {
+            let is_same_executable = match executable.matches(&exe_path) {
+                Ok(matches) => matches,
+                Err(e) => {
+                    warn!(
+                        error = %e,
+                        path = ?types_path,
+                        exe_path = ?exe_path,
+                        "Failed to compare executable with type registry, assuming types are outdated",
+                    );
+
+                    false
+                }
+            };
+
             if let Some(version_tag) = &version_tag {
-                if version_tag != &type_registry.version {
+                if version_tag != &type_registry.version || !is_same_executable {
                     warn!(
                         path = ?types_path,
                         kfc_path = ?kfc_path,
+                        exe_path = ?exe_path,
                         "Type registry is outdated, attempting to extract types again..."
                     );
 
-                    None
+                    (None, Some(type_registry))
                 } else {
-                    Some(type_registry)
+                    (Some(type_registry), None)
                 }
             } else {
-                None
+                (None, None)
             }
         },
-        None => None
+        None => (None, None)
     };
 
     let (type_registry, is_dirty) = match type_registry {
@@ -288,24 +292,7 @@ pub fn load_type_registry(
                 if let Some(version_tag) = version_tag {
                     registry.version = version_tag;
 
-                    match serde_json::to_string(&registry) {
-                        Ok(json) => {
-                            if let Err(e) = std::fs::write(&types_path, json) {
-                                warn!(
-                                    error = %e,
-                                    path = ?types_path,
-                                    "Failed to write type registry to file",
-                                );
-                            }
-                        }
-                        Err(e) => {
-                            warn!(
-                                error = %e,
-                                path = ?types_path,
-                                "Failed to serialize type registry to JSON",
-                            );
-                        }
-                    }
+                    write_type_registry(&types_path, &exe_path, &registry);
                 } else {
                     warn!(
                         path = ?types_path,
@@ -314,6 +301,10 @@ pub fn load_type_registry(
                     );
                 }
 
+                if let Some(outdated_registry) = outdated_registry {
+                    write_type_changes(cache_dir, &outdated_registry, &registry);
+                }
+
                 (registry, true)
             }
             Err(e) => {
@@ -341,6 +332,84 @@ pub fn load_type_registry(
     Ok((type_registry, is_dirty))
 }
 
+/// Writes the type registry to the binary cache together with the fingerprint of the executable,
+/// so a replaced executable is detected even if the game version did not change.
+fn write_type_registry(
+    types_path: &Path,
+    exe_path: &Path,
+    type_registry: &TypeRegistry,
+) {
+    let executable = match ExecutableFingerprint::from_path(exe_path) {
+        Ok(executable) => executable,
+        Err(e) => {
+            warn!(
+                error = %e,
+                exe_path = ?exe_path,
+                "Failed to compute checksum of executable, type registry cannot be saved",
+            );
+            return;
+        }
+    };
+
+    let result = File::create(types_path)
+        .and_then(|file| type_registry.write_cache(&mut BufWriter::new(file), &executable));
+
+    if let Err(e) = result {
+        warn!(
+            error = %e,
+            path = ?types_path,
+            "Failed to write type registry to file",
+        );
+        return;
+    }
+
+    // the JSON cache of previous versions is not used anymore
+    if let Some(cache_dir) = types_path.parent() {
+        let _ = std::fs::remove_file(cache_dir.join("types.json"));
+    }
+}
+
+/// Writes the changes between the outdated and the new type registry to `type_changes.json`,
+/// so mod authors can check which fields their scripts use have moved.
+fn write_type_changes(
+    cache_dir: &Path,
+    outdated_registry: &TypeRegistry,
+    type_registry: &TypeRegistry,
+) {
+    let changes_path = cache_dir.join("type_changes.json");
+    let diff = diff_type_registries(outdated_registry, type_registry);
+
+    warn!(
+        path = ?changes_path,
+        old_version = %diff.old_version,
+        new_version = %diff.new_version,
+        added = diff.added_types.len(),
+        removed = diff.removed_types.len(),
+        changed = diff.changed_types.len(),
+        affected = diff.affected_types.len(),
+        "Types changed since the last game version",
+    );
+
+    match serde_json::to_string_pretty(&diff) {
+        Ok(json) => {
+            if let Err(e) = std::fs::write(&changes_path, json) {
+                warn!(
+                    error = %e,
+                    path = ?changes_path,
+                    "Failed to write type changes to file",
+                );
+            }
+        }
+        Err(e) => {
+            warn!(
+                error = %e,
+                path = ?changes_path,
+                "Failed to serialize type changes to JSON",
+            );
+        }
+    }
+}
+
 pub fn export_lua_definitions(
     cache_dir: &Path,
     type_registry: &TypeRegistry,
@@ -393,3 +462,72 @@ pub fn export_lua_definitions(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use kfc::{container::{DiskStorage, KFCStorage, KFCStream}, testing::*};
+
+    use super::*;
+
+    fn utf8_path(path: &std::path::Path) -> &Path {
+        Path::from_path(path).expect("temporary paths are valid UTF-8")
+    }
+
+    #[test]
+    fn test_create_backup() {
+        let archive = sample_archive(1);
+        let game_dir = utf8_path(archive.path());
+
+        let snapshot = create_backup(game_dir, archive.file_name()).unwrap();
+        assert_eq!(snapshot.name, ORIGINAL_SNAPSHOT_NAME);
+        assert_eq!(snapshot.game_version, archive.reader().unwrap().file().game_version());
+
+        // the existing snapshot is reused
+        let manager = KFCSnapshotManager::for_game_dir(game_dir, archive.file_name());
+        let reused = create_backup(game_dir, archive.file_name()).unwrap();
+
+        assert_eq!(reused.name, snapshot.name);
+        assert_eq!(manager.snapshots().unwrap().len(), 1);
+
+        // a snapshot which does not match the archive is neither reused nor replaced
+        let storage = DiskStorage::new(archive.path(), archive.file_name());
+        let size = storage.len(KFCStream::Container(0)).unwrap();
+
+        storage.set_len(KFCStream::Container(0), size - 1).unwrap();
+
+        assert!(create_backup(game_dir, archive.file_name()).is_err());
+        assert_eq!(manager.snapshots().unwrap(), vec![snapshot]);
+    }
+
+    #[test]
+    fn test_create_reader() {
+        let archive = sample_archive(1);
+        let game_dir = utf8_path(archive.path());
+        let cache_dir = tempfile::tempdir().unwrap();
+
+        let snapshot = create_backup(game_dir, archive.file_name()).unwrap();
+
+        for chunk_cache_dir in [None, Some(utf8_path(cache_dir.path()))] {
+            let mut cursor = create_reader(game_dir, archive.file_name(), &snapshot, chunk_cache_dir).unwrap();
+
+            for i in 0..32 {
+                let id = sample_resource_id(i, sample_resource_type(i));
+                let expected = sample_resource_data(i, i as usize * 997);
+
+                assert_eq!(cursor.read_resource(&id).unwrap().as_deref(), Some(&expected[..]));
+            }
+        }
+
+        // the second reader filled the chunk cache
+        assert!(std::fs::read_dir(cache_dir.path()).unwrap().count() > 0);
+
+        let cursor = create_reader(game_dir, archive.file_name(), &snapshot, Some(utf8_path(cache_dir.path()))).unwrap();
+        let chunk_cache = cursor.disk_cache().unwrap();
+
+        assert!(chunk_cache.size().unwrap() > 0);
+
+        prune_chunk_cache(chunk_cache, 0);
+
+        assert_eq!(chunk_cache.size().unwrap(), 0);
+    }
+}
@@ -50,6 +50,10 @@ pub enum Commands {
         /// Force patching even if already patched
         #[arg(short, long, default_value_t = false)]
         force: bool,
+
+        /// Keep decompressed resource chunks in the cache directory to speed up repeated runs
+        #[arg(long, default_value_t = false)]
+        chunk_cache: bool,
     },
 
     /// Restore the original enshrouded files
@@ -31,11 +31,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             game_directory,
             export_directory,
             file_name,
-            force
+            force,
+            chunk_cache
         } => run(
             game_directory,
             file_name,
             force,
+            chunk_cache,
 
             patch,
             export,
@@ -225,10 +227,12 @@ fn create(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 fn run(
     game_directory: PathBuf,
     file_name: Option<String>,
     force: bool,
+    chunk_cache: bool,
     patch: bool,
     export: bool,
     _runtime: bool,
@@ -290,6 +294,7 @@ fn run(
                 patch,
                 export,
                 export_dir: Some(export_directory),
+                chunk_cache,
                 ..Default::default()
             },
         },